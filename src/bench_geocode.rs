@@ -0,0 +1,153 @@
+use crate::cli::Args;
+use crate::geocode::{build_queries, Backends, Provider, ProviderMatch};
+use crate::ids::CountryCode;
+use crate::{build_backends, Address};
+use geo::{HaversineDistance, Point};
+use serde::*;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Deserialize, Debug)]
+struct TruthRow {
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    street: String,
+    postal_code: Option<String>,
+    city: Option<String>,
+    country: CountryCode,
+    lat: f64,
+    lng: f64,
+}
+
+/// How one provider did over the sample.
+#[derive(Default, Debug)]
+struct ProviderScore {
+    rows: usize,
+    found: usize,
+    errors: usize,
+    /// Distance between each match and the true location, in meters.
+    distances: Vec<f64>,
+    latencies: Vec<Duration>,
+}
+
+impl ProviderScore {
+    /// Tries the address queries from most to least specific, as a crawl would, and scores the
+    /// first match.
+    async fn add(&mut self, backends: &Backends, provider: Provider, row: &TruthRow) {
+        self.rows += 1;
+        let address = Address::new(
+            row.street.clone(),
+            row.postal_code.clone(),
+            row.city.clone(),
+            row.country.clone(),
+        );
+        for q in build_queries(&address) {
+            match backends
+                .forward_timed(provider, &q.query, q.language.as_deref())
+                .await
+            {
+                Ok((found, elapsed)) => {
+                    self.latencies.push(elapsed);
+                    if let Some(ProviderMatch { point, .. }) = found {
+                        let truth = Point::new(row.lng, row.lat);
+                        self.found += 1;
+                        self.distances
+                            .push(truth.haversine_distance(&Point::new(point.x(), point.y())));
+                        return;
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{provider:?} failed for {}: {e}", row.id);
+                    self.errors += 1;
+                    return;
+                }
+            }
+        }
+    }
+
+    fn within(&self, meters: f64) -> String {
+        percent(
+            self.distances.iter().filter(|d| **d <= meters).count(),
+            self.rows,
+        )
+    }
+}
+
+fn percent(part: usize, whole: usize) -> String {
+    if whole == 0 {
+        return "-".to_owned();
+    }
+    format!("{:.1}%", part as f64 * 100.0 / whole as f64)
+}
+
+/// Value at `quantile` (0.0 to 1.0) of `values`, which must be sorted.
+fn quantile<T: Copy>(values: &[T], quantile: f64) -> Option<T> {
+    if values.is_empty() {
+        return None;
+    }
+    let index = ((values.len() - 1) as f64 * quantile).round() as usize;
+    Some(values[index])
+}
+
+/// Geocodes a ground-truth sample with each configured provider in turn and prints, per provider,
+/// how often it found the address, how far off it was, and how fast it answered.
+pub async fn bench_geocode(
+    args: &Args,
+    truth: &Path,
+    sample: Option<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut rdr = csv::Reader::from_path(truth)?;
+    let rows = rdr
+        .deserialize()
+        .take(sample.unwrap_or(usize::MAX))
+        .collect::<Result<Vec<TruthRow>, _>>()?;
+    let backends = build_backends(args)?;
+
+    println!(
+        "{:<10} {:>6} {:>7} {:>6} {:>10} {:>8} {:>8} {:>8} {:>9} {:>9}",
+        "provider",
+        "rows",
+        "found",
+        "errors",
+        "median km",
+        "<= 1 km",
+        "<= 10 km",
+        "requests",
+        "mean ms",
+        "p95 ms"
+    );
+    for provider in &args.geocoders {
+        let mut score = ProviderScore::default();
+        for row in &rows {
+            score.add(&backends, *provider, row).await;
+        }
+
+        score.distances.sort_by(f64::total_cmp);
+        score.latencies.sort();
+        let mean = match score.latencies.len() {
+            0 => None,
+            n => Some(score.latencies.iter().sum::<Duration>() / n as u32),
+        };
+        let ms = |d: Option<Duration>| {
+            d.map(|d| format!("{:.0}", d.as_secs_f64() * 1000.0))
+                .unwrap_or_else(|| "-".to_owned())
+        };
+        println!(
+            "{:<10} {:>6} {:>7} {:>6} {:>10} {:>8} {:>8} {:>8} {:>9} {:>9}",
+            format!("{provider:?}").to_lowercase(),
+            score.rows,
+            percent(score.found, score.rows),
+            score.errors,
+            quantile(&score.distances, 0.5)
+                .map(|d| format!("{:.2}", d / 1000.0))
+                .unwrap_or_else(|| "-".to_owned()),
+            score.within(1_000.0),
+            score.within(10_000.0),
+            score.latencies.len(),
+            ms(mean),
+            ms(quantile(&score.latencies, 0.95)),
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,596 @@
+use crate::config::QuoteStyle;
+use crate::countries::CountrySource;
+use crate::coverage::CoverageFormat;
+use crate::duplicates::DuplicatePolicy;
+use crate::esa::EsaList;
+use crate::geocode::{LocationPrecision, Provider};
+use crate::geojson_chunks::Chunking;
+use crate::ids::{CountryCode, OperatorId, SectionCode};
+use crate::import::XlsImport;
+use crate::legacy_encoding;
+use crate::manifest::SortOrder;
+use crate::names::MissingNamePolicy;
+use crate::output::Compression;
+use crate::release;
+use crate::traces::urls::OffsetRange;
+use clap::{Parser, Subcommand};
+use encoding_rs::Encoding;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use url::Url;
+
+/// Crawls the TRACES establishment directory and writes geocoded OFF packager codes.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// TOML configuration file.
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Only re-fetch countries whose category counts changed since the last run.
+    #[arg(long)]
+    pub since_last_run: bool,
+
+    /// Re-fetch every country even if the run manifest says it is unchanged.
+    #[arg(long, requires = "since_last_run")]
+    pub force: bool,
+
+    /// Go ahead without asking after printing the estimated request count and duration. Runs
+    /// without a terminal never ask.
+    #[arg(long, short = 'y')]
+    pub yes: bool,
+
+    /// Where the run manifest is read from and written to.
+    #[arg(long, default_value = "run-manifest.json")]
+    pub manifest: PathBuf,
+
+    /// Stop after this many establishments overall, for a quick smoke test; the run manifest is
+    /// left untouched.
+    #[arg(long)]
+    pub limit: Option<usize>,
+
+    /// Stop after this many establishments per country, for a quick smoke test; the run manifest
+    /// is left untouched.
+    #[arg(long)]
+    pub limit_per_country: Option<usize>,
+
+    /// Record every crawl in this SQLite database, read by the `history` subcommand.
+    #[arg(long)]
+    pub history_db: Option<PathBuf>,
+
+    /// Language used for human-readable names such as classification sections.
+    #[arg(long, default_value = "en")]
+    pub lang: String,
+
+    /// Exit with status 3 instead of writing output when no packager codes were produced.
+    #[arg(long)]
+    pub fail_on_empty: bool,
+
+    /// Exit with status 4 instead of writing output when fewer than this share (0.0 to 1.0) of
+    /// geocoding attempts succeeded.
+    #[arg(long, value_parser = parse_rate)]
+    pub min_success_rate: Option<f64>,
+
+    /// Seconds a TRACES listing page may take to arrive before it is abandoned and retried.
+    #[arg(long, default_value_t = 120, value_parser = clap::value_parser!(u32).range(1..))]
+    pub listing_timeout: u32,
+
+    /// Seconds a TRACES establishment detail record may take to arrive before it is abandoned and
+    /// retried.
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..))]
+    pub detail_timeout: u32,
+
+    /// TRACES publication API base URL, e.g. a staging deployment or a local mock server.
+    #[arg(long)]
+    pub traces_base_url: Option<String>,
+
+    /// Record every TRACES response under this directory, for later replay with `--from-raw`.
+    #[arg(long, conflicts_with = "from_raw")]
+    pub save_raw: Option<PathBuf>,
+
+    /// Keep the responses a previous `--save-raw` run into the same directory recorded
+    /// completely, fetching only missing and partial ones.
+    #[arg(long, requires = "save_raw")]
+    pub resume_raw: bool,
+
+    /// Instead of crawling, record the establishment pages holding records `START..END` of each
+    /// selected section again into the `--save-raw` directory, e.g. to repair part of a snapshot
+    /// before replaying it with `--from-raw`.
+    #[arg(
+        long,
+        value_name = "START..END",
+        requires = "save_raw",
+        conflicts_with = "resume_raw"
+    )]
+    pub offset_range: Option<OffsetRange>,
+
+    /// Replay TRACES responses recorded with `--save-raw` instead of contacting TRACES; geocoding
+    /// still uses the configured providers.
+    #[arg(long)]
+    pub from_raw: Option<PathBuf>,
+
+    /// Append a JSON line per outbound HTTP request (URL, time, status, duration) to this file.
+    #[arg(long)]
+    pub audit_log: Option<PathBuf>,
+
+    /// Let the TRACES request rate adapt to response times and throttling instead of a fixed one
+    /// request per second.
+    #[arg(long)]
+    pub adaptive_rate: bool,
+
+    /// Also crawl the TRACES lists of approved establishments in third countries exporting to the
+    /// EU.
+    #[arg(long)]
+    pub include_third_countries: bool,
+
+    /// TRACES country statuses to crawl, e.g. `V,S` to also include suspended countries for
+    /// historical comparison; categories of other countries are reported and skipped.
+    #[arg(long, value_delimiter = ',', default_value = "V")]
+    pub include_statuses: Vec<String>,
+
+    /// Fetch the detail record of establishments whose listed address lacks a street or postal
+    /// code, and use it to complete the address before geocoding.
+    #[arg(long)]
+    pub fetch_details: bool,
+
+    /// Only crawl these classification sections, e.g. `I,II`; the `sections` subcommand lists a
+    /// country's sections.
+    #[arg(long, value_delimiter = ',')]
+    pub sections: Vec<SectionCode>,
+
+    /// Only crawl these countries, e.g. `FR,BE`.
+    #[arg(long, value_delimiter = ',')]
+    pub countries: Vec<CountryCode>,
+
+    /// Leave these countries out of the crawl, e.g. while a registry is broken; their packager
+    /// codes from the previous run are kept.
+    #[arg(long, value_delimiter = ',')]
+    pub exclude_countries: Vec<CountryCode>,
+
+    /// Leave out classification sections that do not list food for human consumption, such as
+    /// animal by-product plants, according to the bundled section mapping.
+    #[arg(long)]
+    pub food_only: bool,
+
+    /// Per-country approval number rendering rules (TOML) adding to or replacing the bundled
+    /// ones, e.g. `[FR]` with `separators = ["."]` and `pad = [2, 3, 3]`.
+    #[arg(long)]
+    pub rules_file: Option<PathBuf>,
+
+    /// Country prefixing the packager code of establishments whose address lies outside the
+    /// approving country.
+    #[arg(long, value_enum, default_value = "approving")]
+    pub code_country: CountrySource,
+
+    /// Country used in the geocoding queries of establishments whose address lies outside the
+    /// approving country.
+    #[arg(long, value_enum, default_value = "address")]
+    pub geocode_country: CountrySource,
+
+    /// Section mapping (`code,food` with `yes` or `no`) used by `--food-only` instead of the
+    /// bundled one.
+    #[arg(long, requires = "food_only")]
+    pub sections_file: Option<PathBuf>,
+
+    /// Also process an XLS/XLSX or CSV establishment list published outside TRACES, as
+    /// `COUNTRY[:SECTION]=PATH`; may be repeated.
+    #[arg(long)]
+    pub import_xls: Vec<XlsImport>,
+
+    /// Read imported CSV lists in this encoding, e.g. `windows-1252`, instead of detecting it.
+    #[arg(long, value_parser = legacy_encoding::parse_label)]
+    pub force_encoding: Option<&'static Encoding>,
+
+    /// Also process an establishment list of Iceland or Liechtenstein published by the EFTA
+    /// Surveillance Authority as XLS/XLSX, as `COUNTRY[:SECTION]=URL`; may be repeated. Their
+    /// codes carry the `EFTA` mark.
+    #[arg(long = "esa-list")]
+    pub esa_lists: Vec<EsaList>,
+
+    /// CSV of community-corrected coordinates (`code,lat,lng`) applied over the geocoding
+    /// results; ignored if the file does not exist.
+    #[arg(long, default_value = "manual-overrides.csv")]
+    pub overrides: PathBuf,
+
+    /// How to handle establishments listed without an operator name.
+    #[arg(long, value_enum, default_value_t)]
+    pub missing_names: MissingNamePolicy,
+
+    /// Name exported for establishments without one with `--missing-names placeholder`.
+    #[arg(long, default_value = "Unknown operator")]
+    pub missing_name_placeholder: String,
+
+    /// Trim operator names and collapse runs of whitespace in them.
+    #[arg(long)]
+    pub clean_names: bool,
+
+    /// Remove legal forms such as `S.A.`, `SARL` or `GmbH` from the start and end of operator
+    /// names.
+    #[arg(long)]
+    pub strip_legal_forms: bool,
+
+    /// Title-case operator names written entirely in capitals, following the conventions of the
+    /// country's main language.
+    #[arg(long)]
+    pub title_case_names: bool,
+
+    /// Round output coordinates to this many decimal places (banker's rounding).
+    #[arg(long, value_parser = clap::value_parser!(u32).range(0..=15))]
+    pub coordinate_precision: Option<u32>,
+
+    /// Leave codes located less precisely than this out of the exports, e.g. `street` to drop
+    /// town-level matches.
+    #[arg(long, value_enum)]
+    pub min_precision: Option<LocationPrecision>,
+
+    /// Add `x_3857` and `y_3857` columns with the Web Mercator (EPSG:3857) position in meters, for
+    /// maps drawn on Web Mercator tiles.
+    #[arg(long)]
+    pub web_mercator: bool,
+
+    /// Add a `country_name` column with the country's display name in `--lang`, from the bundled
+    /// EU country list.
+    #[arg(long)]
+    pub country_names: bool,
+
+    /// How to handle a packager code shared by differently named operators; `review` withholds
+    /// them and writes them to `duplicate-codes.csv`.
+    #[arg(long, value_enum, default_value_t)]
+    pub duplicate_codes: DuplicatePolicy,
+
+    /// Write pairs of rows with different codes but near-identical names and coordinates, which
+    /// are often the same establishment registered again, to this CSV for review.
+    #[arg(long)]
+    pub likely_duplicates: Option<PathBuf>,
+
+    /// How far apart in meters rows listed by `--likely-duplicates` may be.
+    #[arg(long, default_value_t = 50.0, requires = "likely_duplicates")]
+    pub likely_duplicate_distance: f64,
+
+    /// How alike names of rows listed by `--likely-duplicates` must be, from 0 to 1.
+    #[arg(long, default_value_t = 0.9, requires = "likely_duplicates")]
+    pub likely_duplicate_similarity: f64,
+
+    /// Order of the packager codes within each country in the exports. Duplicate codes are
+    /// numbered in this order too.
+    #[arg(long, value_enum, default_value_t)]
+    pub sort: SortOrder,
+
+    /// Field delimiter of `data.csv`, e.g. `;`.
+    #[arg(long, default_value_t = ',')]
+    pub delimiter: char,
+
+    /// When fields of `data.csv` are quoted.
+    #[arg(long, value_enum, default_value_t)]
+    pub quote_style: QuoteStyle,
+
+    /// Leave the header row out of `data.csv`.
+    #[arg(long)]
+    pub no_header: bool,
+
+    /// Compress file outputs; the matching extension is appended to each file name.
+    #[arg(long, value_enum)]
+    pub compress: Option<Compression>,
+
+    /// Write a `.meta.json` sidecar next to each exported file with the generation time, data
+    /// sources, record count and SHA-256 of the file.
+    #[arg(long)]
+    pub metadata: bool,
+
+    /// Also write the packagers overlay JSON used by the OFF map tooling to this file.
+    #[arg(long)]
+    pub off_packagers: Option<PathBuf>,
+
+    /// Also write the packager codes as GeoJSON chunks with an `index.json` to this directory, so
+    /// map frontends can load regions lazily.
+    #[arg(long)]
+    pub geojson_dir: Option<PathBuf>,
+
+    /// How GeoJSON output is split into files.
+    #[arg(long, value_enum, default_value_t)]
+    pub geojson_chunks: Chunking,
+
+    /// Also write a gzip-compressed JSON bundle per country (`[code, name, lat, lng]` rows) with
+    /// an `index.json` to this directory, for offline lookup in the OFF mobile apps. Always
+    /// gzipped, whatever `--compress` says.
+    #[arg(long)]
+    pub mobile_bundles: Option<PathBuf>,
+
+    /// Also write each country's packager codes to `data-<country>.csv` in this directory as soon
+    /// as the country is geocoded, so finished countries can be picked up while others are still
+    /// running. The files are written again at the end with duplicates resolved across
+    /// countries, as in `data.csv`; with `--overpass-check` they are only written then.
+    #[arg(long)]
+    pub country_dir: Option<PathBuf>,
+
+    /// Write the packager codes added, removed or renamed since the previous run to this CSV.
+    #[arg(long)]
+    pub diff: Option<PathBuf>,
+
+    /// Write the packager codes that disappeared from TRACES since the previous run to this CSV,
+    /// with their last known name and position, for moderators deciding whether to retire them.
+    #[arg(long)]
+    pub removed_codes: Option<PathBuf>,
+
+    /// Also export packager codes, skipped establishments and errors to this SQLite database.
+    #[arg(long)]
+    pub sqlite: Option<PathBuf>,
+
+    /// Also replace the `packager_codes` table of this Postgres database (a connection string
+    /// such as `host=localhost user=off dbname=off`) with the exported codes, including a PostGIS
+    /// geometry column where the extension is installed.
+    #[arg(long, value_name = "DSN")]
+    pub postgres: Option<String>,
+
+    /// Release the exported files under this dataset version: it is added to their names and
+    /// metadata, and `latest.json` plus `-latest` symlinks are pointed at them. Without a value,
+    /// the version is today's date as `YYYY.MM.DD`.
+    #[arg(long, num_args = 0..=1, default_missing_value = release::DATE_VERSION)]
+    pub dataset_version: Option<String>,
+
+    /// Also upload the exported files to this `s3://bucket/prefix`, keeping their relative paths.
+    /// Needs a build with the `s3` feature.
+    #[arg(long)]
+    pub output: Option<Url>,
+
+    /// POST a JSON summary of the run to this URL when a crawl fails; the `text` field makes it
+    /// usable as a Slack incoming webhook.
+    #[arg(long)]
+    pub on_failure_webhook: Option<Url>,
+
+    /// POST a JSON summary of the run to this URL when a crawl succeeds.
+    #[arg(long)]
+    pub on_success_webhook: Option<Url>,
+
+    /// Write a standalone HTML report of the run for review before publishing: summary counts,
+    /// per-country coverage, a map of new and renamed codes, and the failed establishments.
+    #[arg(long)]
+    pub report: Option<PathBuf>,
+
+    /// Write a JSON summary of the run, including geocoding counts and stage timings.
+    #[arg(long)]
+    pub summary: Option<PathBuf>,
+
+    /// Eurostat GISCO NUTS boundaries (GeoJSON, EPSG:4326) used to add NUTS2/NUTS3 columns.
+    #[arg(long)]
+    pub nuts_boundaries: Option<PathBuf>,
+
+    /// Flag points that fall in the sea according to a bundled coarse land mask, typically
+    /// centroids returned for coastal addresses.
+    #[arg(long)]
+    pub check_water: bool,
+
+    /// Ask OpenStreetMap, through Overpass, whether a feature named like the operator lies near
+    /// each geocoded point, recorded in a `poi_nearby` column. One request per code, so slow on a
+    /// first run; later runs reuse answers for unchanged codes.
+    #[arg(long)]
+    pub overpass_check: bool,
+
+    /// Overpass API interpreter endpoint.
+    #[arg(long, default_value = "https://overpass-api.de/api/interpreter")]
+    pub overpass_url: Url,
+
+    /// Search radius around each point for `--overpass-check`, in meters.
+    #[arg(long, default_value_t = 150)]
+    pub overpass_radius: u32,
+
+    /// Geocoding providers, tried in order until one finds the address.
+    #[arg(
+        long,
+        visible_alias = "geocoder",
+        value_enum,
+        value_delimiter = ',',
+        default_value = "nominatim"
+    )]
+    pub geocoders: Vec<Provider>,
+
+    /// Retry addresses whose street is not found as `operator name, city, country` before falling
+    /// back to the postcode or city, as many plants are well-known places; such matches get the
+    /// `operator_name` precision.
+    #[arg(long)]
+    pub name_fallback: bool,
+
+    /// Number of concurrent geocoding workers; providers stay rate limited regardless.
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..))]
+    pub geocode_workers: u16,
+
+    /// Number of countries whose establishments are fetched from TRACES at once; the rate limit
+    /// still applies across all of them.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    pub fetch_concurrency: u16,
+
+    /// Write the geocoding queries each establishment would be tried with to this CSV and stop
+    /// before geocoding, e.g. to review address normalization changes as a diff.
+    #[arg(long)]
+    pub emit_queries: Option<PathBuf>,
+
+    /// Seconds a remote geocoder may take to answer before the request is abandoned and counted
+    /// as an error.
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..))]
+    pub geocode_timeout: u32,
+
+    /// Nominatim endpoint, e.g. a local instance; needs a trailing slash.
+    #[arg(long, default_value = "https://nominatim.openstreetmap.org/")]
+    pub nominatim_url: String,
+
+    /// Photon search endpoint, e.g. a local instance.
+    #[arg(long, default_value = "https://photon.komoot.io/api/")]
+    pub photon_url: String,
+
+    /// Address index CSV (`query,lat,lng`) used by the offline geocoder.
+    #[arg(long, visible_alias = "index")]
+    pub geocoder_index: Option<PathBuf>,
+
+    /// Town centroid table (`country,name,lat,lng`) used by the lau geocoder.
+    #[arg(long)]
+    pub lau_centroids: Option<PathBuf>,
+
+    /// File persisting per-provider geocoding outcomes between runs.
+    #[arg(long)]
+    pub geocode_cache: Option<PathBuf>,
+
+    /// Days a cached geocoding match is used before the provider is asked again.
+    #[arg(long, default_value_t = 365)]
+    pub geocode_cache_days: u32,
+
+    /// Days a cached geocoding failure is used before the provider is asked again, so hopeless
+    /// addresses are only retried now and then.
+    #[arg(long, default_value_t = 30)]
+    pub negative_cache_days: u32,
+
+    /// Re-geocode points resolved more than this many days ago: older cached answers are not
+    /// used, and with `--since-last-run` countries holding such points, or points from before
+    /// `geocoded_at` was recorded, are fetched again rather than carried over.
+    #[arg(long, value_name = "DAYS")]
+    pub max_geocode_age: Option<u32>,
+}
+
+impl Args {
+    /// Whether `--countries` or `--sections` leave part of the registry out of the run.
+    pub fn is_filtered(&self) -> bool {
+        !self.countries.is_empty() || !self.sections.is_empty()
+    }
+
+    /// Checks what clap cannot: that no country is both selected and excluded.
+    pub fn validate(&self) -> Result<(), String> {
+        let both: Vec<&str> = self
+            .countries
+            .iter()
+            .filter(|c| self.exclude_countries.contains(c))
+            .map(|c| c.as_str())
+            .collect();
+        if !both.is_empty() {
+            return Err(format!(
+                "--countries and --exclude-countries both list {}",
+                both.join(", ")
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn parse_rate(value: &str) -> Result<f64, String> {
+    let rate: f64 = value.parse().map_err(|e| format!("{e}"))?;
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(format!("{rate} is not between 0 and 1"));
+    }
+    Ok(rate)
+}
+
+/// Subcommands; without one, a full crawl is run.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print the raw JSON, parsed record and geocoding result of a single establishment.
+    FetchOne {
+        /// Country code the establishment is listed under.
+        #[arg(long)]
+        country: CountryCode,
+
+        /// Classification section code the establishment is listed under.
+        #[arg(long)]
+        section: SectionCode,
+
+        /// TRACES operator ID of the establishment.
+        #[arg(long)]
+        operator_id: OperatorId,
+    },
+
+    /// Geocode an address CSV (`street,postal_code,city,country`, optionally `id`) using the
+    /// configured providers, cache and rate limits.
+    Geocode {
+        /// Input CSV with one address per row.
+        #[arg(long)]
+        input: PathBuf,
+
+        /// Output CSV with the input columns plus the geocoding result.
+        #[arg(long)]
+        output: PathBuf,
+    },
+
+    /// Geocode the addresses of a ground-truth CSV (the `geocode` input columns plus the true
+    /// `lat` and `lng`) with each configured provider on its own, and report accuracy and
+    /// latency per provider.
+    BenchGeocode {
+        /// Ground-truth CSV.
+        #[arg(long)]
+        truth: PathBuf,
+
+        /// Only use the first this many rows.
+        #[arg(long)]
+        sample: Option<usize>,
+    },
+
+    /// Serve the packager codes of the last run over HTTP, with the export options applied:
+    /// `/packager-codes?country=FR`, `/packager-codes/{code}` and `/geojson`.
+    Serve {
+        /// Address and port to listen on.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+    },
+
+    /// Print recent runs from the run history database.
+    History {
+        /// Number of runs to show.
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Write the establishments of a crawl as JSON before geocoding, for use by other tools.
+    DumpEstablishments {
+        /// Output JSON file, keyed by country.
+        #[arg(long)]
+        output: PathBuf,
+    },
+
+    /// Print the classification sections TRACES lists for a country, with their establishment
+    /// counts, for choosing `--sections`. The listing is cached for a day in
+    /// `sections-cache.json`.
+    Sections {
+        /// Country code, e.g. `DE`.
+        #[arg(long)]
+        country: CountryCode,
+
+        /// Fetch the listing again even if the cached one is recent.
+        #[arg(long)]
+        refresh: bool,
+    },
+
+    /// Write the countries × sections matrix of the last run with the number of establishments
+    /// TRACES lists and how many of them were geocoded, for publishing alongside data releases.
+    Coverage {
+        /// Output file.
+        #[arg(long)]
+        output: PathBuf,
+
+        #[arg(long, value_enum, default_value_t)]
+        format: CoverageFormat,
+    },
+
+    /// Move the `--geocode-cache` between machines, so CI runners and contributors can start
+    /// from a warmed cache.
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheCommand {
+    /// Write the unexpired cached geocodes as portable JSON, sorted for stable diffs.
+    Export {
+        /// Output JSON file.
+        #[arg(long)]
+        output: PathBuf,
+    },
+
+    /// Merge an export into the cache, keeping the more recent outcome of each query and
+    /// provider.
+    Import {
+        /// JSON file written by `cache export`.
+        #[arg(long)]
+        input: PathBuf,
+    },
+}
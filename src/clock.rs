@@ -0,0 +1,24 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Source of time for request pacing and retries.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send;
+}
+
+/// Time as the Tokio runtime sees it, which tests can pause and advance instantly with
+/// `#[tokio::test(start_paused = true)]`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+        tokio::time::sleep(duration)
+    }
+}
@@ -0,0 +1,105 @@
+pub mod rules;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// Abbreviations of "European Community" used in the identification mark across EU languages
+/// (Regulation (EC) No 853/2004, Annex II), which member states sometimes include in the
+/// approval number itself.
+const EU_MARKS: &[&str] = &[
+    "EC", "CE", "EG", "EF", "EK", "ΕΚ", "ЕО", "EY", "ES", "EÜ", "EB", "EZ", "KE", "WE",
+];
+
+/// Renders the OFF packager code of an establishment from its country and approval number.
+///
+/// Implement this and pass it to [`register_formatter`] when a country's conventions differ from
+/// the default `CC NUMBER EC` rendering.
+pub trait CodeFormatter: Send + Sync {
+    fn format(&self, country: &str, approval_number: &str) -> String;
+}
+
+impl<F> CodeFormatter for F
+where
+    F: Fn(&str, &str) -> String + Send + Sync,
+{
+    fn format(&self, country: &str, approval_number: &str) -> String {
+        self(country, approval_number)
+    }
+}
+
+fn formatters() -> &'static RwLock<HashMap<String, Arc<dyn CodeFormatter>>> {
+    static FORMATTERS: OnceLock<RwLock<HashMap<String, Arc<dyn CodeFormatter>>>> = OnceLock::new();
+    FORMATTERS.get_or_init(Default::default)
+}
+
+/// Uses `formatter` for establishments in `country` instead of the built-in rendering, replacing
+/// any formatter registered for it before.
+pub fn register_formatter(country: &str, formatter: impl CodeFormatter + 'static) {
+    formatters()
+        .write()
+        .unwrap()
+        .insert(country.to_uppercase(), Arc::new(formatter));
+}
+
+/// Formats the code of an establishment, using the formatter registered for `country` if any and
+/// otherwise rendering the number by the country's [rules](rules::rule).
+pub fn format_establishment_code(
+    country: &str,
+    approval_number: &str,
+    third_country: bool,
+) -> String {
+    let registered = formatters()
+        .read()
+        .unwrap()
+        .get(&country.to_uppercase())
+        .cloned();
+    if let Some(formatter) = registered {
+        return formatter.format(country, approval_number);
+    }
+
+    let number = strip_affixes(country, approval_number);
+    let number = match rules::rule(country) {
+        Some(rule) => Cow::Owned(rule.apply(number)),
+        None => Cow::Borrowed(number),
+    };
+    if third_country {
+        format_third_country_code(country, &number)
+    } else {
+        format_code(country, &number)
+    }
+}
+
+/// Formats an OFF packager code such as `FR 12.345.001 EC`.
+pub fn format_code(country: &str, approval_number: &str) -> String {
+    format!("{} {} EC", country, strip_affixes(country, approval_number))
+}
+
+/// Formats the code of a third-country exporter, which carries no EU identification mark.
+pub fn format_third_country_code(country: &str, approval_number: &str) -> String {
+    format!("{} {}", country, strip_affixes(country, approval_number))
+}
+
+/// Removes a leading country code and trailing EU mark already present in `approval_number`,
+/// so that e.g. `FR 12.345.01 CE` does not end up as `FR FR 12.345.01 CE EC`.
+pub fn strip_affixes<'a>(country: &str, approval_number: &'a str) -> &'a str {
+    let mut number = approval_number.trim();
+
+    if let Some((prefix, rest)) = number.split_once(|c: char| c.is_whitespace() || c == '-') {
+        if prefix.eq_ignore_ascii_case(country) && !rest.trim().is_empty() {
+            number = rest.trim_start();
+        }
+    }
+
+    if let Some((rest, suffix)) = number.rsplit_once(char::is_whitespace) {
+        if EU_MARKS
+            .iter()
+            .any(|m| m.to_uppercase() == suffix.to_uppercase())
+            && !rest.trim().is_empty()
+        {
+            number = rest.trim_end();
+        }
+    }
+
+    number
+}
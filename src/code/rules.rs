@@ -0,0 +1,95 @@
+use serde::*;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Per-country rendering rules shipped with the binary, see the file for the format.
+const CODE_RULES_TOML: &str = include_str!("../../data/code_rules.toml");
+
+/// How one country's approval numbers are rendered.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CodeRule {
+    /// Separators placed between the groups of letters and digits, in order; the last one
+    /// repeats. Empty keeps the published separators.
+    separators: Vec<String>,
+    /// Width each group of digits is zero-padded to, in order.
+    pad: Vec<usize>,
+    uppercase: bool,
+}
+
+impl CodeRule {
+    /// Renders `number`, an approval number without country prefix or EU mark.
+    pub fn apply(&self, number: &str) -> String {
+        let mut groups = vec![];
+        let mut separators = vec![];
+        let mut rest = number.trim_matches(|c: char| !c.is_alphanumeric());
+        while !rest.is_empty() {
+            let end = rest
+                .find(|c: char| !c.is_alphanumeric())
+                .unwrap_or(rest.len());
+            groups.push(&rest[..end]);
+            rest = &rest[end..];
+            let end = rest.find(char::is_alphanumeric).unwrap_or(rest.len());
+            if end > 0 {
+                separators.push(&rest[..end]);
+            }
+            rest = &rest[end..];
+        }
+
+        let mut rendered = String::with_capacity(number.len());
+        for (i, group) in groups.iter().enumerate() {
+            if i > 0 {
+                let separator = match self.separators.get(i - 1).or(self.separators.last()) {
+                    Some(separator) => separator.as_str(),
+                    None => separators[i - 1],
+                };
+                rendered.push_str(separator);
+            }
+            let group = match self.pad.get(i) {
+                Some(width) if group.chars().all(|c| c.is_ascii_digit()) => {
+                    Cow::Owned(format!("{group:0>width$}"))
+                }
+                _ => Cow::Borrowed(*group),
+            };
+            if self.uppercase {
+                rendered.push_str(&group.to_uppercase());
+            } else {
+                rendered.push_str(&group);
+            }
+        }
+        rendered
+    }
+}
+
+static RULES: OnceLock<HashMap<String, CodeRule>> = OnceLock::new();
+
+fn parse(text: &str) -> Result<HashMap<String, CodeRule>, toml::de::Error> {
+    let rules: HashMap<String, CodeRule> = toml::from_str(text)?;
+    Ok(rules
+        .into_iter()
+        .map(|(country, rule)| (country.to_uppercase(), rule))
+        .collect())
+}
+
+/// Loads the bundled rules, with the entries of `path` added or replacing bundled ones.
+///
+/// Has no effect once the first code was rendered.
+pub fn configure(path: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut rules = parse(CODE_RULES_TOML)?;
+    if let Some(path) = path {
+        let overrides = parse(&std::fs::read_to_string(path)?)
+            .map_err(|e| format!("invalid rules file {}: {e}", path.display()))?;
+        rules.extend(overrides);
+    }
+    let _ = RULES.set(rules);
+    Ok(())
+}
+
+/// The rule for `country`, if it has one.
+pub fn rule(country: &str) -> Option<&'static CodeRule> {
+    RULES
+        .get_or_init(|| parse(CODE_RULES_TOML).expect("the bundled code rules are valid"))
+        .get(&country.to_uppercase())
+}
@@ -0,0 +1,164 @@
+use crate::countries::CountrySource;
+use crate::ids::{ApprovalNumber, OperatorId};
+use crate::Establishment;
+use clap::ValueEnum;
+use regex::Regex;
+use serde::*;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Settings read from the TOML file passed with `--config`.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub traces: TracesConfig,
+    pub export: ExportConfig,
+    pub blocklist: Blocklist,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TracesConfig {
+    /// Overrides the TRACES publication API base URL; `--traces-base-url` takes precedence.
+    pub base_url: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportConfig {
+    /// Countries that additionally get their own CSV file in a legacy-compatible format,
+    /// keyed by country code.
+    pub countries: BTreeMap<String, CsvFormat>,
+}
+
+/// Known-bogus registry entries excluded from the output on every run.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Blocklist {
+    pub operator_ids: Vec<OperatorId>,
+    pub approval_numbers: Vec<ApprovalNumber>,
+    /// Regular expressions matched against the formatted packager code, e.g. `^FR 99\.`.
+    #[serde(deserialize_with = "deserialize_patterns")]
+    pub patterns: Vec<Regex>,
+}
+
+impl Blocklist {
+    pub fn is_empty(&self) -> bool {
+        self.operator_ids.is_empty() && self.approval_numbers.is_empty() && self.patterns.is_empty()
+    }
+
+    pub fn is_blocked(&self, e: &Establishment, code_country: CountrySource) -> bool {
+        if self.operator_ids.contains(&e.operator_id) && !e.operator_id.is_unknown() {
+            return true;
+        }
+        let Some(approval_number) = &e.approval_number else {
+            return false;
+        };
+        if self.approval_numbers.contains(approval_number) {
+            return true;
+        }
+        let code = e.packager_code(code_country).unwrap_or_default();
+        self.patterns.iter().any(|p| p.is_match(&code))
+    }
+}
+
+fn deserialize_patterns<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Regex>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|p| Regex::new(p).map_err(de::Error::custom))
+        .collect()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CsvFormat {
+    pub delimiter: char,
+    /// WHATWG encoding label, e.g. `utf-8` or `windows-1252`.
+    pub encoding: String,
+    pub header: HeaderStyle,
+    pub quote_style: QuoteStyle,
+}
+
+impl Default for CsvFormat {
+    fn default() -> Self {
+        CsvFormat {
+            delimiter: ',',
+            encoding: "utf-8".to_owned(),
+            header: HeaderStyle::Default,
+            quote_style: QuoteStyle::Necessary,
+        }
+    }
+}
+
+impl CsvFormat {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.delimiter.is_ascii() {
+            return Err(format!("delimiter {:?} is not ASCII", self.delimiter));
+        }
+        if encoding_rs::Encoding::for_label(self.encoding.as_bytes()).is_none() {
+            return Err(format!("unknown encoding {:?}", self.encoding));
+        }
+        Ok(())
+    }
+
+    /// CSV writer settings for this format; the encoding is applied separately.
+    pub fn writer_builder(&self) -> csv::WriterBuilder {
+        let mut builder = csv::WriterBuilder::new();
+        builder
+            .delimiter(self.delimiter as u8)
+            .has_headers(self.header != HeaderStyle::None)
+            .quote_style(self.quote_style.into());
+        builder
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HeaderStyle {
+    Default,
+    None,
+}
+
+/// When CSV fields are put in quotes.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteStyle {
+    /// Only fields containing the delimiter, quotes or line breaks.
+    #[default]
+    Necessary,
+    Always,
+    /// All fields that are not numbers.
+    NonNumeric,
+    /// No fields, even if that makes the file ambiguous.
+    Never,
+}
+
+impl From<QuoteStyle> for csv::QuoteStyle {
+    fn from(style: QuoteStyle) -> csv::QuoteStyle {
+        match style {
+            QuoteStyle::Necessary => csv::QuoteStyle::Necessary,
+            QuoteStyle::Always => csv::QuoteStyle::Always,
+            QuoteStyle::NonNumeric => csv::QuoteStyle::NonNumeric,
+            QuoteStyle::Never => csv::QuoteStyle::Never,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, Box<dyn std::error::Error>> {
+        let config: Config = toml::from_str(&std::fs::read_to_string(path)?)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for (country, format) in &self.export.countries {
+            format
+                .validate()
+                .map_err(|e| format!("export.countries.{country}: {e}"))?;
+        }
+        Ok(())
+    }
+}
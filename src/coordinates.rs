@@ -0,0 +1,174 @@
+use geo::Point;
+use std::fmt;
+
+/// A WGS84 position, named by axis so latitude and longitude cannot be swapped unnoticed.
+///
+/// `geo::Point` and GeoJSON put the longitude first, while most people and the CSV output put the
+/// latitude first; convert with [`Coordinates::from_point`] and [`Coordinates::point`] rather than
+/// by hand.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Coordinates {
+    lat: f64,
+    lng: f64,
+}
+
+/// A latitude outside -90..=90 or a longitude outside -180..=180.
+#[derive(Debug)]
+pub struct OutOfRange {
+    pub lat: f64,
+    pub lng: f64,
+}
+
+impl fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "latitude {} and longitude {} are not a valid position",
+            self.lat, self.lng
+        )
+    }
+}
+
+impl std::error::Error for OutOfRange {}
+
+impl Coordinates {
+    pub fn new(lat: f64, lng: f64) -> Result<Coordinates, OutOfRange> {
+        if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lng) {
+            Ok(Coordinates { lat, lng })
+        } else {
+            Err(OutOfRange { lat, lng })
+        }
+    }
+
+    /// Converts a point as the geocoding providers return it, with the longitude as `x`.
+    pub fn from_point(point: Point<f64>) -> Result<Coordinates, OutOfRange> {
+        Coordinates::new(point.y(), point.x())
+    }
+
+    pub fn lat(&self) -> f64 {
+        self.lat
+    }
+
+    pub fn lng(&self) -> f64 {
+        self.lng
+    }
+
+    /// The position as a `geo` point, with the longitude as `x`.
+    pub fn point(&self) -> Point<f64> {
+        Point::new(self.lng, self.lat)
+    }
+
+    /// The position as a GeoJSON position, longitude first.
+    pub fn geojson_position(&self) -> Vec<f64> {
+        vec![self.lng, self.lat]
+    }
+
+    /// The position as WKT, longitude first.
+    pub fn wkt(&self) -> String {
+        format!("POINT({} {})", self.lng, self.lat)
+    }
+
+    /// Rounds both axes to `decimals` places.
+    pub fn round(&self, decimals: u32) -> Coordinates {
+        Coordinates {
+            lat: round_half_even(self.lat, decimals),
+            lng: round_half_even(self.lng, decimals),
+        }
+    }
+
+    /// Web Mercator (EPSG:3857) position as `(x, y)` in meters.
+    pub fn web_mercator(&self) -> (f64, f64) {
+        web_mercator(self.lat, self.lng)
+    }
+}
+
+/// Rounds `value` to `decimals` places, resolving ties to the even neighbour (banker's rounding)
+/// so that rounding many coordinates introduces no systematic bias.
+pub fn round_half_even(value: f64, decimals: u32) -> f64 {
+    let factor = 10f64.powi(decimals as i32);
+    (value * factor).round_ties_even() / factor
+}
+
+/// Equatorial radius of the WGS84 ellipsoid, which Web Mercator uses as a sphere radius.
+const EARTH_RADIUS: f64 = 6_378_137.0;
+
+/// Latitude beyond which Web Mercator tiles end, making the projected square.
+const MAX_MERCATOR_LATITUDE: f64 = 85.051_128_779_806_59;
+
+/// Projects a WGS84 position to Web Mercator (EPSG:3857), returning `(x, y)` in meters. Latitudes
+/// beyond the tile square are clamped to its edge.
+pub fn web_mercator(lat: f64, lng: f64) -> (f64, f64) {
+    let lat = lat.clamp(-MAX_MERCATOR_LATITUDE, MAX_MERCATOR_LATITUDE);
+    let x = EARTH_RADIUS * lng.to_radians();
+    let y = EARTH_RADIUS
+        * (std::f64::consts::FRAC_PI_4 + lat.to_radians() / 2.0)
+            .tan()
+            .ln();
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_ties_to_even() {
+        assert_eq!(round_half_even(0.125, 2), 0.12);
+        assert_eq!(round_half_even(0.375, 2), 0.38);
+        assert_eq!(round_half_even(2.5, 0), 2.0);
+        assert_eq!(round_half_even(3.5, 0), 4.0);
+        assert_eq!(round_half_even(-0.125, 2), -0.12);
+    }
+
+    #[test]
+    fn rounds_non_ties_to_nearest() {
+        assert_eq!(round_half_even(48.858_370_1, 6), 48.858_37);
+        assert_eq!(round_half_even(2.294_481_6, 6), 2.294_482);
+        assert_eq!(round_half_even(-3.703_790_5, 3), -3.704);
+    }
+
+    #[test]
+    fn keeps_values_already_at_precision() {
+        assert_eq!(round_half_even(52.52, 7), 52.52);
+        assert_eq!(round_half_even(13.405, 3), 13.405);
+    }
+
+    #[test]
+    fn projects_to_web_mercator() {
+        let (x, y) = web_mercator(0.0, 0.0);
+        assert!(x.abs() < 1e-6 && y.abs() < 1e-6);
+
+        let (x, y) = web_mercator(48.858_37, 2.294_481);
+        assert!((x - 255_420.46).abs() < 0.01, "{x}");
+        assert!((y - 6_250_863.83).abs() < 0.01, "{y}");
+
+        let (x, _) = web_mercator(0.0, 180.0);
+        assert!((x - 20_037_508.342_789).abs() < 1e-3);
+    }
+
+    #[test]
+    fn converts_points_with_longitude_as_x() {
+        let c = Coordinates::from_point(Point::new(2.294_481, 48.858_37)).unwrap();
+        assert_eq!((c.lat(), c.lng()), (48.858_37, 2.294_481));
+        assert_eq!(c.point(), Point::new(2.294_481, 48.858_37));
+        assert_eq!(c.geojson_position(), vec![2.294_481, 48.858_37]);
+    }
+
+    #[test]
+    fn rejects_out_of_range_positions() {
+        assert!(Coordinates::new(90.0, -180.0).is_ok());
+        assert!(Coordinates::new(91.0, 0.0).is_err());
+        assert!(Coordinates::new(0.0, 180.5).is_err());
+        assert!(Coordinates::new(f64::NAN, 0.0).is_err());
+    }
+
+    #[test]
+    fn clamps_polar_latitudes() {
+        assert_eq!(
+            web_mercator(90.0, 0.0),
+            web_mercator(MAX_MERCATOR_LATITUDE, 0.0)
+        );
+        let (_, y) = web_mercator(MAX_MERCATOR_LATITUDE, 0.0);
+        assert!((y - 20_037_508.342_789).abs() < 1e-3, "{y}");
+    }
+}
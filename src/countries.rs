@@ -0,0 +1,103 @@
+use clap::ValueEnum;
+use csv::StringRecord;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Country names per language, extracted from the EU Publications Office country authority table,
+/// followed by the languages addresses in the country are commonly written in.
+///
+/// Keyed by the codes TRACES uses, so Greece is listed under `EL` as well as `GR`.
+const COUNTRIES_CSV: &str = include_str!("../data/countries.csv");
+
+/// Which of an establishment's countries to use, for the rare operators whose address lies outside
+/// the country that approved them.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CountrySource {
+    /// The country whose TRACES listing the establishment came from.
+    Approving,
+    /// The country of the establishment's address.
+    Address,
+}
+
+/// Officially assigned ISO 3166-1 alpha-2 codes.
+const ISO_3166_CODES: &[&str] = &[
+    "AD", "AE", "AF", "AG", "AI", "AL", "AM", "AO", "AQ", "AR", "AS", "AT", "AU", "AW", "AX", "AZ",
+    "BA", "BB", "BD", "BE", "BF", "BG", "BH", "BI", "BJ", "BL", "BM", "BN", "BO", "BQ", "BR", "BS",
+    "BT", "BV", "BW", "BY", "BZ", "CA", "CC", "CD", "CF", "CG", "CH", "CI", "CK", "CL", "CM", "CN",
+    "CO", "CR", "CU", "CV", "CW", "CX", "CY", "CZ", "DE", "DJ", "DK", "DM", "DO", "DZ", "EC", "EE",
+    "EG", "EH", "ER", "ES", "ET", "FI", "FJ", "FK", "FM", "FO", "FR", "GA", "GB", "GD", "GE", "GF",
+    "GG", "GH", "GI", "GL", "GM", "GN", "GP", "GQ", "GR", "GS", "GT", "GU", "GW", "GY", "HK", "HM",
+    "HN", "HR", "HT", "HU", "ID", "IE", "IL", "IM", "IN", "IO", "IQ", "IR", "IS", "IT", "JE", "JM",
+    "JO", "JP", "KE", "KG", "KH", "KI", "KM", "KN", "KP", "KR", "KW", "KY", "KZ", "LA", "LB", "LC",
+    "LI", "LK", "LR", "LS", "LT", "LU", "LV", "LY", "MA", "MC", "MD", "ME", "MF", "MG", "MH", "MK",
+    "ML", "MM", "MN", "MO", "MP", "MQ", "MR", "MS", "MT", "MU", "MV", "MW", "MX", "MY", "MZ", "NA",
+    "NC", "NE", "NF", "NG", "NI", "NL", "NO", "NP", "NR", "NU", "NZ", "OM", "PA", "PE", "PF", "PG",
+    "PH", "PK", "PL", "PM", "PN", "PR", "PS", "PT", "PW", "PY", "QA", "RE", "RO", "RS", "RU", "RW",
+    "SA", "SB", "SC", "SD", "SE", "SG", "SH", "SI", "SJ", "SK", "SL", "SM", "SN", "SO", "SR", "SS",
+    "ST", "SV", "SX", "SY", "SZ", "TC", "TD", "TF", "TG", "TH", "TJ", "TK", "TL", "TM", "TN", "TO",
+    "TR", "TT", "TV", "TW", "TZ", "UA", "UG", "UM", "US", "UY", "UZ", "VA", "VC", "VE", "VG", "VI",
+    "VN", "VU", "WF", "WS", "YE", "YT", "ZA", "ZM", "ZW",
+];
+
+/// Codes TRACES uses outside ISO 3166-1: `EL` for Greece, `XI` for Northern Ireland and `XK` for
+/// Kosovo.
+const TRACES_ALIASES: &[&str] = &["EL", "XI", "XK"];
+
+const FALLBACK_LANG: &str = "en";
+const LANGUAGES_COLUMN: &str = "languages";
+
+struct CountryTable {
+    headers: StringRecord,
+    rows: HashMap<String, StringRecord>,
+}
+
+impl CountryTable {
+    fn get(&self, code: &str, column: &str) -> Option<&str> {
+        let row = self.rows.get(&code.to_uppercase())?;
+        let index = self.headers.iter().position(|h| h == column)?;
+        row.get(index).filter(|v| !v.is_empty())
+    }
+}
+
+fn table() -> &'static CountryTable {
+    static TABLE: OnceLock<CountryTable> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut reader = csv::Reader::from_reader(COUNTRIES_CSV.as_bytes());
+        let headers = reader
+            .headers()
+            .expect("bundled country list has a header")
+            .clone();
+        let rows = reader
+            .records()
+            .map(|r| r.expect("bundled country list is valid CSV"))
+            .map(|r| (r[0].to_owned(), r))
+            .collect();
+        CountryTable { headers, rows }
+    })
+}
+
+/// Display name of `code` in `lang`, falling back to English for languages the list lacks.
+pub fn name(code: &str, lang: &str) -> Option<&'static str> {
+    let table = table();
+    let lang = lang.to_lowercase();
+    if lang != LANGUAGES_COLUMN {
+        if let Some(name) = table.get(code, &lang) {
+            return Some(name);
+        }
+    }
+    table.get(code, FALLBACK_LANG)
+}
+
+/// Languages addresses in `code` are commonly written in, most common first, as an
+/// `Accept-Language` style list such as `nl,fr,de`.
+pub fn languages(code: &str) -> Option<String> {
+    table()
+        .get(code, LANGUAGES_COLUMN)
+        .map(|l| l.split_whitespace().collect::<Vec<_>>().join(","))
+}
+
+/// Whether `code` is an ISO 3166-1 alpha-2 code or one of the aliases TRACES uses.
+pub fn is_known(code: &str) -> bool {
+    let code = code.to_uppercase();
+    ISO_3166_CODES.contains(&code.as_str()) || TRACES_ALIASES.contains(&code.as_str())
+}
@@ -0,0 +1,145 @@
+use crate::manifest::RunManifest;
+use crate::output::AtomicFile;
+use clap::ValueEnum;
+use serde::*;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum CoverageFormat {
+    #[default]
+    Csv,
+    Markdown,
+}
+
+/// Establishments listed by TRACES for one country and section, and how many made it into the
+/// output with coordinates.
+#[derive(Serialize, Debug)]
+pub struct CoverageRow {
+    pub country: String,
+    pub section: String,
+    pub establishments: i32,
+    pub geocoded: usize,
+    pub skipped: usize,
+    pub errors: usize,
+}
+
+impl CoverageRow {
+    fn new(country: &str, section: &str, establishments: i32) -> CoverageRow {
+        CoverageRow {
+            country: country.to_owned(),
+            section: section.to_owned(),
+            establishments,
+            geocoded: 0,
+            skipped: 0,
+            errors: 0,
+        }
+    }
+
+    pub fn share(&self) -> String {
+        if self.establishments > 0 {
+            format!(
+                "{:.1}%",
+                self.geocoded as f64 * 100.0 / self.establishments as f64
+            )
+        } else {
+            "-".to_owned()
+        }
+    }
+}
+
+/// Country × section matrix of the last run, with a total row per country.
+pub fn coverage(manifest: &RunManifest) -> Vec<CoverageRow> {
+    let mut rows = vec![];
+    for (country, c) in &manifest.countries {
+        let mut sections: BTreeMap<&str, CoverageRow> = c
+            .categories
+            .iter()
+            .map(|(section, count)| (section.as_str(), CoverageRow::new(country, section, *count)))
+            .collect();
+        for p in &c.packager_codes {
+            let section = p.section.as_str();
+            sections
+                .entry(section)
+                .or_insert_with(|| CoverageRow::new(country, section, 0))
+                .geocoded += 1;
+        }
+        for s in &c.skipped {
+            let section = s.section.as_str();
+            sections
+                .entry(section)
+                .or_insert_with(|| CoverageRow::new(country, section, 0))
+                .skipped += 1;
+        }
+        for e in &c.errors {
+            let section = e.section.as_str();
+            sections
+                .entry(section)
+                .or_insert_with(|| CoverageRow::new(country, section, 0))
+                .errors += 1;
+        }
+
+        let mut total = CoverageRow::new(country, "total", 0);
+        for r in sections.into_values() {
+            total.establishments += r.establishments;
+            total.geocoded += r.geocoded;
+            total.skipped += r.skipped;
+            total.errors += r.errors;
+            rows.push(r);
+        }
+        rows.push(total);
+    }
+
+    rows
+}
+
+/// Writes the coverage matrix of the run manifest at `manifest` to `output`.
+pub fn write_coverage(
+    manifest: &Path,
+    output: &Path,
+    format: CoverageFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(manifest) = RunManifest::load(manifest)? else {
+        return Err(format!("no run manifest at {}", manifest.display()).into());
+    };
+    let rows = coverage(&manifest);
+
+    match format {
+        CoverageFormat::Csv => {
+            let mut wtr = csv::Writer::from_writer(AtomicFile::create(output)?);
+            for r in &rows {
+                wtr.serialize(r)?;
+            }
+            wtr.into_inner()?.commit()?;
+        }
+        CoverageFormat::Markdown => {
+            let mut out = AtomicFile::create(output)?;
+            writeln!(
+                out,
+                "| Country | Section | Establishments | Geocoded | Coverage | Skipped | Errors |"
+            )?;
+            writeln!(out, "|---|---|---:|---:|---:|---:|---:|")?;
+            for r in &rows {
+                let section = if r.section == "total" {
+                    "**total**"
+                } else {
+                    &r.section
+                };
+                writeln!(
+                    out,
+                    "| {} | {} | {} | {} | {} | {} | {} |",
+                    r.country,
+                    section,
+                    r.establishments,
+                    r.geocoded,
+                    r.share(),
+                    r.skipped,
+                    r.errors
+                )?;
+            }
+            out.commit()?;
+        }
+    }
+    Ok(())
+}
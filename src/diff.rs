@@ -0,0 +1,202 @@
+use crate::code;
+use crate::export::off_tag;
+use crate::manifest::RunManifest;
+use crate::output::{self, Compression, OutputWriter};
+use serde::*;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How a packager code differs between the previous run and this one.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    /// The code persisted but its operator was renamed.
+    Updated,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Change {
+    pub change: ChangeKind,
+    pub code: String,
+    pub name: String,
+    pub previous_name: Option<String>,
+}
+
+/// An operator rename observed between two runs.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NameChange {
+    pub code: String,
+    pub previous_name: String,
+    pub name: String,
+    /// Unix time of the run that first saw the new name.
+    pub changed_at: u64,
+}
+
+fn names_by_code(manifest: &RunManifest) -> BTreeMap<&str, &str> {
+    manifest
+        .countries
+        .values()
+        .flat_map(|c| c.packager_codes.iter())
+        .map(|p| (p.code.as_str(), p.name.as_str()))
+        .collect()
+}
+
+/// Compares the packager codes of two runs by code, reporting renames as a single `updated`
+/// record instead of a removal and an addition.
+pub fn diff(previous: &RunManifest, current: &RunManifest) -> Vec<Change> {
+    let before = names_by_code(previous);
+    let after = names_by_code(current);
+
+    let mut changes = vec![];
+    for (code, name) in &after {
+        match before.get(code) {
+            None => changes.push(Change {
+                change: ChangeKind::Added,
+                code: code.to_string(),
+                name: name.to_string(),
+                previous_name: None,
+            }),
+            Some(previous_name) if previous_name != name => changes.push(Change {
+                change: ChangeKind::Updated,
+                code: code.to_string(),
+                name: name.to_string(),
+                previous_name: Some(previous_name.to_string()),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (code, name) in &before {
+        if !after.contains_key(code) {
+            changes.push(Change {
+                change: ChangeKind::Removed,
+                code: code.to_string(),
+                name: name.to_string(),
+                previous_name: None,
+            });
+        }
+    }
+
+    changes
+}
+
+/// Turns the renames in `changes` into name-history entries stamped with the current time.
+pub fn name_changes(changes: &[Change]) -> Vec<NameChange> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    changes
+        .iter()
+        .filter(|c| c.change == ChangeKind::Updated)
+        .map(|c| NameChange {
+            code: c.code.clone(),
+            previous_name: c.previous_name.clone().unwrap_or_default(),
+            name: c.name.clone(),
+            changed_at: now,
+        })
+        .collect()
+}
+
+pub fn write_diff_csv(
+    path: &Path,
+    changes: &[Change],
+    compression: Option<Compression>,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = output::output_path(path, compression);
+    let mut wtr = csv::Writer::from_writer(OutputWriter::create(&path, compression)?);
+    for c in changes {
+        wtr.serialize(c)?;
+    }
+
+    wtr.into_inner()?.finish()?;
+    Ok(path)
+}
+
+/// A packager code that is no longer listed, as last seen, for moderators deciding whether to
+/// retire it on Open Food Facts.
+#[derive(Serialize, Debug)]
+struct RemovedCode<'a> {
+    country: &'a str,
+    code: &'a str,
+    off_tag: String,
+    last_name: &'a str,
+    last_lat: f64,
+    last_lng: f64,
+    section: &'a str,
+    section_name: &'a str,
+}
+
+/// Codes of establishments that are still listed but did not make it into this run's output,
+/// such as those that failed to geocode.
+fn still_listed(current: &RunManifest) -> HashSet<String> {
+    let mut codes = HashSet::new();
+    for (country, c) in &current.countries {
+        let approval_numbers = c
+            .skipped
+            .iter()
+            .filter_map(|s| s.approval_number.as_deref())
+            .chain(c.errors.iter().filter_map(|e| e.approval_number.as_deref()));
+        for approval_number in approval_numbers {
+            for third_country in [false, true] {
+                codes.insert(code::format_establishment_code(
+                    country,
+                    approval_number,
+                    third_country,
+                ));
+            }
+        }
+    }
+    codes
+}
+
+/// Writes the codes of the previous run that disappeared from TRACES, with their last known name
+/// and position. Codes still listed but left out of this run for other reasons are not reported.
+/// Returns the path written and the number of codes in it.
+pub fn write_removed_codes_csv(
+    path: &Path,
+    previous: &RunManifest,
+    current: &RunManifest,
+    compression: Option<Compression>,
+) -> Result<(PathBuf, usize), Box<dyn std::error::Error>> {
+    let after = names_by_code(current);
+    let still_listed = still_listed(current);
+
+    let path = output::output_path(path, compression);
+    let mut wtr = csv::Writer::from_writer(OutputWriter::create(&path, compression)?);
+    let mut written = 0;
+    let mut not_exported = 0;
+    for (country, c) in &previous.countries {
+        for p in &c.packager_codes {
+            if after.contains_key(p.code.as_str()) {
+                continue;
+            }
+            if still_listed.contains(&p.code) {
+                not_exported += 1;
+                continue;
+            }
+            let coordinates = p.coordinates();
+            wtr.serialize(RemovedCode {
+                country,
+                code: &p.code,
+                off_tag: off_tag(&p.code),
+                last_name: &p.name,
+                last_lat: coordinates.lat(),
+                last_lng: coordinates.lng(),
+                section: p.section.as_str(),
+                section_name: &p.section_name,
+            })?;
+            written += 1;
+        }
+    }
+    wtr.into_inner()?.finish()?;
+    if not_exported > 0 {
+        eprintln!(
+            "{not_exported} codes missing from this run are still listed in TRACES; not reporting \
+             them as removed"
+        );
+    }
+    Ok((path, written))
+}
@@ -0,0 +1,232 @@
+use crate::manifest::RunManifest;
+use crate::names::strip_legal_forms;
+use crate::output::{self, Compression, OutputWriter};
+use crate::PackagerCode;
+use clap::ValueEnum;
+use geo::HaversineDistance;
+use serde::*;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Meters per degree of latitude, to turn a distance into a latitude window.
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// What to do when operators with different names share a packager code, e.g. a site operator
+/// listed next to the owning company.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// Keep all rows, numbering them in the `disambiguator` column.
+    #[default]
+    KeepBoth,
+    /// Keep the first row of each code and drop the others.
+    KeepFirst,
+    /// Withhold all rows of the code from the output and list them for manual review.
+    Review,
+}
+
+/// Applies `policy` to codes shared by differently named operators and returns the rows that were
+/// removed from the output.
+///
+/// Rows repeating both code and name (the same operator listed under several sections) are not
+/// conflicts and are left alone.
+pub fn resolve(manifest: &mut RunManifest, policy: DuplicatePolicy) -> Vec<PackagerCode> {
+    let mut names_by_code: HashMap<String, Vec<String>> = HashMap::new();
+    for p in manifest
+        .countries
+        .values()
+        .flat_map(|c| c.packager_codes.iter())
+    {
+        let names = names_by_code.entry(p.code.clone()).or_default();
+        if !names.contains(&p.name) {
+            names.push(p.name.clone());
+        }
+    }
+    names_by_code.retain(|_, names| names.len() > 1);
+
+    let mut removed = vec![];
+    for c in manifest.countries.values_mut() {
+        c.packager_codes = std::mem::take(&mut c.packager_codes)
+            .into_iter()
+            .filter_map(|mut p| {
+                let Some(names) = names_by_code.get(&p.code) else {
+                    return Some(p);
+                };
+                let index = names.iter().position(|n| *n == p.name).unwrap_or_default();
+                match policy {
+                    DuplicatePolicy::KeepBoth => {
+                        p.disambiguator = Some(index as u32 + 1);
+                        Some(p)
+                    }
+                    DuplicatePolicy::KeepFirst if index == 0 => Some(p),
+                    DuplicatePolicy::KeepFirst | DuplicatePolicy::Review => {
+                        removed.push(p);
+                        None
+                    }
+                }
+            })
+            .collect();
+    }
+
+    if !names_by_code.is_empty() {
+        eprintln!(
+            "{} packager codes are shared by differently named operators",
+            names_by_code.len()
+        );
+    }
+    removed
+}
+
+/// Writes rows withheld for manual review.
+pub fn write_review_csv(
+    path: &Path,
+    packager_codes: &[PackagerCode],
+    compression: Option<Compression>,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = output::output_path(path, compression);
+    let mut wtr = csv::Writer::from_writer(OutputWriter::create(&path, compression)?);
+    for p in packager_codes {
+        wtr.serialize(p)?;
+    }
+    wtr.into_inner()?.finish()?;
+    Ok(path)
+}
+
+/// Two rows with different codes whose operators look like the same one, such as an establishment
+/// that was registered again under a new number.
+#[derive(Serialize, Debug)]
+pub struct LikelyDuplicate<'a> {
+    pub country: &'a str,
+    pub code: &'a str,
+    pub name: &'a str,
+    pub other_code: &'a str,
+    pub other_name: &'a str,
+    pub distance_m: u32,
+    pub similarity: f64,
+}
+
+/// Name reduced to what tells operators apart: lower case, letters and digits only, without legal
+/// forms.
+fn normalize(name: &str) -> String {
+    strip_legal_forms(name)
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+fn bigrams(name: &str) -> HashSet<(char, char)> {
+    let chars: Vec<char> = name.chars().collect();
+    chars.windows(2).map(|w| (w[0], w[1])).collect()
+}
+
+/// Dice coefficient of the character bigrams of two normalized names, from 0.0 to 1.0.
+fn similarity(a: &str, b: &str) -> f64 {
+    if a == b {
+        return 1.0;
+    }
+    let (a, b) = (bigrams(a), bigrams(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    2.0 * a.intersection(&b).count() as f64 / (a.len() + b.len()) as f64
+}
+
+/// Pairs of rows of the same country with different codes, at most `max_distance` meters apart,
+/// whose names are at least `min_similarity` alike.
+pub fn find_likely_duplicates(
+    manifest: &RunManifest,
+    max_distance: f64,
+    min_similarity: f64,
+) -> Vec<LikelyDuplicate<'_>> {
+    let mut rows: Vec<(&str, &PackagerCode, String)> = manifest
+        .countries
+        .iter()
+        .flat_map(|(country, c)| c.packager_codes.iter().map(move |p| (country.as_str(), p)))
+        .map(|(country, p)| (country, p, normalize(&p.name)))
+        .filter(|(_, _, name)| !name.is_empty())
+        .collect();
+    rows.sort_by(|a, b| a.1.coordinates().lat().total_cmp(&b.1.coordinates().lat()));
+
+    let window = max_distance / METERS_PER_DEGREE;
+    let mut pairs = vec![];
+    for (i, (country, p, name)) in rows.iter().enumerate() {
+        let position = p.coordinates();
+        for (other_country, other, other_name) in rows[i + 1..]
+            .iter()
+            .take_while(|(_, o, _)| o.coordinates().lat() - position.lat() <= window)
+        {
+            if other_country != country || other.code == p.code {
+                continue;
+            }
+            let distance = position
+                .point()
+                .haversine_distance(&other.coordinates().point());
+            if distance > max_distance {
+                continue;
+            }
+            let similarity = similarity(name, other_name);
+            if similarity >= min_similarity {
+                let (p, other) = if p.code <= other.code {
+                    (p, other)
+                } else {
+                    (other, p)
+                };
+                pairs.push(LikelyDuplicate {
+                    country,
+                    code: &p.code,
+                    name: &p.name,
+                    other_code: &other.code,
+                    other_name: &other.name,
+                    distance_m: distance.round() as u32,
+                    similarity: (similarity * 100.0).round() / 100.0,
+                });
+            }
+        }
+    }
+    pairs.sort_by(|a, b| (a.country, a.code, a.other_code).cmp(&(b.country, b.code, b.other_code)));
+    pairs.dedup_by(|a, b| (a.code, a.other_code) == (b.code, b.other_code));
+    pairs
+}
+
+/// Writes likely duplicates for review.
+pub fn write_likely_duplicates_csv(
+    path: &Path,
+    pairs: &[LikelyDuplicate],
+    compression: Option<Compression>,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = output::output_path(path, compression);
+    let mut wtr = csv::Writer::from_writer(OutputWriter::create(&path, compression)?);
+    for pair in pairs {
+        wtr.serialize(pair)?;
+    }
+    wtr.into_inner()?.finish()?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_differing_in_legal_form_and_punctuation_are_alike() {
+        assert_eq!(
+            similarity(
+                &normalize("Fromagerie Dupont SARL"),
+                &normalize("FROMAGERIE DUPONT")
+            ),
+            1.0
+        );
+        assert!(
+            similarity(
+                &normalize("Boucherie Martin"),
+                &normalize("Boucherie Martins")
+            ) > 0.9
+        );
+        assert!(
+            similarity(
+                &normalize("Boucherie Martin"),
+                &normalize("Fromagerie Dupont")
+            ) < 0.5
+        );
+    }
+}
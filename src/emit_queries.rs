@@ -0,0 +1,69 @@
+use crate::countries::CountrySource;
+use crate::geocode::{build_queries_with_name, QueryTier};
+use crate::ids::{ApprovalNumber, OperatorId, SectionCode};
+use crate::output::AtomicFile;
+use crate::transliterate::QueryScript;
+use crate::{Establishment, EstablishmentsByCountry};
+use serde::*;
+use std::path::Path;
+
+#[derive(Serialize, Debug)]
+struct QueryRow<'a> {
+    country: &'a str,
+    operator_id: OperatorId,
+    approval_number: &'a ApprovalNumber,
+    section: &'a SectionCode,
+    tier: QueryTier,
+    script: QueryScript,
+    query: &'a str,
+    language: Option<&'a str>,
+}
+
+/// Writes the geocoding queries each establishment would be tried with, most specific first,
+/// without sending any. Rows are sorted by country and operator so that runs diff cleanly.
+///
+/// Returns the number of queries written.
+pub fn write_queries_csv(
+    path: &Path,
+    establishments_by_country: &EstablishmentsByCountry,
+    geocode_country: CountrySource,
+    name_fallback: bool,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut countries: Vec<_> = establishments_by_country.iter().collect();
+    countries.sort_by_key(|(country, _)| *country);
+
+    let mut wtr = csv::Writer::from_writer(AtomicFile::create(path)?);
+    let mut written = 0;
+    for (country, establishments) in countries {
+        let mut establishments: Vec<&Establishment> = establishments.iter().collect();
+        establishments.sort_by(|a, b| {
+            (a.operator_id, &a.section, &a.approval_number).cmp(&(
+                b.operator_id,
+                &b.section,
+                &b.approval_number,
+            ))
+        });
+        for e in establishments {
+            let Some(approval_number) = e.approval_number.as_ref().filter(|a| !a.is_empty()) else {
+                continue;
+            };
+            let address = e.geocoding_address(geocode_country);
+            let name = e.operator_name.as_deref().filter(|_| name_fallback);
+            for q in build_queries_with_name(&address, name) {
+                wtr.serialize(QueryRow {
+                    country,
+                    operator_id: e.operator_id,
+                    approval_number,
+                    section: &e.section,
+                    tier: q.tier,
+                    script: q.script,
+                    query: &q.query,
+                    language: q.language.as_deref(),
+                })?;
+                written += 1;
+            }
+        }
+    }
+    wtr.into_inner()?.commit()?;
+    Ok(written)
+}
@@ -0,0 +1,108 @@
+use crate::code::{register_formatter, strip_affixes};
+use crate::ids::{CountryCode, SectionCode};
+use crate::Establishment;
+#[cfg(feature = "xls")]
+use crate::{http, import};
+use std::str::FromStr;
+use url::Url;
+
+/// EEA EFTA states whose approved establishments are published by the EFTA Surveillance Authority
+/// rather than TRACES.
+const ESA_COUNTRIES: &[&str] = &["IS", "LI"];
+
+/// Identification mark of establishments in the EEA EFTA states.
+const EFTA_MARK: &str = "EFTA";
+
+/// An establishment list published by the EFTA Surveillance Authority as an XLS/XLSX workbook,
+/// given as `COUNTRY[:SECTION]=URL`.
+#[derive(Debug, Clone)]
+pub struct EsaList {
+    pub country: CountryCode,
+    pub section: SectionCode,
+    pub url: Url,
+}
+
+impl FromStr for EsaList {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (source, url) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected COUNTRY[:SECTION]=URL, got {value:?}"))?;
+        let (country, section) = source.split_once(':').unwrap_or((source, ""));
+        let country: CountryCode = country.parse()?;
+        if !ESA_COUNTRIES.contains(&country.as_str()) {
+            return Err(format!(
+                "ESA publishes lists for {} only, not {country}",
+                ESA_COUNTRIES.join(" and ")
+            ));
+        }
+
+        Ok(EsaList {
+            country,
+            section: section.parse()?,
+            url: url.parse().map_err(|e| format!("{url:?}: {e}"))?,
+        })
+    }
+}
+
+/// Downloads an ESA list and reads the establishments of its first worksheet.
+#[cfg(feature = "xls")]
+pub async fn fetch_esa_establishments(
+    list: &EsaList,
+) -> Result<Vec<Establishment>, Box<dyn std::error::Error>> {
+    let bytes = http::get(list.url.clone())
+        .await?
+        .error_for_status()
+        .map_err(http::redact_error)?
+        .bytes()
+        .await
+        .map_err(http::redact_error)?;
+    let workbook = calamine::open_workbook_auto_from_rs(std::io::Cursor::new(bytes))?;
+    let source = http::redact(&list.url).to_string();
+    let rows = import::first_worksheet_rows(workbook, &source)?;
+    import::read_establishments(rows, &list.country, &list.section, &source)
+}
+
+#[cfg(not(feature = "xls"))]
+pub async fn fetch_esa_establishments(
+    _list: &EsaList,
+) -> Result<Vec<Establishment>, Box<dyn std::error::Error>> {
+    Err("--esa-list needs a build with the `xls` feature".into())
+}
+
+/// Renders codes of the ESA countries with the `EFTA` mark, as in `IS A123 EFTA`, instead of `EC`.
+pub fn register_formatters() {
+    for country in ESA_COUNTRIES {
+        register_formatter(country, format_efta_code);
+    }
+}
+
+fn format_efta_code(country: &str, approval_number: &str) -> String {
+    let number = strip_affixes(country, approval_number);
+    let number = number
+        .rsplit_once(char::is_whitespace)
+        .filter(|(rest, mark)| mark.eq_ignore_ascii_case(EFTA_MARK) && !rest.trim().is_empty())
+        .map_or(number, |(rest, _)| rest.trim_end());
+    format!("{country} {number} {EFTA_MARK}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_codes_with_the_efta_mark() {
+        assert_eq!(format_efta_code("IS", "A123"), "IS A123 EFTA");
+        assert_eq!(format_efta_code("IS", "IS A123 EFTA"), "IS A123 EFTA");
+        assert_eq!(format_efta_code("LI", "LI-0012 EC"), "LI 0012 EFTA");
+    }
+
+    #[test]
+    fn accepts_esa_countries_only() {
+        assert!("IS:I=https://example.org/is.xlsx"
+            .parse::<EsaList>()
+            .is_ok());
+        assert!("NO=https://example.org/no.xlsx".parse::<EsaList>().is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use crate::cli::Args;
+use crate::geocode::REMOTE_REQUEST_INTERVAL;
+use crate::{rate, raw, traces, CountryCategory, ESTABLISHMENT_PAGE_SIZE};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::time::Duration;
+
+/// What a crawl is expected to cost, worked out from the category listing before any
+/// establishment is fetched.
+#[derive(Debug, Default)]
+pub struct Estimate {
+    pub establishments: usize,
+    pub traces_requests: usize,
+    pub geocode_requests: usize,
+    pub duration: Duration,
+}
+
+impl Estimate {
+    /// Estimates fetching `categories` within `--limit` and `--limit-per-country`, and geocoding
+    /// what they list with one request each to the first provider if it is remote. Detail
+    /// requests, retries and geocode cache hits are not accounted for.
+    pub fn new(categories: &[CountryCategory], args: &Args) -> Estimate {
+        let mut by_country: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for c in categories {
+            by_country
+                .entry(c.country.code.as_str())
+                .or_default()
+                .push(usize::try_from(c.number_of_establishments).unwrap_or_default());
+        }
+
+        let mut estimate = Estimate::default();
+        for sections in by_country.values() {
+            let mut in_country = 0;
+            for listed in sections {
+                let mut taken = *listed;
+                if let Some(limit) = args.limit_per_country {
+                    taken = taken.min(limit.saturating_sub(in_country));
+                }
+                if let Some(limit) = args.limit {
+                    taken = taken.min(limit.saturating_sub(estimate.establishments));
+                }
+                if taken > 0 && !raw::is_replaying() {
+                    estimate.traces_requests += taken.div_ceil(ESTABLISHMENT_PAGE_SIZE as usize);
+                }
+                in_country += taken;
+                estimate.establishments += taken;
+            }
+        }
+
+        if args.emit_queries.is_none() && args.geocoders.first().is_some_and(|p| p.is_remote()) {
+            estimate.geocode_requests = estimate.establishments;
+        }
+        let traces_interval = if args.adaptive_rate {
+            rate::ADAPTIVE_START
+        } else {
+            traces::REQUEST_INTERVAL
+        };
+        estimate.duration = traces_interval * estimate.traces_requests as u32
+            + REMOTE_REQUEST_INTERVAL * estimate.geocode_requests as u32;
+        estimate
+    }
+
+    pub fn print(&self) {
+        eprintln!(
+            "About to fetch {} establishments with {} TRACES requests and geocode them with {} \
+             requests to remote providers, taking about {}",
+            self.establishments,
+            self.traces_requests,
+            self.geocode_requests,
+            humantime::format_duration(Duration::from_secs(self.duration.as_secs()))
+        );
+    }
+}
+
+/// The crawl was not confirmed, so nothing ran: it is neither recorded nor reported as a run.
+#[derive(Debug)]
+pub struct Declined;
+
+impl Declined {
+    /// Process exit code, distinct from those of quality failures.
+    pub fn exit_code(&self) -> i32 {
+        5
+    }
+}
+
+impl fmt::Display for Declined {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the crawl was not confirmed")
+    }
+}
+
+impl std::error::Error for Declined {}
+
+/// Asks on the terminal whether to go ahead; unattended runs, with no terminal to ask on, go
+/// ahead without asking.
+pub fn confirm() -> io::Result<bool> {
+    if !io::stdin().is_terminal() {
+        return Ok(true);
+    }
+    eprint!("Continue? [y/N] ");
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
@@ -0,0 +1,195 @@
+use crate::cli::Args;
+use crate::config::CsvFormat;
+use crate::duplicates::DuplicatePolicy;
+use crate::output::{self, Compression, OutputWriter};
+use crate::PackagerCode;
+use encoding_rs::{EncoderResult, Encoding};
+use serde::ser::SerializeStruct;
+use serde::*;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The optional columns of `data.csv`, each written only when the flag producing it was given,
+/// so that by default the file stays close to the `name,code,lat,lng` OFF ingests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DataColumns {
+    nuts: bool,
+    country_name: bool,
+    disambiguator: bool,
+    in_water: bool,
+    poi_nearby: bool,
+    web_mercator: bool,
+}
+
+impl DataColumns {
+    pub fn for_args(args: &Args) -> DataColumns {
+        DataColumns {
+            nuts: args.nuts_boundaries.is_some(),
+            country_name: args.country_names,
+            disambiguator: args.duplicate_codes == DuplicatePolicy::KeepBoth,
+            in_water: args.check_water,
+            poi_nearby: args.overpass_check,
+            web_mercator: args.web_mercator,
+        }
+    }
+}
+
+/// A row of `data.csv` or a per-country file, with the columns `columns` selects.
+struct DataRow<'a> {
+    code: &'a PackagerCode,
+    columns: DataColumns,
+}
+
+impl Serialize for DataRow<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (p, columns) = (self.code, self.columns);
+        let mut row = serializer.serialize_struct("DataRow", 20)?;
+        row.serialize_field("name", &p.name)?;
+        row.serialize_field("code", &p.code)?;
+        row.serialize_field("lat", &p.lat)?;
+        row.serialize_field("lng", &p.lng)?;
+        row.serialize_field("section", &p.section)?;
+        row.serialize_field("section_name", &p.section_name)?;
+        row.serialize_field("geocode_tier", &p.geocode_tier)?;
+        row.serialize_field("location_precision", &p.location_precision)?;
+        if columns.nuts {
+            row.serialize_field("nuts2", &p.nuts2)?;
+            row.serialize_field("nuts3", &p.nuts3)?;
+        }
+        if columns.country_name {
+            row.serialize_field("country_name", &p.country_name)?;
+        }
+        if columns.disambiguator {
+            row.serialize_field("disambiguator", &p.disambiguator)?;
+        }
+        if columns.in_water {
+            row.serialize_field("in_water", &p.in_water)?;
+        }
+        if columns.web_mercator {
+            row.serialize_field("x_3857", &p.x_3857)?;
+            row.serialize_field("y_3857", &p.y_3857)?;
+        }
+        row.serialize_field("address_country", &p.address_country)?;
+        if columns.poi_nearby {
+            row.serialize_field("poi_nearby", &p.poi_nearby)?;
+        }
+        row.serialize_field("query_script", &p.query_script)?;
+        row.serialize_field("code_raw", &p.code_raw)?;
+        row.serialize_field("geocoded_at", &p.geocoded_at)?;
+        row.end()
+    }
+}
+
+/// Writes all packager codes to `data.csv` in UTF-8, with the delimiter, quoting and header of
+/// `format`.
+pub fn write_packager_codes_csv(
+    packager_codes: &[PackagerCode],
+    columns: DataColumns,
+    format: &CsvFormat,
+    compression: Option<Compression>,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = output::output_path(Path::new("data.csv"), compression);
+    let data_file = OutputWriter::create(&path, compression)?;
+    let mut wtr = format.writer_builder().from_writer(data_file);
+    for code in packager_codes {
+        wtr.serialize(DataRow { code, columns })?;
+    }
+
+    wtr.into_inner()?.finish()?;
+    Ok(path)
+}
+
+/// Writes one country's packager codes as `data-<country>.csv` in `dir` using its configured
+/// format.
+pub fn write_country_csv(
+    dir: &Path,
+    country: &str,
+    packager_codes: &[PackagerCode],
+    columns: DataColumns,
+    format: &CsvFormat,
+    compression: Option<Compression>,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let mut wtr = format.writer_builder().from_writer(vec![]);
+    for code in packager_codes {
+        wtr.serialize(DataRow { code, columns })?;
+    }
+    let utf8 = String::from_utf8(wtr.into_inner()?)?;
+
+    let encoding = Encoding::for_label(format.encoding.as_bytes())
+        .ok_or_else(|| format!("unknown encoding {:?}", format.encoding))?;
+    let path = output::output_path(&dir.join(format!("data-{country}.csv")), compression);
+    let mut data_file = OutputWriter::create(&path, compression)?;
+    data_file.write_all(&encode_lossy(&utf8, encoding))?;
+    data_file.finish()?;
+    Ok(path)
+}
+
+/// Entry of the packagers overlay consumed by the OFF map tooling.
+#[derive(Serialize, Debug)]
+struct OffPackager<'a> {
+    code: &'a str,
+    name: &'a str,
+    lat: f64,
+    lng: f64,
+}
+
+/// Writes the packagers overlay used by the OFF maps: a JSON object keyed by the packager code
+/// in OFF tag form (e.g. `fr-12-345-001-ec`). Rows kept for the same code under `keep-both` are
+/// keyed with their disambiguator appended (e.g. `fr-12-345-001-ec-2`), so none of them is lost.
+pub fn write_off_packagers_json(
+    path: &Path,
+    packager_codes: &[PackagerCode],
+    compression: Option<Compression>,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let packagers: BTreeMap<String, OffPackager> = packager_codes
+        .iter()
+        .map(|p| {
+            let packager = OffPackager {
+                code: &p.code,
+                name: &p.name,
+                lat: p.coordinates().lat(),
+                lng: p.coordinates().lng(),
+            };
+            let tag = match p.disambiguator {
+                Some(n) => format!("{}-{n}", off_tag(&p.code)),
+                None => off_tag(&p.code),
+            };
+            (tag, packager)
+        })
+        .collect();
+
+    let path = output::output_path(path, compression);
+    let mut file = OutputWriter::create(&path, compression)?;
+    serde_json::to_writer(&mut file, &packagers)?;
+    file.finish()?;
+    Ok(path)
+}
+
+/// OFF's tag form of a code: lowercase, with runs of punctuation and spaces turned into `-`.
+pub fn off_tag(code: &str) -> String {
+    code.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Encodes `text`, replacing characters the target encoding cannot represent with `?`.
+fn encode_lossy(text: &str, encoding: &'static Encoding) -> Vec<u8> {
+    let mut encoder = encoding.output_encoding().new_encoder();
+    let mut out = Vec::with_capacity(text.len());
+    let mut buf = [0u8; 4096];
+    let mut remaining = text;
+    loop {
+        let (result, read, written) =
+            encoder.encode_from_utf8_without_replacement(remaining, &mut buf, true);
+        out.extend_from_slice(&buf[..written]);
+        remaining = &remaining[read..];
+        match result {
+            EncoderResult::InputEmpty => return out,
+            EncoderResult::OutputFull => {}
+            EncoderResult::Unmappable(_) => out.push(b'?'),
+        }
+    }
+}
@@ -0,0 +1,452 @@
+mod cache;
+mod lau;
+mod offline;
+mod provider;
+
+pub use cache::{CacheTtl, GeocodeCache};
+pub use lau::LauIndex;
+pub use offline::OfflineIndex;
+pub use provider::{Backends, Provider, ProviderMatch, REMOTE_REQUEST_INTERVAL};
+
+use crate::coordinates::Coordinates;
+use crate::countries;
+use crate::street;
+use crate::transliterate::{self, QueryScript};
+use crate::Address;
+use cache::Outcome;
+use clap::ValueEnum;
+use serde::*;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// How much of the establishment's address went into the query that produced a match.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryTier {
+    FullAddress,
+    /// The full address with street type abbreviations spelled out.
+    ExpandedAbbreviations,
+    /// The full address without the house number.
+    WithoutHouseNumber,
+    CityPostcode,
+    City,
+    /// Coordinates taken from the manual overrides file rather than a geocoder.
+    Manual,
+    /// The operator name with the city, tried after the street tiers with `--name-fallback`.
+    OperatorName,
+}
+
+impl QueryTier {
+    #[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueryTier::FullAddress => "full_address",
+            QueryTier::ExpandedAbbreviations => "expanded_abbreviations",
+            QueryTier::WithoutHouseNumber => "without_house_number",
+            QueryTier::CityPostcode => "city_postcode",
+            QueryTier::City => "city",
+            QueryTier::Manual => "manual",
+            QueryTier::OperatorName => "operator_name",
+        }
+    }
+
+    /// Precision assumed for a match of this tier when the provider does not say.
+    pub fn precision(&self) -> LocationPrecision {
+        match self {
+            QueryTier::FullAddress
+            | QueryTier::ExpandedAbbreviations
+            | QueryTier::WithoutHouseNumber => LocationPrecision::Street,
+            QueryTier::CityPostcode => LocationPrecision::Postcode,
+            QueryTier::City => LocationPrecision::City,
+            // Coordinates placed by hand are taken as exact.
+            QueryTier::Manual => LocationPrecision::HouseNumber,
+            QueryTier::OperatorName => LocationPrecision::OperatorName,
+        }
+    }
+}
+
+/// What a geocoded point stands for, from coarsest to finest.
+#[derive(
+    ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationPrecision {
+    /// A region or the whole country: anything coarser than a town.
+    Country,
+    City,
+    /// A place named like the operator in its town, which may be another site of the same
+    /// company.
+    OperatorName,
+    Postcode,
+    Street,
+    HouseNumber,
+}
+
+impl LocationPrecision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LocationPrecision::Country => "country",
+            LocationPrecision::City => "city",
+            LocationPrecision::OperatorName => "operator_name",
+            LocationPrecision::Postcode => "postcode",
+            LocationPrecision::Street => "street",
+            LocationPrecision::HouseNumber => "house_number",
+        }
+    }
+}
+
+/// Counts of geocoding outcomes over a run.
+#[derive(Serialize, Debug, Default)]
+pub struct GeocodeStats {
+    pub attempted: usize,
+    pub geocoded: usize,
+    /// Geocoded points that fell in the sea, when checked.
+    pub in_water: usize,
+    /// Establishments whose address lies outside the approving country.
+    pub foreign_address: usize,
+}
+
+impl GeocodeStats {
+    /// Share of attempts that produced a point, or `None` if nothing was geocoded this run.
+    pub fn success_rate(&self) -> Option<f64> {
+        if self.attempted == 0 {
+            return None;
+        }
+        Some(self.geocoded as f64 / self.attempted as f64)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GeocodeQuery {
+    pub tier: QueryTier,
+    pub query: String,
+    /// Whether the query writes the address as listed or transliterated.
+    pub script: QueryScript,
+    /// Languages the address is likely written in, as an `Accept-Language` style list.
+    pub language: Option<String>,
+}
+
+/// Builds the geocoding queries for an address, from most to least specific.
+///
+/// Tiers lacking their defining component are left out, so an address without a street starts at
+/// city and postcode instead of sending a bare "postcode, country" query. A postal code and city
+/// embedded in the street value are split off, and fill in the components if they are missing.
+///
+/// A full address that is not found is retried in simplified forms, first with abbreviations
+/// spelled out and then without the house number, before the street is dropped.
+pub fn build_queries(address: &Address) -> Vec<GeocodeQuery> {
+    let known_postal_code = non_empty(&address.city_reference.postal_code);
+    let known_city = non_empty(&address.city_reference.name);
+    let split = street::split_street(&address.street.value, known_postal_code, known_city);
+    let street = Some(split.street).filter(|s| !s.is_empty() && *s != ".");
+    let postal_code = known_postal_code.or(split.postal_code.as_deref());
+    let city = known_city.or(split.city.as_deref());
+    let country = Some(address.city_reference.country.code.trim()).filter(|s| !s.is_empty());
+    let language = country.and_then(countries::languages);
+
+    let mut queries: Vec<GeocodeQuery> = vec![];
+    let primary_language = language
+        .as_deref()
+        .and_then(|l| l.split(',').next())
+        .unwrap_or_default()
+        .to_owned();
+    let mut push = |tier: QueryTier, components: &[Option<&str>]| {
+        let query = components
+            .iter()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>()
+            .join(", ");
+        // The other script is tried right after each query, before falling back to a coarser
+        // tier; the country code is left as it is.
+        let variant = components
+            .iter()
+            .flatten()
+            .map(|c| match Some(*c) == country {
+                true => None,
+                false => transliterate::variant(c, &primary_language),
+            })
+            .collect::<Vec<_>>();
+        let script = variant.iter().flatten().map(|(s, _)| *s).next();
+        let mut candidates = vec![(QueryScript::Original, query)];
+        if let Some(script) = script {
+            let transliterated = components
+                .iter()
+                .flatten()
+                .zip(&variant)
+                .map(|(c, v)| v.as_ref().map_or(*c, |(_, text)| text.as_str()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            candidates.push((script, transliterated));
+        }
+        for (script, query) in candidates {
+            if !queries.iter().any(|q| q.query == query) {
+                queries.push(GeocodeQuery {
+                    tier,
+                    query,
+                    script,
+                    language: language.clone(),
+                });
+            }
+        }
+    };
+
+    if let Some(street) = street {
+        push(
+            QueryTier::FullAddress,
+            &[Some(street), postal_code, city, country],
+        );
+        let expanded = street::expand_abbreviations(street, &primary_language);
+        push(
+            QueryTier::ExpandedAbbreviations,
+            &[Some(&expanded), postal_code, city, country],
+        );
+        if let Some(bare) = street::without_house_number(&expanded) {
+            push(
+                QueryTier::WithoutHouseNumber,
+                &[Some(&bare), postal_code, city, country],
+            );
+        }
+    }
+    if city.is_some() && postal_code.is_some() {
+        push(QueryTier::CityPostcode, &[postal_code, city, country]);
+    }
+    if city.is_some() {
+        push(QueryTier::City, &[city, country]);
+    }
+
+    queries
+}
+
+/// Builds the queries for an address like [`build_queries`], with the `operator name, city,
+/// country` query for `operator_name` if given tried once the street tiers are not found but
+/// before settling for the postcode or city, as many food plants are well-known places in
+/// OpenStreetMap.
+pub fn build_queries_with_name(
+    address: &Address,
+    operator_name: Option<&str>,
+) -> Vec<GeocodeQuery> {
+    let mut queries = build_queries(address);
+    if let Some(name_query) = operator_name.and_then(|n| build_name_query(address, n)) {
+        let position = queries
+            .iter()
+            .position(|q| matches!(q.tier, QueryTier::CityPostcode | QueryTier::City))
+            .unwrap_or(queries.len());
+        queries.insert(position, name_query);
+    }
+    queries
+}
+
+/// Builds the `operator name, city, country` query. Needs a city, as a name alone is too
+/// ambiguous.
+fn build_name_query(address: &Address, operator_name: &str) -> Option<GeocodeQuery> {
+    let name = Some(operator_name.trim()).filter(|n| !n.is_empty())?;
+    let known_city = non_empty(&address.city_reference.name);
+    let split = street::split_street(
+        &address.street.value,
+        non_empty(&address.city_reference.postal_code),
+        known_city,
+    );
+    let city = known_city.or(split.city.as_deref())?;
+    let country = Some(address.city_reference.country.code.trim()).filter(|s| !s.is_empty());
+    let query = [Some(name), Some(city), country]
+        .iter()
+        .flatten()
+        .copied()
+        .collect::<Vec<_>>()
+        .join(", ");
+    Some(GeocodeQuery {
+        tier: QueryTier::OperatorName,
+        query,
+        script: QueryScript::Original,
+        language: country.and_then(countries::languages),
+    })
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|s| !s.is_empty())
+}
+
+/// Result of geocoding an establishment's address.
+#[derive(Debug, Clone, Copy)]
+pub enum AddressMatch {
+    Found {
+        coordinates: Coordinates,
+        tier: QueryTier,
+        precision: LocationPrecision,
+        script: QueryScript,
+        /// When the provider resolved the address, which is earlier than now for cached answers.
+        geocoded_at: SystemTime,
+    },
+    /// The address lacks the components needed to build any query.
+    NoQuery,
+    NotFound,
+    /// Providers only returned points outside the plausible area.
+    OutOfBounds,
+}
+
+/// Geocodes addresses through a chain of providers, consulting the cache before each one.
+///
+/// Every provider outcome is cached, so establishments sharing an address only hit the providers
+/// once per run, and cached failures skip straight to the next provider.
+///
+/// The geocoder can be shared between tasks; the cache is locked only around lookups and inserts.
+pub struct Geocoder {
+    providers: Vec<Provider>,
+    backends: Backends,
+    cache: Mutex<GeocodeCache>,
+    name_fallback: bool,
+}
+
+impl Geocoder {
+    pub fn new(providers: Vec<Provider>, backends: Backends, cache: GeocodeCache) -> Geocoder {
+        Geocoder {
+            providers,
+            backends,
+            cache: Mutex::new(cache),
+            name_fallback: false,
+        }
+    }
+
+    /// Retries addresses whose street is not found with the operator name, see
+    /// [`build_queries_with_name`].
+    pub fn with_name_fallback(mut self, enabled: bool) -> Geocoder {
+        self.name_fallback = enabled;
+        self
+    }
+
+    pub fn save_cache(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        self.cache.lock().unwrap().save(path)
+    }
+
+    /// Tries each tier in turn and returns the first match together with the tier that produced
+    /// it. With the name fallback enabled, `operator_name` is tried before the postcode and city
+    /// tiers, see [`build_queries_with_name`].
+    pub async fn geocode_address(
+        &self,
+        address: &Address,
+        operator_name: Option<&str>,
+    ) -> Result<AddressMatch, Box<dyn std::error::Error>> {
+        let queries =
+            build_queries_with_name(address, operator_name.filter(|_| self.name_fallback));
+        if queries.is_empty() {
+            return Ok(AddressMatch::NoQuery);
+        }
+
+        let mut out_of_bounds = false;
+        for q in queries {
+            let (outcome, geocoded_at) = self.geocode(&q).await?;
+            match outcome {
+                Outcome::Found {
+                    lng,
+                    lat,
+                    precision,
+                } => match Coordinates::new(lat, lng) {
+                    Ok(coordinates) => {
+                        // Whatever the provider says, a match on the name is only as good as the
+                        // name.
+                        let precision = match q.tier {
+                            QueryTier::OperatorName => q.tier.precision(),
+                            _ => precision.unwrap_or(q.tier.precision()),
+                        };
+                        return Ok(AddressMatch::Found {
+                            coordinates,
+                            tier: q.tier,
+                            precision,
+                            script: q.script,
+                            geocoded_at,
+                        });
+                    }
+                    Err(_) => out_of_bounds = true,
+                },
+                Outcome::OutOfBounds { .. } => out_of_bounds = true,
+                Outcome::NotFound => {}
+            }
+        }
+
+        Ok(if out_of_bounds {
+            AddressMatch::OutOfBounds
+        } else {
+            AddressMatch::NotFound
+        })
+    }
+
+    /// Returns the first provider's match, or the most informative failure, with when it was
+    /// resolved.
+    async fn geocode(
+        &self,
+        query: &GeocodeQuery,
+    ) -> Result<(Outcome, SystemTime), Box<dyn std::error::Error>> {
+        let key = normalize_query(&query.query);
+        let mut result = (Outcome::NotFound, SystemTime::now());
+        for provider in &self.providers {
+            if !provider.answers(query.tier) {
+                continue;
+            }
+            let cached = self.cache.lock().unwrap().get(&key, *provider);
+            let (outcome, resolved_at) = match cached {
+                Some(cached) => cached,
+                None => {
+                    let outcome = Outcome::from(
+                        self.backends
+                            .forward(*provider, &query.query, query.language.as_deref())
+                            .await?,
+                    );
+                    if provider.is_remote() {
+                        self.cache
+                            .lock()
+                            .unwrap()
+                            .insert(key.clone(), *provider, outcome);
+                    }
+                    (outcome, SystemTime::now())
+                }
+            };
+            match outcome {
+                Outcome::Found { .. } => return Ok((outcome, resolved_at)),
+                Outcome::OutOfBounds { .. } => result = (outcome, resolved_at),
+                Outcome::NotFound => {}
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+/// Case- and whitespace-insensitive form of a query, used as the cache key.
+pub fn normalize_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tries_the_operator_name_before_the_city() {
+        let address = Address::new(
+            "12 Rue des Lilas".to_owned(),
+            Some("29200".to_owned()),
+            Some("Brest".to_owned()),
+            "FR".parse().unwrap(),
+        );
+        let tiers: Vec<QueryTier> = build_queries_with_name(&address, Some("Laiterie de l'Ouest"))
+            .iter()
+            .map(|q| q.tier)
+            .collect();
+        assert_eq!(
+            tiers,
+            [
+                QueryTier::FullAddress,
+                QueryTier::WithoutHouseNumber,
+                QueryTier::OperatorName,
+                QueryTier::CityPostcode,
+                QueryTier::City,
+            ]
+        );
+    }
+}
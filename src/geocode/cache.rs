@@ -0,0 +1,338 @@
+use super::{LocationPrecision, Provider, ProviderMatch};
+use crate::coordinates::Coordinates;
+use crate::output::AtomicFile;
+use serde::*;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What a provider returned for a query.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Found {
+        lng: f64,
+        lat: f64,
+        /// As reported by the provider; missing for providers that do not tell and for outcomes
+        /// cached before precisions were recorded.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        precision: Option<LocationPrecision>,
+    },
+    /// The provider returned a point outside the valid latitude and longitude ranges.
+    OutOfBounds {
+        lng: f64,
+        lat: f64,
+    },
+    NotFound,
+}
+
+impl From<Option<ProviderMatch>> for Outcome {
+    fn from(found: Option<ProviderMatch>) -> Self {
+        match found {
+            Some(ProviderMatch { point, precision }) => match Coordinates::from_point(point) {
+                Ok(c) => Outcome::Found {
+                    lng: c.lng(),
+                    lat: c.lat(),
+                    precision,
+                },
+                Err(_) => Outcome::OutOfBounds {
+                    lng: point.x(),
+                    lat: point.y(),
+                },
+            },
+            None => Outcome::NotFound,
+        }
+    }
+}
+
+/// How long cached outcomes are used before asking the provider again. Failures expire sooner,
+/// so hopeless addresses are not retried on every run but still get another chance as providers
+/// improve.
+#[derive(Debug, Clone, Copy)]
+pub struct CacheTtl {
+    pub found: Duration,
+    pub not_found: Duration,
+}
+
+impl Default for CacheTtl {
+    fn default() -> CacheTtl {
+        CacheTtl {
+            found: Duration::from_secs(365 * DAY),
+            not_found: Duration::from_secs(30 * DAY),
+        }
+    }
+}
+
+const DAY: u64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+struct Entry {
+    outcome: Outcome,
+    /// Unix time the provider was asked.
+    cached_at: u64,
+}
+
+/// An entry as stored, including those of caches written before entries had a time.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredEntry {
+    Entry(Entry),
+    Undated(Outcome),
+}
+
+#[derive(Deserialize)]
+struct StoredCache {
+    entries: HashMap<String, BTreeMap<Provider, StoredEntry>>,
+}
+
+/// Version of the portable cache format written by [`GeocodeCache::export`].
+const PORTABLE_VERSION: u32 = 1;
+
+/// The cache in a form meant for sharing between machines, with entries sorted so that exports
+/// of the same cache are identical and can be committed or diffed.
+#[derive(Serialize, Deserialize)]
+struct PortableCache {
+    version: u32,
+    exported_at: String,
+    entries: Vec<PortableEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PortableEntry {
+    query: String,
+    provider: Provider,
+    outcome: Outcome,
+    cached_at: u64,
+}
+
+/// What [`GeocodeCache::import`] did with the entries it read.
+#[derive(Debug, Default)]
+pub struct ImportCounts {
+    pub added: usize,
+    /// Entries replacing older ones for the same query and provider.
+    pub updated: usize,
+    /// Entries left out as expired or not newer than the cached ones.
+    pub ignored: usize,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Geocoding outcomes per normalized query and provider.
+///
+/// Negative outcomes are kept too, so a rerun can go straight to the provider that answered last
+/// time instead of retrying the ones that did not.
+#[derive(Serialize, Debug, Default)]
+pub struct GeocodeCache {
+    entries: HashMap<String, BTreeMap<Provider, Entry>>,
+    #[serde(skip)]
+    ttl: CacheTtl,
+}
+
+impl GeocodeCache {
+    /// Loads the cache from `path`, starting empty if it does not exist yet. Undated matches are
+    /// taken as cached now, undated failures as expired, as are points wrongly taken as out of
+    /// bounds.
+    pub fn load(path: &Path, ttl: CacheTtl) -> Result<GeocodeCache, Box<dyn std::error::Error>> {
+        if !path.exists() {
+            return Ok(GeocodeCache {
+                ttl,
+                ..GeocodeCache::default()
+            });
+        }
+
+        let reader = BufReader::new(File::open(path)?);
+        let stored: StoredCache = serde_json::from_reader(reader)?;
+        let now = unix_now();
+        let entries = stored
+            .entries
+            .into_iter()
+            .map(|(query, outcomes)| {
+                let outcomes = outcomes
+                    .into_iter()
+                    .map(|(provider, stored)| {
+                        let entry = match stored {
+                            // Points west of Greenwich or south of the equator used to be taken
+                            // as out of bounds, so those are asked for again.
+                            StoredEntry::Entry(Entry {
+                                outcome: outcome @ Outcome::OutOfBounds { lng, lat },
+                                ..
+                            }) if Coordinates::new(lat, lng).is_ok() => Entry {
+                                outcome,
+                                cached_at: 0,
+                            },
+                            StoredEntry::Entry(entry) => entry,
+                            StoredEntry::Undated(outcome @ Outcome::Found { .. }) => Entry {
+                                outcome,
+                                cached_at: now,
+                            },
+                            StoredEntry::Undated(outcome) => Entry {
+                                outcome,
+                                cached_at: 0,
+                            },
+                        };
+                        (provider, entry)
+                    })
+                    .collect();
+                (query, outcomes)
+            })
+            .collect();
+        Ok(GeocodeCache { entries, ttl })
+    }
+
+    /// Writes the cache to `path`, leaving out expired outcomes.
+    pub fn save(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let now = unix_now();
+        let ttl = self.ttl;
+        for outcomes in self.entries.values_mut() {
+            outcomes.retain(|_, entry| !entry.is_expired(&ttl, now));
+        }
+        self.entries.retain(|_, outcomes| !outcomes.is_empty());
+
+        let mut file = AtomicFile::create(path)?;
+        serde_json::to_writer(&mut file, self)?;
+        file.commit()?;
+        Ok(())
+    }
+
+    /// Writes the unexpired entries to `path` in the portable format, returning how many there
+    /// were.
+    pub fn export(&self, path: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+        let now = unix_now();
+        let mut entries: Vec<PortableEntry> = self
+            .entries
+            .iter()
+            .flat_map(|(query, outcomes)| {
+                outcomes
+                    .iter()
+                    .filter(|(_, entry)| !entry.is_expired(&self.ttl, now))
+                    .map(|(provider, entry)| PortableEntry {
+                        query: query.clone(),
+                        provider: *provider,
+                        outcome: entry.outcome,
+                        cached_at: entry.cached_at,
+                    })
+            })
+            .collect();
+        entries.sort_by(|a, b| (&a.query, a.provider).cmp(&(&b.query, b.provider)));
+
+        let portable = PortableCache {
+            version: PORTABLE_VERSION,
+            exported_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            entries,
+        };
+        let mut file = AtomicFile::create(path)?;
+        serde_json::to_writer_pretty(&mut file, &portable)?;
+        file.commit()?;
+        Ok(portable.entries.len())
+    }
+
+    /// Merges a portable export into the cache, keeping the more recent outcome where both have
+    /// one for the same query and provider.
+    pub fn import(&mut self, path: &Path) -> Result<ImportCounts, Box<dyn std::error::Error>> {
+        let reader = BufReader::new(File::open(path)?);
+        let portable: PortableCache = serde_json::from_reader(reader)?;
+        if portable.version != PORTABLE_VERSION {
+            return Err(format!(
+                "{} is version {} of the cache export format, expected {PORTABLE_VERSION}",
+                path.display(),
+                portable.version
+            )
+            .into());
+        }
+
+        let now = unix_now();
+        let mut counts = ImportCounts::default();
+        for imported in portable.entries {
+            let entry = Entry {
+                outcome: imported.outcome,
+                cached_at: imported.cached_at,
+            };
+            if entry.is_expired(&self.ttl, now) {
+                counts.ignored += 1;
+                continue;
+            }
+            let outcomes = self.entries.entry(imported.query).or_default();
+            match outcomes.get(&imported.provider) {
+                Some(cached) if cached.cached_at >= entry.cached_at => counts.ignored += 1,
+                Some(_) => {
+                    outcomes.insert(imported.provider, entry);
+                    counts.updated += 1;
+                }
+                None => {
+                    outcomes.insert(imported.provider, entry);
+                    counts.added += 1;
+                }
+            }
+        }
+        Ok(counts)
+    }
+
+    /// The outcome cached for `query` and when the provider gave it, unless it expired.
+    pub fn get(&self, query: &str, provider: Provider) -> Option<(Outcome, SystemTime)> {
+        let entry = self.entries.get(query)?.get(&provider)?;
+        (!entry.is_expired(&self.ttl, unix_now())).then(|| {
+            (
+                entry.outcome,
+                UNIX_EPOCH + Duration::from_secs(entry.cached_at),
+            )
+        })
+    }
+
+    pub fn insert(&mut self, query: String, provider: Provider, outcome: Outcome) {
+        self.entries.entry(query).or_default().insert(
+            provider,
+            Entry {
+                outcome,
+                cached_at: unix_now(),
+            },
+        );
+    }
+}
+
+impl Entry {
+    fn is_expired(&self, ttl: &CacheTtl, now: u64) -> bool {
+        let ttl = match self.outcome {
+            Outcome::Found { .. } => ttl.found,
+            Outcome::OutOfBounds { .. } | Outcome::NotFound => ttl.not_found,
+        };
+        now.saturating_sub(self.cached_at) >= ttl.as_secs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::Point;
+
+    #[test]
+    fn keeps_points_west_of_greenwich_and_south_of_the_equator() {
+        for (lng, lat) in [(-8.6, 41.1), (18.4, -33.9)] {
+            let found = Outcome::from(Some(ProviderMatch {
+                point: Point::new(lng, lat),
+                precision: None,
+            }));
+            assert_eq!(
+                found,
+                Outcome::Found {
+                    lng,
+                    lat,
+                    precision: None
+                }
+            );
+        }
+        assert!(matches!(
+            Outcome::from(Some(ProviderMatch {
+                point: Point::new(41.1, -98.6),
+                precision: None,
+            })),
+            Outcome::OutOfBounds { .. }
+        ));
+    }
+}
@@ -0,0 +1,56 @@
+use super::normalize_query;
+use crate::coordinates::Coordinates;
+use geo::Point;
+use serde::*;
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Deserialize, Debug)]
+struct CentroidRow {
+    country: String,
+    name: String,
+    lat: f64,
+    lng: f64,
+}
+
+/// Town centroids answering city-level queries without a network round trip.
+///
+/// The table is a CSV with `country,name,lat,lng` columns. Names shared by several units of a
+/// country are ambiguous and never match.
+pub struct LauIndex {
+    points: HashMap<(String, String), Option<Point<f64>>>,
+}
+
+impl LauIndex {
+    /// Reads the table at `path`. A table without centroids is an error, as the geocoder could
+    /// never match.
+    pub fn load(path: &Path) -> Result<LauIndex, Box<dyn std::error::Error>> {
+        let mut points = HashMap::new();
+        for row in csv::Reader::from_path(path)?.deserialize() {
+            let row: CentroidRow = row?;
+            let point = Coordinates::new(row.lat, row.lng)?.point();
+            points
+                .entry(key(&row.country, &row.name))
+                .and_modify(|p| *p = None)
+                .or_insert(Some(point));
+        }
+        if points.is_empty() {
+            return Err(format!("no LAU centroids in {}", path.display()).into());
+        }
+        Ok(LauIndex { points })
+    }
+
+    /// Looks up a city-level query of the form `city, country`.
+    pub fn lookup(&self, query: &str) -> Option<Point<f64>> {
+        let (city, country) = query.rsplit_once(',')?;
+        self.points.get(&key(country, city)).copied().flatten()
+    }
+}
+
+/// Names match regardless of case, spacing and hyphens, as in `Saint-Jean` and `Saint Jean`.
+fn key(country: &str, name: &str) -> (String, String) {
+    (
+        country.trim().to_uppercase(),
+        normalize_query(&name.replace('-', " ")),
+    )
+}
@@ -0,0 +1,39 @@
+use super::normalize_query;
+use crate::coordinates::Coordinates;
+use geo::Point;
+use serde::*;
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Deserialize, Debug)]
+struct IndexRow {
+    query: String,
+    lat: f64,
+    lng: f64,
+}
+
+/// Pre-built address index for fully offline runs.
+///
+/// The index is a CSV with `query,lat,lng` columns, where `query` uses the same
+/// "street, postcode, city, country" shape as the generated geocoding queries, e.g. as exported
+/// from the address nodes of an OSM extract. Queries are matched after normalization.
+pub struct OfflineIndex {
+    points: HashMap<String, Point<f64>>,
+}
+
+impl OfflineIndex {
+    pub fn load(path: &Path) -> Result<OfflineIndex, Box<dyn std::error::Error>> {
+        let mut points = HashMap::new();
+        for row in csv::Reader::from_path(path)?.deserialize() {
+            let row: IndexRow = row?;
+            let point = Coordinates::new(row.lat, row.lng)?.point();
+            points.insert(normalize_query(&row.query), point);
+        }
+
+        Ok(OfflineIndex { points })
+    }
+
+    pub fn lookup(&self, query: &str) -> Option<Point<f64>> {
+        self.points.get(&normalize_query(query)).copied()
+    }
+}
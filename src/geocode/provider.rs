@@ -0,0 +1,261 @@
+use super::{LauIndex, LocationPrecision, OfflineIndex, QueryTier};
+use crate::clock::{Clock, TokioClock};
+use crate::http;
+use crate::rate::{self, RateLimiter};
+use clap::ValueEnum;
+use geo::Point;
+use serde::*;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Geocoding services that can be chained with `--geocoders`.
+#[derive(
+    ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Provider {
+    Nominatim,
+    Photon,
+    /// Lookups in a local address index, see [`OfflineIndex`].
+    Offline,
+    /// Town centroids for city-level queries, see [`LauIndex`].
+    Lau,
+}
+
+impl Provider {
+    /// Whether the provider is a remote service whose outcomes are worth caching.
+    pub fn is_remote(&self) -> bool {
+        !matches!(self, Provider::Offline | Provider::Lau)
+    }
+
+    /// Whether the provider can answer queries of `tier`; town centroids are no answer to a
+    /// street address.
+    pub fn answers(&self, tier: QueryTier) -> bool {
+        !matches!(self, Provider::Lau) || tier == QueryTier::City
+    }
+}
+
+/// Interval between requests to each remote provider, as the public instances' usage policies
+/// ask.
+pub const REMOTE_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A point found by a provider, with what it stands for if the provider tells.
+#[derive(Clone, Copy, Debug)]
+pub struct ProviderMatch {
+    pub point: Point<f64>,
+    pub precision: Option<LocationPrecision>,
+}
+
+impl ProviderMatch {
+    fn imprecise(point: Point<f64>) -> ProviderMatch {
+        ProviderMatch {
+            point,
+            precision: None,
+        }
+    }
+}
+
+/// Endpoints, local data and request pacing used by the providers.
+///
+/// Remote providers are limited to one request per second each, however many tasks share the
+/// backends, as required by the public Nominatim and Photon usage policies.
+pub struct Backends {
+    nominatim_url: String,
+    photon_url: String,
+    offline_index: Option<OfflineIndex>,
+    lau_index: Option<LauIndex>,
+    /// How long a remote provider may take to answer before the request is abandoned.
+    timeout: Duration,
+    nominatim_limiter: Mutex<RateLimiter>,
+    photon_limiter: Mutex<RateLimiter>,
+}
+
+impl Backends {
+    pub fn new(
+        nominatim_url: String,
+        photon_url: String,
+        offline_index: Option<OfflineIndex>,
+        lau_index: Option<LauIndex>,
+        timeout: Duration,
+    ) -> Backends {
+        Backends {
+            nominatim_url,
+            photon_url,
+            offline_index,
+            lau_index,
+            timeout,
+            nominatim_limiter: Mutex::new(RateLimiter::fixed(REMOTE_REQUEST_INTERVAL)),
+            photon_limiter: Mutex::new(RateLimiter::fixed(REMOTE_REQUEST_INTERVAL)),
+        }
+    }
+
+    /// Resolves `query` with `provider`, returning the first match it found.
+    ///
+    /// `language` is a comma-separated preference list passed on to providers that support it.
+    pub async fn forward(
+        &self,
+        provider: Provider,
+        query: &str,
+        language: Option<&str>,
+    ) -> Result<Option<ProviderMatch>, Box<dyn std::error::Error>> {
+        self.forward_timed(provider, query, language)
+            .await
+            .map(|(found, _)| found)
+    }
+
+    /// Like [`forward`](Backends::forward), but also returns how long the provider took to
+    /// answer, not counting the wait imposed by the rate limit.
+    pub async fn forward_timed(
+        &self,
+        provider: Provider,
+        query: &str,
+        language: Option<&str>,
+    ) -> Result<(Option<ProviderMatch>, Duration), Box<dyn std::error::Error>> {
+        match provider {
+            Provider::Nominatim => rate::throttle(&self.nominatim_limiter, &TokioClock).await,
+            Provider::Photon => rate::throttle(&self.photon_limiter, &TokioClock).await,
+            Provider::Offline | Provider::Lau => {}
+        }
+        let started = TokioClock.now();
+        let found = match provider {
+            Provider::Nominatim => {
+                self.with_timeout(
+                    provider,
+                    nominatim_forward(&self.nominatim_url, query, language),
+                )
+                .await?
+            }
+            Provider::Photon => {
+                self.with_timeout(provider, photon_forward(&self.photon_url, query, language))
+                    .await?
+            }
+            Provider::Offline => match &self.offline_index {
+                Some(index) => index.lookup(query).map(ProviderMatch::imprecise),
+                None => return Err("the offline geocoder needs --geocoder-index".into()),
+            },
+            Provider::Lau => match &self.lau_index {
+                Some(index) => index.lookup(query).map(|point| ProviderMatch {
+                    point,
+                    precision: Some(LocationPrecision::City),
+                }),
+                None => return Err("the lau geocoder needs --lau-centroids".into()),
+            },
+        };
+        Ok((found, TokioClock.now() - started))
+    }
+
+    /// Runs a remote lookup, cancelling it if the provider does not answer in time.
+    async fn with_timeout(
+        &self,
+        provider: Provider,
+        lookup: impl Future<Output = Result<Option<ProviderMatch>, Box<dyn std::error::Error>>>,
+    ) -> Result<Option<ProviderMatch>, Box<dyn std::error::Error>> {
+        match tokio::time::timeout(self.timeout, lookup).await {
+            Ok(result) => result,
+            Err(_) => Err(format!(
+                "{provider:?} did not answer within {}s",
+                self.timeout.as_secs_f64()
+            )
+            .into()),
+        }
+    }
+}
+
+/// Languages Photon can return results in, besides each place's local name.
+const PHOTON_LANGUAGES: &[&str] = &["de", "en", "fr"];
+
+async fn nominatim_forward(
+    endpoint: &str,
+    query: &str,
+    language: Option<&str>,
+) -> Result<Option<ProviderMatch>, Box<dyn std::error::Error>> {
+    let mut url = url::Url::parse_with_params(
+        &format!("{endpoint}search"),
+        &[("q", query), ("format", "geojson"), ("limit", "1")],
+    )?;
+    if let Some(language) = language {
+        url.query_pairs_mut()
+            .append_pair("accept-language", language);
+    }
+    let resp: GeoJsonResponse = http::get_json(url).await?;
+    Ok(resp.first_point())
+}
+
+/// The subset of a GeoJSON feature collection of points that Nominatim and Photon return.
+#[derive(Deserialize, Debug)]
+struct GeoJsonResponse {
+    features: Vec<GeoJsonFeature>,
+}
+
+impl GeoJsonResponse {
+    fn first_point(&self) -> Option<ProviderMatch> {
+        self.features.first().map(|f| ProviderMatch {
+            point: Point::new(f.geometry.coordinates.0, f.geometry.coordinates.1),
+            precision: f.properties.precision(),
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct GeoJsonFeature {
+    geometry: GeoJsonGeometry,
+    #[serde(default)]
+    properties: GeoJsonProperties,
+}
+
+/// Feature properties telling what kind of place matched: Nominatim reports the OSM type and
+/// address rank, Photon its own place type and any house number.
+#[derive(Deserialize, Debug, Default)]
+struct GeoJsonProperties {
+    place_rank: Option<u32>,
+    #[serde(rename = "type")]
+    place_type: Option<String>,
+    housenumber: Option<String>,
+}
+
+impl GeoJsonProperties {
+    fn precision(&self) -> Option<LocationPrecision> {
+        let place_type = self.place_type.as_deref();
+        if place_type == Some("postcode") {
+            return Some(LocationPrecision::Postcode);
+        }
+        if let Some(rank) = self.place_rank {
+            // Nominatim address ranks: 26-27 streets, 28 and above house numbers and buildings.
+            return Some(match rank {
+                28.. => LocationPrecision::HouseNumber,
+                26..=27 => LocationPrecision::Street,
+                13..=25 => LocationPrecision::City,
+                _ => LocationPrecision::Country,
+            });
+        }
+        match place_type {
+            Some("house") => Some(LocationPrecision::HouseNumber),
+            _ if self.housenumber.is_some() => Some(LocationPrecision::HouseNumber),
+            Some("street") => Some(LocationPrecision::Street),
+            Some("city" | "district" | "locality") => Some(LocationPrecision::City),
+            Some("county" | "state" | "country") => Some(LocationPrecision::Country),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct GeoJsonGeometry {
+    /// Longitude, latitude.
+    coordinates: (f64, f64),
+}
+
+async fn photon_forward(
+    endpoint: &str,
+    query: &str,
+    language: Option<&str>,
+) -> Result<Option<ProviderMatch>, Box<dyn std::error::Error>> {
+    let mut url = url::Url::parse_with_params(endpoint, &[("q", query), ("limit", "1")])?;
+    let preferred = language.and_then(|l| l.split(',').next());
+    if let Some(lang) = preferred.filter(|l| PHOTON_LANGUAGES.contains(l)) {
+        url.query_pairs_mut().append_pair("lang", lang);
+    }
+    let resp: GeoJsonResponse = http::get_json(url).await?;
+    Ok(resp.first_point())
+}
@@ -0,0 +1,101 @@
+use crate::cli::Args;
+use crate::coordinates::Coordinates;
+use crate::geocode::{AddressMatch, GeocodeCache, LocationPrecision, QueryTier};
+use crate::ids::CountryCode;
+use crate::output::AtomicFile;
+use crate::transliterate::QueryScript;
+use crate::{build_geocoder, cache_ttl, Address};
+use serde::*;
+use std::path::Path;
+
+#[derive(Deserialize, Debug)]
+struct AddressRow {
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    street: String,
+    postal_code: Option<String>,
+    city: Option<String>,
+    country: CountryCode,
+}
+
+#[derive(Serialize, Debug)]
+struct PointRow {
+    id: String,
+    street: String,
+    postal_code: Option<String>,
+    city: Option<String>,
+    country: CountryCode,
+    status: &'static str,
+    lat: Option<f64>,
+    lng: Option<f64>,
+    geocode_tier: Option<QueryTier>,
+    location_precision: Option<LocationPrecision>,
+    query_script: Option<QueryScript>,
+}
+
+/// Runs only the geocoding stage over an address CSV, for fixing individual packager codes.
+pub async fn geocode_file(
+    args: &Args,
+    input: &Path,
+    output: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let cache = match &args.geocode_cache {
+        Some(path) => GeocodeCache::load(path, cache_ttl(args))?,
+        None => GeocodeCache::default(),
+    };
+    let geocoder = build_geocoder(args, cache)?;
+
+    let round = |c: Coordinates| match args.coordinate_precision {
+        Some(decimals) => c.round(decimals),
+        None => c,
+    };
+
+    let mut rdr = csv::Reader::from_path(input)?;
+    let mut wtr = csv::Writer::from_writer(AtomicFile::create(output)?);
+    for row in rdr.deserialize() {
+        let row: AddressRow = row?;
+        let address = Address::new(
+            row.street.clone(),
+            row.postal_code.clone(),
+            row.city.clone(),
+            row.country.clone(),
+        );
+
+        let (status, found) = match geocoder.geocode_address(&address, None).await {
+            Ok(AddressMatch::Found {
+                coordinates,
+                tier,
+                precision,
+                script,
+                ..
+            }) => ("found", Some((round(coordinates), tier, precision, script))),
+            Ok(AddressMatch::NoQuery) => ("no_query", None),
+            Ok(AddressMatch::NotFound) => ("not_found", None),
+            Ok(AddressMatch::OutOfBounds) => ("out_of_bounds", None),
+            Err(e) => {
+                eprintln!("{}: {e}", row.id);
+                ("error", None)
+            }
+        };
+        wtr.serialize(PointRow {
+            id: row.id,
+            street: row.street,
+            postal_code: row.postal_code,
+            city: row.city,
+            country: row.country,
+            status,
+            lat: found.map(|(c, ..)| c.lat()),
+            lng: found.map(|(c, ..)| c.lng()),
+            geocode_tier: found.map(|(_, tier, ..)| tier),
+            location_precision: found.map(|(_, _, precision, _)| precision),
+            query_script: found.map(|(.., script)| script),
+        })?;
+    }
+    wtr.into_inner()?.commit()?;
+
+    if let Some(path) = &args.geocode_cache {
+        geocoder.save_cache(path)?;
+    }
+    Ok(())
+}
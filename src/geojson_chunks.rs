@@ -0,0 +1,121 @@
+use crate::manifest::RunManifest;
+use crate::output::{self, Compression, OutputWriter};
+use crate::PackagerCode;
+use clap::ValueEnum;
+use geojson::{Feature, FeatureCollection, Geometry, JsonObject, Value};
+use serde::*;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Size of a grid cell in degrees when chunking by grid.
+const GRID_CELL_DEGREES: f64 = 5.0;
+
+/// How GeoJSON output is split into files.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Chunking {
+    /// One file per country.
+    #[default]
+    Country,
+    /// One file per 5°×5° grid cell, named after its south-west corner.
+    Grid,
+}
+
+/// Entry of `index.json`, listing the chunk files for lazy loading.
+#[derive(Serialize, Debug)]
+struct ChunkIndexEntry {
+    key: String,
+    file: String,
+    features: usize,
+    /// `[min_lng, min_lat, max_lng, max_lat]`
+    bbox: [f64; 4],
+}
+
+fn chunk_key(chunking: Chunking, country: &str, p: &PackagerCode) -> String {
+    match chunking {
+        Chunking::Country => country.to_owned(),
+        Chunking::Grid => {
+            let cell = |v: f64| (v / GRID_CELL_DEGREES).floor() * GRID_CELL_DEGREES;
+            let c = p.coordinates();
+            format!("{}_{}", cell(c.lat()), cell(c.lng()))
+        }
+    }
+}
+
+/// GeoJSON point of a packager code, with its code, operator name and section as properties.
+pub fn feature(p: &PackagerCode) -> Feature {
+    let mut properties = JsonObject::new();
+    properties.insert("code".to_owned(), p.code.clone().into());
+    properties.insert("name".to_owned(), p.name.clone().into());
+    properties.insert("section".to_owned(), p.section.to_string().into());
+    Feature {
+        geometry: Some(Geometry::new(Value::Point(
+            p.coordinates().geojson_position(),
+        ))),
+        properties: Some(properties),
+        ..Default::default()
+    }
+}
+
+/// Writes the packager codes as GeoJSON files under `dir`, one per chunk, plus an `index.json`
+/// describing each file's key, feature count and bounding box. Returns the paths of all files
+/// written.
+pub fn write_geojson_chunks(
+    dir: &Path,
+    manifest: &RunManifest,
+    chunking: Chunking,
+    compression: Option<Compression>,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut chunks: BTreeMap<String, Vec<&PackagerCode>> = BTreeMap::new();
+    for (country, c) in &manifest.countries {
+        for p in &c.packager_codes {
+            chunks
+                .entry(chunk_key(chunking, country, p))
+                .or_default()
+                .push(p);
+        }
+    }
+
+    std::fs::create_dir_all(dir)?;
+    let mut index = vec![];
+    let mut files = vec![];
+    for (key, packager_codes) in chunks {
+        let path = output::output_path(&dir.join(format!("{key}.geojson")), compression);
+        let collection = FeatureCollection {
+            bbox: None,
+            features: packager_codes.iter().map(|p| feature(p)).collect(),
+            foreign_members: None,
+        };
+        let mut file = OutputWriter::create(&path, compression)?;
+        file.write_all(collection.to_string().as_bytes())?;
+        file.finish()?;
+
+        let bbox = packager_codes.iter().fold(
+            [f64::MAX, f64::MAX, f64::MIN, f64::MIN],
+            |[min_lng, min_lat, max_lng, max_lat], p| {
+                let c = p.coordinates();
+                [
+                    min_lng.min(c.lng()),
+                    min_lat.min(c.lat()),
+                    max_lng.max(c.lng()),
+                    max_lat.max(c.lat()),
+                ]
+            },
+        );
+        index.push(ChunkIndexEntry {
+            key,
+            file: path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            features: packager_codes.len(),
+            bbox,
+        });
+        files.push(path);
+    }
+
+    let index_path = dir.join("index.json");
+    output::write_atomically(&index_path, serde_json::to_string_pretty(&index)?)?;
+    files.push(index_path);
+    Ok(files)
+}
@@ -0,0 +1,139 @@
+#[cfg(feature = "sqlite")]
+use rusqlite::{params, Connection};
+use serde::*;
+use std::path::Path;
+use std::time::SystemTime;
+
+#[cfg(feature = "sqlite")]
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started_at TEXT NOT NULL,
+    duration_secs REAL NOT NULL,
+    outcome TEXT NOT NULL,
+    failure TEXT,
+    countries INTEGER,
+    packager_codes INTEGER,
+    skipped INTEGER,
+    errors INTEGER,
+    geocode_attempted INTEGER,
+    geocoded INTEGER
+);
+";
+
+/// Totals of a completed crawl, recorded in the run history.
+#[derive(Serialize, Debug, Default)]
+pub struct RunCounts {
+    pub countries: usize,
+    pub packager_codes: usize,
+    pub skipped: usize,
+    pub errors: usize,
+    pub geocode_attempted: usize,
+    pub geocoded: usize,
+}
+
+#[cfg(feature = "sqlite")]
+fn open(path: &Path) -> Result<Connection, Box<dyn std::error::Error>> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch(SCHEMA)?;
+    Ok(conn)
+}
+
+/// Appends a run started at `started` to the history database, creating it if needed.
+#[cfg(feature = "sqlite")]
+pub fn record(
+    path: &Path,
+    started: SystemTime,
+    result: &Result<RunCounts, Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let duration = started.elapsed().unwrap_or_default().as_secs_f64();
+    let started_at = humantime::format_rfc3339_seconds(started).to_string();
+    let (outcome, failure, counts) = match result {
+        Ok(counts) => ("success", None, Some(counts)),
+        Err(e) => ("failure", Some(e.to_string()), None),
+    };
+
+    open(path)?.execute(
+        "INSERT INTO runs
+            (started_at, duration_secs, outcome, failure, countries, packager_codes, skipped,
+             errors, geocode_attempted, geocoded)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            started_at,
+            duration,
+            outcome,
+            failure,
+            counts.map(|c| c.countries),
+            counts.map(|c| c.packager_codes),
+            counts.map(|c| c.skipped),
+            counts.map(|c| c.errors),
+            counts.map(|c| c.geocode_attempted),
+            counts.map(|c| c.geocoded),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Prints the `limit` most recent runs, newest first.
+#[cfg(feature = "sqlite")]
+pub fn print(path: &Path, limit: usize) -> Result<(), Box<dyn std::error::Error>> {
+    if !path.exists() {
+        return Err(format!("no run history at {}", path.display()).into());
+    }
+
+    let conn = open(path)?;
+    let mut statement = conn.prepare(
+        "SELECT started_at, duration_secs, outcome, failure, countries, packager_codes, skipped,
+                errors, geocode_attempted, geocoded
+         FROM runs ORDER BY id DESC LIMIT ?1",
+    )?;
+    let mut rows = statement.query(params![limit])?;
+    println!(
+        "{:<20} {:>9} {:<8} {:>9} {:>8} {:>8} {:>7} {:>8}",
+        "started", "duration", "outcome", "countries", "codes", "skipped", "errors", "geocoded"
+    );
+    while let Some(row) = rows.next()? {
+        let count = |i: usize| -> rusqlite::Result<String> {
+            Ok(row
+                .get::<_, Option<i64>>(i)?
+                .map(|n| n.to_string())
+                .unwrap_or_else(|| "-".to_owned()))
+        };
+        let attempted: Option<i64> = row.get(8)?;
+        let geocoded: Option<i64> = row.get(9)?;
+        let rate = match (geocoded, attempted) {
+            (Some(g), Some(a)) if a > 0 => format!("{:.1}%", g as f64 * 100.0 / a as f64),
+            _ => "-".to_owned(),
+        };
+        println!(
+            "{:<20} {:>8.0}s {:<8} {:>9} {:>8} {:>8} {:>7} {:>8}",
+            row.get::<_, String>(0)?,
+            row.get::<_, f64>(1)?,
+            row.get::<_, String>(2)?,
+            count(4)?,
+            count(5)?,
+            count(6)?,
+            count(7)?,
+            rate,
+        );
+        if let Some(failure) = row.get::<_, Option<String>>(3)? {
+            println!("    {failure}");
+        }
+    }
+    Ok(())
+}
+
+/// Without the `sqlite` feature there is no history database to record runs in.
+#[cfg(not(feature = "sqlite"))]
+pub fn record(
+    _path: &Path,
+    _started: SystemTime,
+    _result: &Result<RunCounts, Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Err("--history-db needs a build with the `sqlite` feature".into())
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn print(_path: &Path, _limit: usize) -> Result<(), Box<dyn std::error::Error>> {
+    Err("the run history needs a build with the `sqlite` feature".into())
+}
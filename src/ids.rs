@@ -0,0 +1,128 @@
+use serde::*;
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+/// TRACES identifier of an establishment operator.
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord,
+)]
+#[serde(transparent)]
+pub struct OperatorId(i32);
+
+impl OperatorId {
+    /// Placeholder for establishments that did not come from TRACES, such as XLS imports.
+    pub const UNKNOWN: OperatorId = OperatorId(0);
+
+    pub fn get(self) -> i32 {
+        self.0
+    }
+
+    pub fn is_unknown(self) -> bool {
+        self == OperatorId::UNKNOWN
+    }
+}
+
+impl FromStr for OperatorId {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().parse::<i32>() {
+            Ok(id) if id > 0 => Ok(OperatorId(id)),
+            _ => Err(format!("{value:?} is not a TRACES operator ID")),
+        }
+    }
+}
+
+impl fmt::Display for OperatorId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Defines a string newtype that derefs to `str` and validates values given by users through
+/// `FromStr`. Values read from TRACES are taken as they are.
+macro_rules! string_id {
+    ($(#[$meta:meta])* $name:ident, $validate:expr) => {
+        $(#[$meta])*
+        #[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        #[serde(transparent)]
+        pub struct $name(String);
+
+        impl $name {
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = String;
+
+            fn from_str(value: &str) -> Result<Self, Self::Err> {
+                let validate: fn(&str) -> Result<String, String> = $validate;
+                validate(value.trim()).map($name)
+            }
+        }
+
+        impl Deref for $name {
+            type Target = str;
+
+            fn deref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(value: $name) -> String {
+                value.0
+            }
+        }
+    };
+}
+
+string_id!(
+    /// Country code as used by TRACES: ISO 3166-1 alpha-2, except `EL` for Greece and `XI` for
+    /// Northern Ireland.
+    CountryCode,
+    |value| {
+        if value.len() == 2 && value.chars().all(|c| c.is_ascii_alphabetic()) {
+            Ok(value.to_uppercase())
+        } else {
+            Err(format!("{value:?} is not a two-letter country code"))
+        }
+    }
+);
+
+string_id!(
+    /// Code of a TRACES classification section, such as `I` or `0`.
+    SectionCode,
+    |value| {
+        if value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            Ok(value.to_owned())
+        } else {
+            Err(format!("{value:?} is not a section code"))
+        }
+    }
+);
+
+string_id!(
+    /// Approval number as listed by the competent authority, before formatting into a packager
+    /// code.
+    ApprovalNumber,
+    |value| {
+        if value.is_empty() {
+            Err("approval number is empty".to_owned())
+        } else {
+            Ok(value.to_owned())
+        }
+    }
+);
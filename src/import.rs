@@ -0,0 +1,195 @@
+use crate::ids::{CountryCode, OperatorId, SectionCode};
+use crate::legacy_encoding;
+use crate::{Address, Establishment};
+use encoding_rs::Encoding;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Delimiters national CSV lists are seen with, in order of preference.
+const CSV_DELIMITERS: &[u8] = b";,\t";
+
+/// An XLS/XLSX or CSV establishment list published outside TRACES, given as
+/// `COUNTRY[:SECTION]=PATH`.
+#[derive(Debug, Clone)]
+pub struct XlsImport {
+    pub country: CountryCode,
+    pub section: SectionCode,
+    pub path: PathBuf,
+}
+
+impl FromStr for XlsImport {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (source, path) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected COUNTRY[:SECTION]=PATH, got {value:?}"))?;
+        let (country, section) = source.split_once(':').unwrap_or((source, ""));
+        if path.is_empty() {
+            return Err(format!("expected COUNTRY[:SECTION]=PATH, got {value:?}"));
+        }
+
+        Ok(XlsImport {
+            country: country.parse()?,
+            section: section.parse()?,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+/// Column positions, found by matching the header row of the DG SANTE lists.
+#[derive(Debug, Default)]
+struct Columns {
+    approval_number: usize,
+    name: Option<usize>,
+    street: Option<usize>,
+    postal_code: Option<usize>,
+    city: Option<usize>,
+}
+
+impl Columns {
+    fn from_header(row: &[String]) -> Option<Columns> {
+        let headers: Vec<String> = row.iter().map(|c| c.to_lowercase()).collect();
+        let find = |needles: &[&str]| {
+            headers
+                .iter()
+                .position(|h| needles.iter().any(|n| h.contains(n)))
+        };
+
+        Some(Columns {
+            approval_number: find(&["approval"])?,
+            name: find(&["name"]),
+            street: find(&["address", "street"]),
+            postal_code: find(&["postal", "postcode", "zip"]),
+            city: find(&["town", "city"]),
+        })
+    }
+}
+
+/// Reads the establishments of the first worksheet, or of a CSV file, so they can go through the
+/// same geocoding and export as the TRACES ones. CSV files are decoded with `forced` if given and
+/// otherwise detected, see [`legacy_encoding::decode`].
+pub fn read_imported_establishments(
+    import: &XlsImport,
+    forced: Option<&'static Encoding>,
+) -> Result<Vec<Establishment>, Box<dyn std::error::Error>> {
+    let source = import.path.display().to_string();
+    let is_csv = import
+        .path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("csv"));
+    if is_csv {
+        let bytes = std::fs::read(&import.path)?;
+        let (text, encoding) = legacy_encoding::decode(&bytes, forced);
+        if forced.is_none() && encoding != encoding_rs::UTF_8 {
+            eprintln!("Reading {source} as {}", encoding.name());
+        }
+        let rows = read_csv_rows(&text)?;
+        return read_establishments(rows, &import.country, &import.section, &source);
+    }
+
+    let rows = read_workbook_rows(&import.path)?;
+    read_establishments(rows, &import.country, &import.section, &source)
+}
+
+#[cfg(feature = "xls")]
+fn read_workbook_rows(path: &Path) -> Result<Vec<Vec<String>>, Box<dyn std::error::Error>> {
+    let workbook = calamine::open_workbook_auto(path)?;
+    first_worksheet_rows(workbook, &path.display().to_string())
+}
+
+#[cfg(not(feature = "xls"))]
+fn read_workbook_rows(path: &Path) -> Result<Vec<Vec<String>>, Box<dyn std::error::Error>> {
+    Err(format!(
+        "reading {} needs a build with the `xls` feature; CSV lists can be read without",
+        path.display()
+    )
+    .into())
+}
+
+/// Reads the cells of the first worksheet of `workbook` as text; `source` names it in errors.
+#[cfg(feature = "xls")]
+pub fn first_worksheet_rows<RS: std::io::Read + std::io::Seek>(
+    mut workbook: calamine::Sheets<RS>,
+    source: &str,
+) -> Result<Vec<Vec<String>>, Box<dyn std::error::Error>> {
+    use calamine::Reader;
+
+    let range = workbook
+        .worksheet_range_at(0)
+        .ok_or_else(|| format!("{source} has no worksheets"))??;
+    Ok(range
+        .rows()
+        .map(|row| row.iter().map(|c| c.to_string()).collect())
+        .collect())
+}
+
+/// Reads CSV text into rows of cells, guessing the delimiter from the first line.
+fn read_csv_rows(text: &str) -> Result<Vec<Vec<String>>, Box<dyn std::error::Error>> {
+    let first_line = text.lines().next().unwrap_or_default();
+    let delimiter = CSV_DELIMITERS
+        .iter()
+        .rev()
+        .copied()
+        .max_by_key(|d| first_line.bytes().filter(|b| b == d).count())
+        .unwrap_or(b',');
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let mut rows = vec![];
+    for record in reader.records() {
+        rows.push(record?.iter().map(str::to_owned).collect());
+    }
+    Ok(rows)
+}
+
+/// Reads the establishments below the header row of a worksheet or CSV file; `source` names the
+/// list in errors. Text that was mangled into mojibake by a legacy conversion is repaired.
+pub fn read_establishments(
+    rows: Vec<Vec<String>>,
+    country: &CountryCode,
+    section: &SectionCode,
+    source: &str,
+) -> Result<Vec<Establishment>, Box<dyn std::error::Error>> {
+    let mut rows = rows.iter();
+    let columns = rows
+        .by_ref()
+        .find_map(|row| Columns::from_header(row))
+        .ok_or_else(|| format!("no approval number column in {source}"))?;
+
+    let cell = |row: &[String], column: Option<usize>| {
+        column
+            .and_then(|c| row.get(c))
+            .map(|c| legacy_encoding::repair_mojibake(c.trim()).into_owned())
+            .filter(|s| !s.is_empty())
+    };
+
+    let mut establishments = vec![];
+    for row in rows {
+        let Some(approval_number) =
+            cell(row, Some(columns.approval_number)).and_then(|a| a.parse().ok())
+        else {
+            continue;
+        };
+
+        establishments.push(Establishment {
+            operator_id: OperatorId::UNKNOWN,
+            operator_name: cell(row, columns.name),
+            address: Address::new(
+                cell(row, columns.street).unwrap_or_default(),
+                cell(row, columns.postal_code),
+                cell(row, columns.city),
+                country.clone(),
+            ),
+            approval_number: Some(approval_number),
+            section: section.clone(),
+            third_country: false,
+            approving_country: Some(country.clone()),
+        });
+    }
+
+    Ok(establishments)
+}
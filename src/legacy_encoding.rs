@@ -0,0 +1,66 @@
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use std::borrow::Cow;
+
+/// Parses a WHATWG encoding label such as `windows-1252` or `latin1` for `--force-encoding`.
+pub fn parse_label(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.as_bytes()).ok_or_else(|| format!("unknown encoding {label:?}"))
+}
+
+/// Decodes a national list file: with `forced` if given, otherwise by its byte order mark, as
+/// UTF-8 if it is valid UTF-8, and as Windows-1252 as national authorities' older exports mostly
+/// are. Returns the text and the encoding it was read as.
+pub fn decode<'a>(
+    bytes: &'a [u8],
+    forced: Option<&'static Encoding>,
+) -> (Cow<'a, str>, &'static Encoding) {
+    let encoding = forced
+        .or_else(|| Encoding::for_bom(bytes).map(|(encoding, _)| encoding))
+        .unwrap_or_else(|| {
+            if std::str::from_utf8(bytes).is_ok() {
+                UTF_8
+            } else {
+                WINDOWS_1252
+            }
+        });
+    let (text, encoding, _) = encoding.decode(bytes);
+    (text, encoding)
+}
+
+/// Repairs UTF-8 text that was read as Windows-1252 somewhere along the way, such as `CafÃ©`
+/// for `Café`, which spreadsheets converted from legacy exports often contain. Text that does
+/// not round-trip is returned as is.
+pub fn repair_mojibake(text: &str) -> Cow<'_, str> {
+    if text.is_ascii() {
+        return Cow::Borrowed(text);
+    }
+    let (bytes, _, unmappable) = WINDOWS_1252.encode(text);
+    if unmappable {
+        return Cow::Borrowed(text);
+    }
+    match std::str::from_utf8(&bytes) {
+        Ok(repaired) if repaired != text => Cow::Owned(repaired.to_owned()),
+        _ => Cow::Borrowed(text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_legacy_encodings() {
+        let (text, encoding) = decode(b"Boucherie du Ch\xe2teau", None);
+        assert_eq!(text, "Boucherie du Château");
+        assert_eq!(encoding, WINDOWS_1252);
+        let (text, encoding) = decode("Château".as_bytes(), None);
+        assert_eq!(text, "Château");
+        assert_eq!(encoding, UTF_8);
+    }
+
+    #[test]
+    fn repairs_mojibake_only() {
+        assert_eq!(repair_mojibake("CafÃ© de la Gare"), "Café de la Gare");
+        assert_eq!(repair_mojibake("Café de la Gare"), "Café de la Gare");
+        assert_eq!(repair_mojibake("Fleischerei Müller"), "Fleischerei Müller");
+    }
+}
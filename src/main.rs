@@ -1,53 +1,1520 @@
-use futures::StreamExt;
-use geocoding::{Forward, Openstreetmap, Point};
+mod bench_geocode;
+mod cli;
+mod clock;
+mod code;
+mod config;
+mod coordinates;
+mod countries;
+mod coverage;
+mod diff;
+mod duplicates;
+mod emit_queries;
+mod esa;
+mod estimate;
+mod export;
+mod geocode;
+mod geocode_file;
+mod geojson_chunks;
+mod history;
+mod http;
+mod ids;
+mod import;
+mod legacy_encoding;
+mod manifest;
+mod metadata;
+mod mobile_bundles;
+mod names;
+mod northern_ireland;
+mod nuts;
+mod output;
+mod overpass;
+mod overrides;
+#[cfg(feature = "postgres")]
+mod postgres;
+mod quality;
+mod rate;
+mod raw;
+mod release;
+mod report;
+#[cfg(feature = "s3")]
+mod s3;
+mod sections;
+#[cfg(feature = "serve")]
+mod serve;
+mod shutdown;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod stats;
+mod street;
+mod timing;
+mod traces;
+mod transliterate;
+mod water;
+mod webhook;
+
+use clap::{CommandFactory, Parser};
+use cli::{Args, CacheCommand, Command};
+use config::{Config, CsvFormat, HeaderStyle};
+use coordinates::Coordinates;
+use countries::CountrySource;
+use duplicates::DuplicatePolicy;
+use estimate::Declined;
+use export::DataColumns;
+use futures::{StreamExt, TryStreamExt};
+use geocode::{
+    AddressMatch, Backends, CacheTtl, GeocodeCache, GeocodeStats, Geocoder, LauIndex,
+    LocationPrecision, OfflineIndex, Provider, QueryTier,
+};
+use history::RunCounts;
+use ids::{ApprovalNumber, CountryCode, OperatorId, SectionCode};
+use manifest::{CountryManifest, RunManifest};
+use names::{MissingNamePolicy, NameCleanup};
+use nuts::NutsIndex;
+use output::OutputWriter;
+use overrides::Overrides;
+use quality::QualityFailure;
+use sections::SectionMap;
+use serde::de::{DeserializeOwned, IgnoredAny};
 use serde::*;
-use std::fs::File;
-use std::io::prelude::*;
-use std::{collections::HashMap, thread, time};
-use tokio::*;
-use urlencoding::encode;
+use sha2::{Digest, Sha256};
+use stats::StageCounts;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use timing::StageTimings;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use traces::client::TracesClient;
+use traces::urls::{Listing, PageRange};
+use traces::{ListingPage, RequestKind};
+use transliterate::QueryScript;
+use water::LandMask;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let countries_categories = fetch_valid_categories_by_countries().await?;
-    let establishments_by_country = map_establishments_to_countries(countries_categories).await?;
-    let packager_codes = geocode_all_countries(establishments_by_country).await?;
-    write_packager_codes_csv(packager_codes)?;
-    return Ok(());
+    let args = cli::Args::parse();
+    if let Err(message) = args.validate() {
+        cli::Args::command()
+            .error(clap::error::ErrorKind::ArgumentConflict, message)
+            .exit();
+    }
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    if let Some(path) = &args.audit_log {
+        http::configure_audit_log(path)?;
+    }
+    code::rules::configure(args.rules_file.as_deref())?;
+    esa::register_formatters();
+    northern_ireland::register_formatters();
+    traces::configure_rate_limit(args.adaptive_rate);
+    traces::configure_timeouts(
+        Duration::from_secs(args.listing_timeout.into()),
+        Duration::from_secs(args.detail_timeout.into()),
+    );
+    raw::configure(
+        args.save_raw.clone(),
+        args.from_raw.clone(),
+        args.resume_raw,
+    );
+    traces::configure_base_url(
+        args.traces_base_url
+            .clone()
+            .or_else(|| config.traces.base_url.clone()),
+    );
+    match &args.command {
+        Some(Command::FetchOne {
+            country,
+            section,
+            operator_id,
+        }) => fetch_one(&args, country, section, *operator_id).await,
+        Some(Command::BenchGeocode { truth, sample }) => {
+            bench_geocode::bench_geocode(&args, truth, *sample).await
+        }
+        Some(Command::Geocode { input, output }) => {
+            geocode_file::geocode_file(&args, input, output).await
+        }
+        Some(Command::DumpEstablishments { output }) => dump_establishments(&args, output).await,
+        #[cfg(feature = "serve")]
+        Some(Command::Serve { listen }) => serve::serve(&args, *listen).await,
+        #[cfg(not(feature = "serve"))]
+        Some(Command::Serve { .. }) => Err("serve needs a build with the `serve` feature".into()),
+        Some(Command::History { limit }) => match &args.history_db {
+            Some(path) => history::print(path, *limit),
+            None => Err("the history subcommand needs --history-db".into()),
+        },
+        Some(Command::Coverage { output, format }) => {
+            coverage::write_coverage(&args.manifest, output, *format)
+        }
+        Some(Command::Sections { country, refresh }) => {
+            sections::print_sections(&args, country, *refresh).await
+        }
+        Some(Command::Cache { action }) => transfer_cache(&args, action),
+        None if args.offset_range.is_some() => backfill_raw(&args).await,
+        None => {
+            let started = SystemTime::now();
+            let shutdown = shutdown::install();
+            let result = crawl(&args, &config, &shutdown).await;
+            if let Some(declined) = result
+                .as_ref()
+                .err()
+                .and_then(|e| e.downcast_ref::<Declined>())
+            {
+                eprintln!("Cancelled");
+                std::process::exit(declined.exit_code());
+            }
+            if let Some(path) = &args.history_db {
+                if let Err(e) = history::record(path, started, &result) {
+                    eprintln!("Could not record the run history: {e}");
+                }
+            }
+            webhook::notify(&args, started, &result).await;
+            match result {
+                Err(e) => match e.downcast_ref::<QualityFailure>() {
+                    Some(failure) => {
+                        eprintln!("Not writing output: {failure}");
+                        std::process::exit(failure.exit_code());
+                    }
+                    None => Err(e),
+                },
+                Ok(_) => Ok(()),
+            }
+        }
+    }
 }
 
-#[derive(Serialize, Debug)]
-struct PackagerCode {
+/// Rows withheld by `--duplicate-codes review` are written here.
+const DUPLICATE_REVIEW_FILE: &str = "duplicate-codes.csv";
+
+/// Lists the files of the latest release when `--dataset-version` is given.
+const LATEST_RELEASE_FILE: &str = "latest.json";
+
+/// Per-stage record counts, written next to the exports on every crawl.
+const STATS_FILE: &str = "stats.json";
+
+async fn crawl(
+    args: &Args,
+    config: &Config,
+    shutdown: &CancellationToken,
+) -> Result<RunCounts, Box<dyn std::error::Error>> {
+    let csv_format = CsvFormat {
+        delimiter: args.delimiter,
+        header: if args.no_header {
+            HeaderStyle::None
+        } else {
+            HeaderStyle::Default
+        },
+        quote_style: args.quote_style,
+        ..CsvFormat::default()
+    };
+    csv_format.validate()?;
+    let previous_manifest = RunManifest::load(&args.manifest)?;
+    let overrides = Overrides::load(&args.overrides)?;
+
+    let nuts_index = match &args.nuts_boundaries {
+        Some(path) => Some(NutsIndex::load(path)?),
+        None => None,
+    };
+
+    let limits = Limits {
+        total: args.limit,
+        per_country: args.limit_per_country,
+    };
+    let mut timings = StageTimings::default();
+    let mut stage_counts = StageCounts::default();
+    let started = Instant::now();
+    let mut countries_categories =
+        fetch_valid_categories_by_countries(args.include_third_countries, &args.include_statuses)
+            .await?;
+    retain_selected(&mut countries_categories, args);
+    if args.food_only {
+        let sections = SectionMap::load(args.sections_file.as_deref())?;
+        stage_counts.non_food = retain_food_sections(&mut countries_categories, &sections);
+    }
+    let section_names = fetch_section_names(&args.lang).await.unwrap_or_else(|e| {
+        eprintln!("Could not fetch the section names, leaving them out: {e}");
+        HashMap::new()
+    });
+    timings.categories = started.elapsed();
+    let counts_by_country = manifest::category_counts(&countries_categories);
+
+    let geocoded_cutoff = args
+        .max_geocode_age
+        .map(|age| SystemTime::now() - days(age));
+    let mut run_manifest = RunManifest::default();
+    let mut changed_categories = Vec::<CountryCategory>::default();
+    for c in countries_categories {
+        let country = &c.country.code;
+        let is_imported = args.import_xls.iter().any(|i| &i.country == country)
+            || args.esa_lists.iter().any(|l| &l.country == country);
+        let carried_over = previous_manifest
+            .as_ref()
+            .filter(|_| args.since_last_run && !args.force && !is_imported)
+            .and_then(|m| m.unchanged(country, &counts_by_country[country.as_str()]))
+            .filter(|previous| {
+                !geocoded_cutoff.is_some_and(|cutoff| {
+                    previous
+                        .packager_codes
+                        .iter()
+                        .any(|p| p.geocoded_before(cutoff))
+                })
+            });
+        match carried_over {
+            Some(previous) => {
+                stage_counts.carried_over += previous.packager_codes.len();
+                run_manifest
+                    .countries
+                    .entry(country.to_string())
+                    .or_insert_with(|| previous.clone());
+            }
+            None => changed_categories.push(c),
+        }
+    }
+    for country in &args.exclude_countries {
+        let Some(previous) = previous_manifest
+            .as_ref()
+            .and_then(|m| m.countries.get(country.as_str()))
+        else {
+            continue;
+        };
+        eprintln!("{country} is excluded; keeping its packager codes from the previous run");
+        stage_counts.carried_over += previous.packager_codes.len();
+        run_manifest
+            .countries
+            .entry(country.to_string())
+            .or_insert_with(|| previous.clone());
+    }
+
+    let estimate = estimate::Estimate::new(&changed_categories, args);
+    estimate.print();
+    if !args.yes && !estimate::confirm()? {
+        return Err(Declined.into());
+    }
+
+    let started = Instant::now();
+    let (mut establishments_by_country, hashes_by_country) = tokio::select! {
+        collected = collect_establishments(args, changed_categories, limits, &mut timings) => {
+            collected?
+        }
+        _ = shutdown.cancelled() => return Err("fetching was interrupted".into()),
+    };
+    timings.fetch = started.elapsed();
+    stage_counts.fetched = establishments_by_country.values().map(Vec::len).sum();
+    let great_britain = set_aside_great_britain(&mut establishments_by_country);
+    let (unnamed, missing_names) = handle_missing_names(&mut establishments_by_country, args).await;
+    stage_counts.missing_name = missing_names;
+    let quarantined =
+        quarantine_unknown_countries(&mut establishments_by_country, args.code_country);
+    let mut blocked: HashMap<String, Vec<Skipped>> = HashMap::new();
+    if !config.blocklist.is_empty() {
+        for (country, establishments) in establishments_by_country.iter_mut() {
+            establishments.retain(|e| {
+                let is_blocked = config.blocklist.is_blocked(e, args.code_country);
+                if is_blocked {
+                    blocked
+                        .entry(country.clone())
+                        .or_default()
+                        .push(Skipped::new(e, SkipReason::Blocklisted));
+                }
+                !is_blocked
+            });
+        }
+    }
+    if let Some(path) = &args.emit_queries {
+        let queries = emit_queries::write_queries_csv(
+            path,
+            &establishments_by_country,
+            args.geocode_country,
+            args.name_fallback,
+        )?;
+        eprintln!(
+            "Wrote {queries} geocoding queries to {}; not geocoding",
+            path.display()
+        );
+        return Ok(RunCounts {
+            countries: establishments_by_country.len(),
+            ..RunCounts::default()
+        });
+    }
+    let geocode_cache = match &args.geocode_cache {
+        Some(path) => GeocodeCache::load(path, cache_ttl(args))?,
+        None => GeocodeCache::default(),
+    };
+    let geocoder = Arc::new(build_geocoder(args, geocode_cache)?);
+    let land_mask = if args.check_water {
+        Some(LandMask::load()?)
+    } else {
+        None
+    };
+    let annotations = Annotations {
+        section_names: &section_names,
+        nuts_index: nuts_index.as_ref(),
+        land_mask: land_mask.as_ref(),
+        code_country: args.code_country,
+    };
+    let mut geocode_stats = GeocodeStats::default();
+    let mut country_files: BTreeMap<String, PathBuf> = BTreeMap::new();
+    let mut on_country_done = |country: &str, results: &CountryResults| {
+        let Some(dir) = args.country_dir.as_deref().filter(|_| !args.overpass_check) else {
+            return;
+        };
+        let nuts_index = nuts_index.as_ref();
+        match write_finished_country(
+            args,
+            config,
+            &csv_format,
+            dir,
+            country,
+            results,
+            &overrides,
+            nuts_index,
+        ) {
+            Ok(path) => {
+                eprintln!("Wrote {}", path.display());
+                country_files.insert(country.to_string(), path);
+            }
+            Err(e) => eprintln!("Could not write the packager codes of {country}: {e}"),
+        }
+    };
+    let started = Instant::now();
+    let geocoded = geocode_all_countries(
+        establishments_by_country,
+        geocoder.clone(),
+        args.geocode_workers.into(),
+        args.geocode_country,
+        &annotations,
+        &mut geocode_stats,
+        &mut timings,
+        &mut on_country_done,
+        shutdown,
+    )
+    .await;
+    timings.geocode = started.elapsed();
+    if let Some(path) = &args.geocode_cache {
+        geocoder.save_cache(path)?;
+    }
+    let mut results_by_country = geocoded?;
+    for results in results_by_country.values() {
+        stage_counts.geocoded += results.packager_codes.len();
+        stage_counts.skipped += results.skipped.len();
+        stage_counts.errors += results.errors.len();
+    }
+    for (country, skipped) in blocked {
+        stage_counts.blocklisted += skipped.len();
+        results_by_country
+            .entry(country)
+            .or_default()
+            .skipped
+            .extend(skipped);
+    }
+    for (country, skipped) in great_britain {
+        stage_counts.great_britain += skipped.len();
+        results_by_country
+            .entry(country)
+            .or_default()
+            .skipped
+            .extend(skipped);
+    }
+    for (country, skipped) in unnamed {
+        results_by_country
+            .entry(country)
+            .or_default()
+            .skipped
+            .extend(skipped);
+    }
+    for (country, skipped) in quarantined {
+        stage_counts.unknown_country += skipped.len();
+        results_by_country
+            .entry(country)
+            .or_default()
+            .skipped
+            .extend(skipped);
+    }
+    if geocode_stats.foreign_address > 0 {
+        eprintln!(
+            "{} establishments have an address outside their approving country; see the \
+             address_country column",
+            geocode_stats.foreign_address
+        );
+    }
+    if geocode_stats.in_water > 0 {
+        eprintln!(
+            "{} geocoded points are in the sea; see the in_water column",
+            geocode_stats.in_water
+        );
+    }
+    let processed_countries: BTreeSet<String> = hashes_by_country
+        .keys()
+        .chain(results_by_country.keys())
+        .cloned()
+        .collect();
+    for country in processed_countries {
+        let results = results_by_country.remove(&country).unwrap_or_default();
+        run_manifest.countries.insert(
+            country.clone(),
+            CountryManifest {
+                categories: counts_by_country.get(&country).cloned().unwrap_or_default(),
+                response_hash: hashes_by_country.get(&country).cloned().unwrap_or_default(),
+                packager_codes: results.packager_codes,
+                skipped: results.skipped,
+                errors: results.errors,
+            },
+        );
+    }
+
+    if args.overpass_check {
+        let check = overpass::cross_check(
+            &mut run_manifest,
+            previous_manifest.as_ref(),
+            &args.overpass_url,
+            args.overpass_radius,
+        )
+        .await;
+        if check.no_match > 0 || check.failed > 0 {
+            eprintln!(
+                "{} codes have no feature named like the operator nearby in OpenStreetMap and {} \
+                 could not be checked; see the poi_nearby column",
+                check.no_match, check.failed
+            );
+        }
+    }
+
+    // Codes missing from a run that left out countries or sections, or stopped at a limit, have
+    // not disappeared from the registry, so such a run is not compared with the previous one.
+    let partial = limits.is_limited() || args.is_filtered();
+    let mut previous_manifest = previous_manifest.unwrap_or_default();
+    let changes = if partial {
+        vec![]
+    } else {
+        diff::diff(&previous_manifest, &run_manifest)
+    };
+    run_manifest.name_history = std::mem::take(&mut previous_manifest.name_history);
+    run_manifest
+        .name_history
+        .extend(diff::name_changes(&changes));
+
+    let (exported, withheld) = prepare_export(args, &overrides, &run_manifest, nuts_index.as_ref());
+    if args.duplicate_codes == DuplicatePolicy::Review && !withheld.is_empty() {
+        duplicates::write_review_csv(Path::new(DUPLICATE_REVIEW_FILE), &withheld, args.compress)?;
+    }
+    if let Some(path) = &args.likely_duplicates {
+        let pairs = duplicates::find_likely_duplicates(
+            &exported,
+            args.likely_duplicate_distance,
+            args.likely_duplicate_similarity,
+        );
+        eprintln!(
+            "{} pairs of codes look like the same establishment",
+            pairs.len()
+        );
+        duplicates::write_likely_duplicates_csv(path, &pairs, args.compress)?;
+    }
+    let packager_codes: Vec<PackagerCode> = exported
+        .countries
+        .values()
+        .flat_map(|c| c.packager_codes.iter().cloned())
+        .collect();
+    quality::check(args, packager_codes.len(), &geocode_stats)?;
+    stage_counts.deduplicated = withheld.len();
+    stage_counts.exported = packager_codes.len();
+
+    let started = Instant::now();
+    let mut written = vec![(
+        export::write_packager_codes_csv(
+            &packager_codes,
+            DataColumns::for_args(args),
+            &csv_format,
+            args.compress,
+        )?,
+        packager_codes.len(),
+    )];
+    for (country, format) in &config.export.countries {
+        let packager_codes = exported
+            .countries
+            .get(country)
+            .map(|c| c.packager_codes.as_slice())
+            .unwrap_or_default();
+        written.push((
+            export::write_country_csv(
+                Path::new(""),
+                country,
+                packager_codes,
+                DataColumns::for_args(args),
+                format,
+                args.compress,
+            )?,
+            packager_codes.len(),
+        ));
+    }
+    // Written again now that duplicates are resolved across countries.
+    if let Some(dir) = &args.country_dir {
+        for (country, c) in &exported.countries {
+            let path =
+                write_country_file(args, config, &csv_format, dir, country, &c.packager_codes)?;
+            country_files.insert(country.clone(), path);
+        }
+    }
+    if let Some(path) = &args.off_packagers {
+        written.push((
+            export::write_off_packagers_json(path, &packager_codes, args.compress)?,
+            packager_codes.len(),
+        ));
+    }
+    let mut published: Vec<PathBuf> = country_files.into_values().collect();
+    let dataset_version = args
+        .dataset_version
+        .as_deref()
+        .map(release::resolve_version);
+    if let Some(dir) = &args.geojson_dir {
+        published.extend(geojson_chunks::write_geojson_chunks(
+            dir,
+            &exported,
+            args.geojson_chunks,
+            args.compress,
+        )?);
+    }
+    if let Some(dir) = &args.mobile_bundles {
+        published.extend(mobile_bundles::write_mobile_bundles(
+            dir,
+            &exported,
+            dataset_version.as_deref(),
+        )?);
+    }
+    if partial && (args.diff.is_some() || args.removed_codes.is_some()) {
+        eprintln!("Not writing the changes since the previous run after a partial run");
+    }
+    if let Some(path) = args.diff.as_ref().filter(|_| !partial) {
+        written.push((
+            diff::write_diff_csv(path, &changes, args.compress)?,
+            changes.len(),
+        ));
+    }
+    if let Some(path) = args.removed_codes.as_ref().filter(|_| !partial) {
+        written.push(diff::write_removed_codes_csv(
+            path,
+            &previous_manifest,
+            &run_manifest,
+            args.compress,
+        )?);
+    }
+    let run = metadata::RunInfo::new(args, traces::base_url(), dataset_version.clone());
+    if let Some(path) = export_sqlite(args, &exported, &run)? {
+        written.push((path, packager_codes.len()));
+    }
+    export_postgres(args, &exported).await?;
+    if let Some(version) = &dataset_version {
+        release::stamp(&mut written, version)?;
+    }
+    if args.metadata {
+        for (path, records) in &written {
+            published.push(metadata::write_sidecar(path, *records, &run)?);
+        }
+    }
+    if let Some(version) = &dataset_version {
+        release::update_latest(Path::new(LATEST_RELEASE_FILE), &written, version)?;
+        published.push(PathBuf::from(LATEST_RELEASE_FILE));
+    }
+    stage_counts.write(Path::new(STATS_FILE))?;
+    if let Some(path) = &args.report {
+        report::write_report(path, &exported, &changes, &stage_counts)?;
+    }
+    published.push(PathBuf::from(STATS_FILE));
+    published.extend(written.into_iter().map(|(path, _)| path));
+    publish(args, &published).await?;
+    timings.export = started.elapsed();
+    if partial {
+        eprintln!("Not updating the run manifest after a partial run");
+    } else {
+        run_manifest.save(&args.manifest)?;
+    }
+
+    timings.print_report();
+    if let Some(path) = &args.summary {
+        let summary = serde_json::json!({
+            "geocode": geocode_stats,
+            "timings": timings,
+        });
+        output::write_atomically(path, serde_json::to_string_pretty(&summary)?)?;
+    }
+    Ok(RunCounts {
+        countries: run_manifest.countries.len(),
+        packager_codes: packager_codes.len(),
+        skipped: run_manifest
+            .countries
+            .values()
+            .map(|c| c.skipped.len())
+            .sum(),
+        errors: run_manifest
+            .countries
+            .values()
+            .map(|c| c.errors.len())
+            .sum(),
+        geocode_attempted: geocode_stats.attempted,
+        geocoded: geocode_stats.geocoded,
+    })
+}
+
+/// Applies the export options to the packager codes of a run, returning the exported view and the
+/// rows withheld as duplicates. The run manifest itself keeps the codes as geocoded.
+fn prepare_export<'a>(
+    args: &Args,
+    overrides: &Overrides,
+    run_manifest: &'a RunManifest,
+    nuts_index: Option<&NutsIndex>,
+) -> (Cow<'a, RunManifest>, Vec<PackagerCode>) {
+    let mut exported = Cow::Borrowed(run_manifest);
+    let (applied, imprecise) = apply_record_options(args, overrides, &mut exported, nuts_index);
+    if applied > 0 {
+        eprintln!("Applied {applied} manual coordinate overrides");
+    }
+    if let Some(min) = args.min_precision.filter(|_| imprecise > 0) {
+        eprintln!(
+            "Left out {imprecise} packager codes located less precisely than {}",
+            min.as_str()
+        );
+    }
+    let withheld = duplicates::resolve(exported.to_mut(), args.duplicate_codes);
+    (exported, withheld)
+}
+
+/// Applies the export options that concern each code on its own, returning how many overrides
+/// were applied and how many codes were left out as too imprecise.
+fn apply_record_options(
+    args: &Args,
+    overrides: &Overrides,
+    exported: &mut Cow<RunManifest>,
+    nuts_index: Option<&NutsIndex>,
+) -> (usize, usize) {
+    let mut applied = 0;
+    if !overrides.is_empty() {
+        applied = overrides.apply(exported.to_mut(), nuts_index);
+    }
+    let name_cleanup = NameCleanup {
+        whitespace: args.clean_names,
+        strip_legal_forms: args.strip_legal_forms,
+        title_case: args.title_case_names,
+    };
+    if name_cleanup.is_enabled() {
+        exported.to_mut().clean_names(&name_cleanup);
+    }
+    if let Some(decimals) = args.coordinate_precision {
+        exported.to_mut().round_coordinates(decimals);
+    }
+    let mut imprecise = 0;
+    if let Some(min) = args.min_precision {
+        imprecise = exported.to_mut().retain_min_precision(min);
+    }
+    if args.web_mercator {
+        exported.to_mut().add_web_mercator();
+    }
+    if args.country_names {
+        exported.to_mut().add_country_names(&args.lang);
+    }
+    exported.to_mut().sort_codes(args.sort);
+    (applied, imprecise)
+}
+
+/// Writes a country's exported packager codes to `dir` in its configured format, falling back to
+/// the format of `data.csv`.
+fn write_country_file(
+    args: &Args,
+    config: &Config,
+    csv_format: &CsvFormat,
+    dir: &Path,
+    country: &str,
+    packager_codes: &[PackagerCode],
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(dir)?;
+    let format = config.export.countries.get(country).unwrap_or(csv_format);
+    export::write_country_csv(
+        dir,
+        country,
+        packager_codes,
+        DataColumns::for_args(args),
+        format,
+        args.compress,
+    )
+}
+
+/// Applies the per-code export options to a country that has just been geocoded and writes its
+/// codes to `dir`. Duplicates can only be resolved once all countries are in, so the file is
+/// written again at the end of the run.
+#[allow(clippy::too_many_arguments)]
+fn write_finished_country(
+    args: &Args,
+    config: &Config,
+    csv_format: &CsvFormat,
+    dir: &Path,
+    country: &str,
+    results: &CountryResults,
+    overrides: &Overrides,
+    nuts_index: Option<&NutsIndex>,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let mut finished = RunManifest::default();
+    finished.countries.insert(
+        country.to_string(),
+        CountryManifest {
+            packager_codes: results.packager_codes.clone(),
+            ..CountryManifest::default()
+        },
+    );
+    let mut exported = Cow::Borrowed(&finished);
+    apply_record_options(args, overrides, &mut exported, nuts_index);
+    let packager_codes = exported
+        .countries
+        .get(country)
+        .map(|c| c.packager_codes.as_slice())
+        .unwrap_or_default();
+    write_country_file(args, config, csv_format, dir, country, packager_codes)
+}
+
+/// Uploads the files written by the run to `--output`, if given.
+#[cfg(feature = "s3")]
+async fn publish(args: &Args, files: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(destination) = &args.output {
+        s3::upload(destination, files).await?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "s3"))]
+async fn publish(args: &Args, _files: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+    if args.output.is_some() {
+        return Err("--output needs a build with the `s3` feature".into());
+    }
+    Ok(())
+}
+
+/// Exports to `--sqlite`, if given, returning the path of the database.
+#[cfg(feature = "sqlite")]
+fn export_sqlite(
+    args: &Args,
+    exported: &RunManifest,
+    run: &metadata::RunInfo,
+) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    let Some(path) = &args.sqlite else {
+        return Ok(None);
+    };
+    sqlite::write_sqlite(path, exported, run)?;
+    Ok(Some(path.clone()))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn export_sqlite(
+    args: &Args,
+    _exported: &RunManifest,
+    _run: &metadata::RunInfo,
+) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    if args.sqlite.is_some() {
+        return Err("--sqlite needs a build with the `sqlite` feature".into());
+    }
+    Ok(None)
+}
+
+/// Replaces the packager codes in `--postgres`, if given.
+#[cfg(feature = "postgres")]
+async fn export_postgres(
+    args: &Args,
+    exported: &RunManifest,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(dsn) = &args.postgres {
+        postgres::write_postgres(dsn, exported).await?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "postgres"))]
+async fn export_postgres(
+    args: &Args,
+    _exported: &RunManifest,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if args.postgres.is_some() {
+        return Err("--postgres needs a build with the `postgres` feature".into());
+    }
+    Ok(())
+}
+
+/// Sets aside EU-approved establishments in Great Britain, which TRACES still lists from before
+/// Brexit, whether under `GB` or `XI`. Northern Irish ones are kept even if their address says
+/// `GB`, as are third-country listings.
+fn set_aside_great_britain(
+    establishments_by_country: &mut EstablishmentsByCountry,
+) -> HashMap<String, Vec<Skipped>> {
+    let mut set_aside: HashMap<String, Vec<Skipped>> = HashMap::new();
+    for (country, establishments) in establishments_by_country.iter_mut() {
+        establishments.retain(|e| {
+            if e.third_country || e.is_in_northern_ireland() {
+                return true;
+            }
+            let in_great_britain = northern_ireland::is_great_britain(e.address_country())
+                || e.approving_country
+                    .as_ref()
+                    .is_some_and(|c| northern_ireland::is_great_britain(c));
+            if in_great_britain {
+                set_aside
+                    .entry(country.clone())
+                    .or_default()
+                    .push(Skipped::new(e, SkipReason::GreatBritain));
+            }
+            !in_great_britain
+        });
+    }
+    let count: usize = set_aside.values().map(Vec::len).sum();
+    if count > 0 {
+        eprintln!("Left out {count} establishments in Great Britain listed from before Brexit");
+    }
+    set_aside
+}
+
+/// Applies `--missing-names` to establishments listed without an operator name, returning those
+/// it skipped and how many there were in all.
+async fn handle_missing_names(
+    establishments_by_country: &mut EstablishmentsByCountry,
+    args: &Args,
+) -> (HashMap<String, Vec<Skipped>>, usize) {
+    let mut skipped: HashMap<String, Vec<Skipped>> = HashMap::new();
+    let mut missing = 0;
+    let mut named = 0;
+    for (country, establishments) in establishments_by_country.iter_mut() {
+        let mut kept = Vec::with_capacity(establishments.len());
+        for mut e in std::mem::take(establishments) {
+            if e.operator_name
+                .as_deref()
+                .is_some_and(|n| !n.trim().is_empty())
+            {
+                kept.push(e);
+                continue;
+            }
+            missing += 1;
+            match args.missing_names {
+                MissingNamePolicy::Keep => {}
+                MissingNamePolicy::Skip => {
+                    skipped
+                        .entry(country.clone())
+                        .or_default()
+                        .push(Skipped::new(&e, SkipReason::MissingName));
+                    continue;
+                }
+                MissingNamePolicy::Placeholder => {
+                    e.operator_name = Some(args.missing_name_placeholder.clone());
+                }
+                MissingNamePolicy::Details => {
+                    if let Some(name) = fetch_operator_name(e.operator_id).await {
+                        e.operator_name = Some(name);
+                        named += 1;
+                    }
+                }
+            }
+            kept.push(e);
+        }
+        *establishments = kept;
+    }
+
+    if missing > 0 {
+        match args.missing_names {
+            MissingNamePolicy::Keep => {
+                eprintln!("{missing} establishments have no operator name; exporting them unnamed")
+            }
+            MissingNamePolicy::Skip => {
+                eprintln!("Left out {missing} establishments without an operator name")
+            }
+            MissingNamePolicy::Placeholder => eprintln!(
+                "Named {missing} establishments without an operator name {:?}",
+                args.missing_name_placeholder
+            ),
+            MissingNamePolicy::Details => eprintln!(
+                "Found the operator name of {named} of {missing} unnamed establishments on their \
+                 detail pages"
+            ),
+        }
+    }
+    (skipped, missing)
+}
+
+/// The operator name on an establishment's detail page, if it has one.
+async fn fetch_operator_name(operator_id: OperatorId) -> Option<String> {
+    if operator_id.is_unknown() {
+        return None;
+    }
+    match fetch_establishment_details(operator_id).await {
+        Ok(details) => details.operator_name.filter(|n| !n.trim().is_empty()),
+        Err(err) => {
+            eprintln!("Could not fetch details of operator {operator_id}: {err}");
+            None
+        }
+    }
+}
+
+/// Sets aside the establishments whose packager code would start with an unknown country code,
+/// rather than exporting codes with a bogus prefix, and warns about every unknown code seen,
+/// including those only found in addresses.
+fn quarantine_unknown_countries(
+    establishments_by_country: &mut EstablishmentsByCountry,
+    code_country: CountrySource,
+) -> HashMap<String, Vec<Skipped>> {
+    let mut quarantined: HashMap<String, Vec<Skipped>> = HashMap::new();
+    let mut unknown: BTreeMap<String, usize> = BTreeMap::new();
+    for (country, establishments) in establishments_by_country.iter_mut() {
+        establishments.retain(|e| {
+            let code_prefix = e.country(code_country);
+            let address_country = e.address_country();
+            if address_country != code_prefix
+                && !address_country.is_empty()
+                && !countries::is_known(address_country)
+            {
+                *unknown.entry(address_country.to_string()).or_default() += 1;
+            }
+            if countries::is_known(code_prefix) {
+                return true;
+            }
+            *unknown.entry(code_prefix.to_string()).or_default() += 1;
+            quarantined
+                .entry(country.clone())
+                .or_default()
+                .push(Skipped::new(e, SkipReason::UnknownCountry));
+            false
+        });
+    }
+    for (code, count) in &unknown {
+        eprintln!("Unknown country code {code:?} on {count} establishments");
+    }
+    quarantined
+}
+
+/// Fetches the establishments of `categories`, completes their addresses if asked to, and adds
+/// the XLS imports and ESA lists, all within `limits`.
+async fn collect_establishments(
+    args: &Args,
+    categories: Vec<CountryCategory>,
+    limits: Limits,
+    timings: &mut StageTimings,
+) -> Result<(EstablishmentsByCountry, HashMap<String, String>), Box<dyn std::error::Error>> {
+    let (mut establishments_by_country, hashes_by_country) =
+        map_establishments_to_countries(categories, limits, args.fetch_concurrency.into(), timings)
+            .await?;
+    if args.fetch_details {
+        enrich_incomplete_addresses(&mut establishments_by_country).await;
+    }
+    for import in &args.import_xls {
+        let imported = import::read_imported_establishments(import, args.force_encoding)?;
+        establishments_by_country
+            .entry(import.country.to_string())
+            .or_default()
+            .extend(imported);
+    }
+    for list in &args.esa_lists {
+        let listed = esa::fetch_esa_establishments(list).await?;
+        eprintln!(
+            "{} establishments from the ESA list for {}",
+            listed.len(),
+            list.country
+        );
+        establishments_by_country
+            .entry(list.country.to_string())
+            .or_default()
+            .extend(listed);
+    }
+    limits.apply(&mut establishments_by_country);
+    Ok((establishments_by_country, hashes_by_country))
+}
+
+/// Records the establishment pages covering `--offset-range` of every selected section into the
+/// raw snapshot again, replacing what an earlier `--save-raw` run stored for them.
+async fn backfill_raw(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(range) = args.offset_range else {
+        return Ok(());
+    };
+    let mut categories =
+        fetch_valid_categories_by_countries(args.include_third_countries, &args.include_statuses)
+            .await?;
+    retain_selected(&mut categories, args);
+    if args.food_only {
+        let sections = SectionMap::load(args.sections_file.as_deref())?;
+        retain_food_sections(&mut categories, &sections);
+    }
+
+    let mut pages = 0;
+    for c in &categories {
+        let (country, section) = (&c.country.code, &c.classification_section_id.code);
+        let listed = usize::try_from(c.number_of_establishments).unwrap_or_default();
+        for page in range
+            .pages(ESTABLISHMENT_PAGE_SIZE)
+            .take_while(|p| (p.offset as usize) < listed)
+        {
+            let fetched: Vec<ListingPage<IgnoredAny>> =
+                fetch_establishment_range(country, section, page.offset, page.max).await?;
+            let records: usize = fetched.iter().map(|p| p.items.len()).sum();
+            eprintln!(
+                "{country}/{section}: recorded {records} establishments from offset {}",
+                page.offset
+            );
+            pages += 1;
+        }
+    }
+    eprintln!("Recorded {pages} pages again; replay them with --from-raw");
+    Ok(())
+}
+
+/// Writes the establishments as they would be geocoded, as a JSON object keyed by country.
+async fn dump_establishments(args: &Args, output: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let limits = Limits {
+        total: args.limit,
+        per_country: args.limit_per_country,
+    };
+    let mut categories =
+        fetch_valid_categories_by_countries(args.include_third_countries, &args.include_statuses)
+            .await?;
+    retain_selected(&mut categories, args);
+    let (establishments_by_country, _) =
+        collect_establishments(args, categories, limits, &mut StageTimings::default()).await?;
+    let sorted: BTreeMap<_, _> = establishments_by_country.into_iter().collect();
+
+    let path = output::output_path(output, args.compress);
+    let mut file = OutputWriter::create(&path, args.compress)?;
+    serde_json::to_writer(&mut file, &sorted)?;
+    file.finish()?;
+    Ok(())
+}
+
+fn cache_ttl(args: &Args) -> CacheTtl {
+    let max_age = args.max_geocode_age.unwrap_or(u32::MAX);
+    CacheTtl {
+        found: days(args.geocode_cache_days.min(max_age)),
+        not_found: days(args.negative_cache_days.min(max_age)),
+    }
+}
+
+fn days(count: u32) -> Duration {
+    Duration::from_secs(u64::from(count) * 24 * 60 * 60)
+}
+
+/// Exports or imports the geocode cache for the `cache` subcommand.
+fn transfer_cache(args: &Args, action: &CacheCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let path = args
+        .geocode_cache
+        .as_deref()
+        .ok_or("the cache subcommand needs --geocode-cache")?;
+    let mut cache = GeocodeCache::load(path, cache_ttl(args))?;
+    match action {
+        CacheCommand::Export { output } => {
+            let entries = cache.export(output)?;
+            eprintln!("Exported {entries} cached geocodes to {}", output.display());
+        }
+        CacheCommand::Import { input } => {
+            let counts = cache.import(input)?;
+            cache.save(path)?;
+            eprintln!(
+                "Imported {} new and {} more recent cached geocodes from {}; ignored {} expired \
+                 or older ones",
+                counts.added,
+                counts.updated,
+                input.display(),
+                counts.ignored
+            );
+        }
+    }
+    Ok(())
+}
+
+fn build_geocoder(
+    args: &Args,
+    cache: GeocodeCache,
+) -> Result<Geocoder, Box<dyn std::error::Error>> {
+    Ok(
+        Geocoder::new(args.geocoders.clone(), build_backends(args)?, cache)
+            .with_name_fallback(args.name_fallback),
+    )
+}
+
+fn build_backends(args: &Args) -> Result<Backends, Box<dyn std::error::Error>> {
+    let offline_index = match &args.geocoder_index {
+        Some(path) => Some(OfflineIndex::load(path)?),
+        None if args.geocoders.contains(&Provider::Offline) => {
+            return Err("the offline geocoder needs --geocoder-index".into())
+        }
+        None => None,
+    };
+    let lau_index = match &args.lau_centroids {
+        Some(path) => Some(LauIndex::load(path)?),
+        None if args.geocoders.contains(&Provider::Lau) => {
+            return Err("the lau geocoder needs --lau-centroids".into())
+        }
+        None => None,
+    };
+    Ok(Backends::new(
+        args.nominatim_url.clone(),
+        args.photon_url.clone(),
+        offline_index,
+        lau_index,
+        Duration::from_secs(args.geocode_timeout.into()),
+    ))
+}
+
+/// Prints everything known about one establishment, to debug parsing and geocoding in isolation.
+async fn fetch_one(
+    args: &Args,
+    country: &CountryCode,
+    section: &SectionCode,
+    operator_id: OperatorId,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let establishments = TracesClient::default()
+        .establishments::<serde_json::Value>(country.clone(), section.clone())
+        .try_filter(|e| {
+            futures::future::ready(e["operatorId"].as_i64() == Some(operator_id.get().into()))
+        });
+    let raw = std::pin::pin!(establishments)
+        .try_next()
+        .await?
+        .ok_or_else(|| format!("operator {operator_id} not found in {country}/{section}"))?;
+    println!("Raw JSON:\n{}\n", serde_json::to_string_pretty(&raw)?);
+
+    let mut establishment: Establishment = serde_json::from_value(raw)?;
+    establishment.approving_country = Some(country.clone());
+    println!("Parsed:\n{establishment:#?}\n");
+
+    println!("Geocoding queries:");
+    let address = establishment.geocoding_address(args.geocode_country);
+    let name = establishment.operator_name.as_deref();
+    for q in geocode::build_queries_with_name(&address, name.filter(|_| args.name_fallback)) {
+        println!("  {:?} ({:?}): {}", q.tier, q.script, q.query);
+    }
+
+    let geocoder = build_geocoder(args, GeocodeCache::default())?;
+    match geocoder.geocode_address(&address, name).await? {
+        AddressMatch::Found {
+            coordinates,
+            tier,
+            precision,
+            script,
+            ..
+        } => {
+            println!(
+                "\nGeocoded ({tier:?}, {precision:?}, {script:?}): {}, {}",
+                coordinates.lat(),
+                coordinates.lng()
+            )
+        }
+        other => println!("\nNot geocoded: {other:?}"),
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PackagerCode {
     name: String,
     code: String,
+    /// Read and written through [`PackagerCode::coordinates`] and
+    /// [`PackagerCode::set_coordinates`], so the axes stay apart.
     lat: f64,
     lng: f64,
+    #[serde(default)]
+    section: SectionCode,
+    #[serde(default)]
+    section_name: String,
+    #[serde(default)]
+    geocode_tier: Option<QueryTier>,
+    /// What the point stands for, as far as the geocoder told.
+    #[serde(default)]
+    location_precision: Option<LocationPrecision>,
+    #[serde(default)]
+    nuts2: Option<String>,
+    #[serde(default)]
+    nuts3: Option<String>,
+    #[serde(default)]
+    country_name: Option<String>,
+    /// Numbers operators sharing this code when duplicates are kept.
+    #[serde(default)]
+    disambiguator: Option<u32>,
+    /// Whether the point lies outside all land, if `--check-water` was given.
+    #[serde(default)]
+    in_water: Option<bool>,
+    /// Web Mercator (EPSG:3857) position in meters, if `--web-mercator` was given.
+    #[serde(default)]
+    x_3857: Option<f64>,
+    #[serde(default)]
+    y_3857: Option<f64>,
+    /// Country of the address when it differs from the approving country.
+    #[serde(default)]
+    address_country: Option<String>,
+    /// Whether OpenStreetMap has a feature named like the operator near the point, if
+    /// `--overpass-check` was given.
+    #[serde(default)]
+    poi_nearby: Option<bool>,
+    /// Whether the address matched as listed or transliterated into another script.
+    #[serde(default)]
+    query_script: Option<QueryScript>,
+    /// Approval number exactly as TRACES or the imported list gave it, before formatting into
+    /// `code`.
+    #[serde(default)]
+    code_raw: Option<String>,
+    /// When the provider resolved the point, as RFC 3339; for cached answers the time they were
+    /// cached. Unset for overridden points and in manifests written before it was recorded.
+    #[serde(default)]
+    geocoded_at: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
+impl PackagerCode {
+    pub fn coordinates(&self) -> Coordinates {
+        Coordinates::new(self.lat, self.lng)
+            .expect("packager codes are only given valid coordinates")
+    }
+
+    pub fn set_coordinates(&mut self, coordinates: Coordinates) {
+        self.lat = coordinates.lat();
+        self.lng = coordinates.lng();
+    }
+
+    /// Whether the point was geocoded before `cutoff`, or at an unknown time.
+    fn geocoded_before(&self, cutoff: SystemTime) -> bool {
+        self.geocoded_at
+            .as_deref()
+            .and_then(|at| humantime::parse_rfc3339_weak(at).ok())
+            .is_none_or(|at| at < cutoff)
+    }
+}
+
+/// Why an establishment did not make it into the output.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    NoApprovalNumber,
+    BadAddress,
+    GeocodeFailure,
+    OutOfBounds,
+    /// Excluded by the configured blocklist.
+    Blocklisted,
+    /// The packager code would start with a country code that is not ISO 3166-1 or a TRACES
+    /// alias.
+    UnknownCountry,
+    /// Listed as EU-approved but located in Great Britain, which left the EU.
+    GreatBritain,
+    /// Listed without an operator name, with `--missing-names skip`.
+    MissingName,
+}
+
+impl SkipReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SkipReason::NoApprovalNumber => "no_approval_number",
+            SkipReason::BadAddress => "bad_address",
+            SkipReason::GeocodeFailure => "geocode_failure",
+            SkipReason::OutOfBounds => "out_of_bounds",
+            SkipReason::Blocklisted => "blocklisted",
+            SkipReason::UnknownCountry => "unknown_country",
+            SkipReason::GreatBritain => "great_britain",
+            SkipReason::MissingName => "missing_name",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Skipped {
+    operator_id: OperatorId,
+    operator_name: Option<String>,
+    approval_number: Option<ApprovalNumber>,
+    section: SectionCode,
+    reason: SkipReason,
+}
+
+impl Skipped {
+    fn new(e: &Establishment, reason: SkipReason) -> Skipped {
+        Skipped {
+            operator_id: e.operator_id,
+            operator_name: e.operator_name.clone(),
+            approval_number: e.approval_number.clone(),
+            section: e.section.clone(),
+            reason,
+        }
+    }
+}
+
+/// An establishment whose processing failed with an error rather than a known skip reason.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EstablishmentError {
+    operator_id: OperatorId,
+    operator_name: Option<String>,
+    approval_number: Option<ApprovalNumber>,
+    section: SectionCode,
+    message: String,
+}
+
+impl EstablishmentError {
+    fn new(e: &Establishment, message: String) -> EstablishmentError {
+        EstablishmentError {
+            operator_id: e.operator_id,
+            operator_name: e.operator_name.clone(),
+            approval_number: e.approval_number.clone(),
+            section: e.section.clone(),
+            message,
+        }
+    }
+}
+
+/// Everything the geocoding stage produced for one country.
+#[derive(Debug, Default)]
+struct CountryResults {
+    packager_codes: Vec<PackagerCode>,
+    skipped: Vec<Skipped>,
+    errors: Vec<EstablishmentError>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct Establishment {
-    operator_id: i32,
+    operator_id: OperatorId,
     operator_name: Option<String>,
     address: Address,
-    approval_number: Option<String>,
+    approval_number: Option<ApprovalNumber>,
+    /// Classification section the establishment was listed under; not part of the payload.
+    #[serde(default)]
+    section: SectionCode,
+    /// Whether the establishment is a third-country exporter rather than EU-approved.
+    #[serde(default)]
+    third_country: bool,
+    /// Country whose listing the establishment came from; not part of the payload.
+    #[serde(default)]
+    approving_country: Option<CountryCode>,
 }
 
-#[derive(Deserialize, Debug)]
+impl Establishment {
+    fn address_country(&self) -> &CountryCode {
+        &self.address.city_reference.country.code
+    }
+
+    /// Country of the address if it differs from the approving country.
+    fn foreign_address_country(&self) -> Option<&CountryCode> {
+        let approving = self.approving_country.as_ref()?;
+        Some(self.address_country()).filter(|c| *c != approving && !c.is_empty())
+    }
+
+    /// The approving or address country, falling back to the address if the approving country
+    /// is not known. Northern Irish addresses given as `GB` count as `XI`.
+    fn country(&self, source: CountrySource) -> &CountryCode {
+        match (source, &self.approving_country) {
+            (CountrySource::Approving, Some(approving)) => approving,
+            (_, Some(approving))
+                if approving.as_str() == northern_ireland::NORTHERN_IRELAND
+                    && self.is_in_northern_ireland() =>
+            {
+                approving
+            }
+            _ => self.address_country(),
+        }
+    }
+
+    /// Whether the address is in Northern Ireland, by its country or its postcode.
+    fn is_in_northern_ireland(&self) -> bool {
+        let city = &self.address.city_reference;
+        city.country.code.as_str() == northern_ireland::NORTHERN_IRELAND
+            || (northern_ireland::is_great_britain(&city.country.code)
+                && city
+                    .postal_code
+                    .as_deref()
+                    .is_some_and(northern_ireland::is_northern_irish_postcode))
+    }
+
+    /// OFF packager code, prefixed with the country picked by `code_country`.
+    fn packager_code(&self, code_country: CountrySource) -> Option<String> {
+        let approval_number = self.approval_number.as_deref()?;
+        Some(code::format_establishment_code(
+            self.country(code_country),
+            approval_number,
+            self.third_country,
+        ))
+    }
+
+    /// The address as geocoded, with its country replaced by the approving country if
+    /// `geocode_country` asks for it, and `XI` by `GB`.
+    fn geocoding_address(&self, geocode_country: CountrySource) -> Cow<'_, Address> {
+        let mut country = self.country(geocode_country).clone();
+        if country.as_str() == northern_ireland::NORTHERN_IRELAND {
+            // Geocoders know Northern Ireland as part of the United Kingdom only.
+            country = "GB".parse().unwrap();
+        }
+        if &country == self.address_country() {
+            return Cow::Borrowed(&self.address);
+        }
+        let mut address = self.address.clone();
+        address.city_reference.country.code = country;
+        Cow::Owned(address)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 struct Address {
     street: Street,
     city_reference: City,
 }
 
-#[derive(Deserialize, Debug)]
+impl Address {
+    /// Whether the street or postal code is missing, which the detail endpoint may fill in.
+    fn is_incomplete(&self) -> bool {
+        let street = self.street.value.trim();
+        street.is_empty()
+            || street == "."
+            || self
+                .city_reference
+                .postal_code
+                .as_deref()
+                .is_none_or(|p| p.trim().is_empty())
+    }
+
+    /// Fills components missing here from `other`.
+    fn complete_from(&mut self, other: Address) {
+        let street = self.street.value.trim();
+        if (street.is_empty() || street == ".") && !other.street.value.trim().is_empty() {
+            self.street = other.street;
+        }
+        let city = &mut self.city_reference;
+        if city
+            .postal_code
+            .as_deref()
+            .is_none_or(|p| p.trim().is_empty())
+        {
+            city.postal_code = other.city_reference.postal_code;
+        }
+        if city.name.as_deref().is_none_or(|n| n.trim().is_empty()) {
+            city.name = other.city_reference.name;
+        }
+    }
+
+    /// Builds an address from plain components, for sources other than TRACES.
+    fn new(
+        street: String,
+        postal_code: Option<String>,
+        city: Option<String>,
+        country: CountryCode,
+    ) -> Address {
+        Address {
+            street: Street { value: street },
+            city_reference: City {
+                city_id: 0,
+                postal_code,
+                name: city,
+                country: Country {
+                    code: country,
+                    status: CountryStatus { id: "V".to_owned() },
+                },
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 struct Street {
     value: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
 struct City {
     city_id: i32,
     postal_code: Option<String>,
@@ -55,217 +1522,780 @@ struct City {
     country: Country,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 struct CountryStatus {
     id: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
-struct Country {
-    code: String,
+pub struct Country {
+    code: CountryCode,
     status: CountryStatus,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct ClassificationSectionId {
+#[allow(dead_code)]
+pub struct ClassificationSectionId {
     id: String,
-    code: String,
+    code: SectionCode,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct CountryCategory {
+#[allow(dead_code)]
+pub struct CountryCategory {
     sequence_number: i32,
     country: Country,
     classification_section_id: ClassificationSectionId,
     number_of_establishments: i32,
+    /// Whether the category comes from the list of approved third-country exporters.
+    #[serde(skip)]
+    third_country: bool,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SectionActivity {
+    code: String,
+    translation: Option<String>,
+}
+
+/// Establishment pages requested at once when the size of a listing is known; the TRACES rate
+/// limiter still spaces out the requests.
+const ESTABLISHMENT_PAGE_CONCURRENCY: usize = 4;
+
+/// Largest establishment page TRACES serves, and the size a crawl requests.
+const ESTABLISHMENT_PAGE_SIZE: i32 = 1000;
+
+/// Fetches the establishments listed for a country and section, stopping after `limit` if given,
+/// along with the SHA-256 of the raw pages.
+///
+/// When the listing size is known, from the total count header of the first page or else the
+/// `advertised` category count, the remaining pages are fetched concurrently and the number of
+/// records is checked against it. Otherwise pages are fetched one after another until an empty
+/// one. If the first page comes back short of a listing its known size says is longer, TRACES
+/// capped the page size and the rest is paged by what it served instead.
 async fn fetch_establishments_for_country_and_section(
-    country: String,
-    section: String,
-) -> Result<Vec<Establishment>, Box<dyn std::error::Error>> {
-    let mut offset = 0;
-    let page_size = 1000;
+    country: CountryCode,
+    section: SectionCode,
+    advertised: Option<usize>,
+    limit: Option<usize>,
+) -> Result<(Vec<Establishment>, Vec<u8>), Box<dyn std::error::Error>> {
+    let requested = limit.map_or(ESTABLISHMENT_PAGE_SIZE, |l| {
+        l.clamp(1, ESTABLISHMENT_PAGE_SIZE as usize) as i32
+    });
+    let first = fetch_establishment_range(&country, &section, 0, requested).await?;
+    let total = first.first().and_then(|p| p.total).or(advertised);
+
+    let mut hasher = Sha256::new();
     let mut establishments = Vec::<Establishment>::default();
+    let mut fetched = read_establishment_pages(first, &section, &mut hasher, &mut establishments);
+    let page_size = traces::effective_page_size(requested, fetched, total);
+    if page_size != requested {
+        eprintln!(
+            "{country}/{section}: TRACES served {page_size} of {requested} establishments \
+             requested; paging by {page_size}"
+        );
+    }
+
+    // Pages of a known listing are fetched ahead, but consumed in order one at a time so that
+    // only the pages in flight are held as text rather than the whole section.
+    let mut prefetch_left = total.filter(|_| fetched > 0).map_or(0, |total| {
+        let wanted = limit.map_or(total, |l| l.min(total));
+        wanted.div_ceil(page_size as usize).saturating_sub(1)
+    });
+    let mut prefetched = std::pin::pin!(futures::stream::iter(1..=prefetch_left)
+        .map(|page| {
+            let (country, section) = (&country, &section);
+            async move {
+                fetch_establishment_range(country, section, page as i32 * page_size, page_size)
+                    .await
+                    // Keeps the buffered stream `Send` for replays spawned on other threads.
+                    .map_err(|e| e.to_string())
+            }
+        })
+        .buffered(ESTABLISHMENT_PAGE_CONCURRENCY));
+
+    let mut offset = fetched as i32;
     loop {
-        let mut establishments_page = fetch_establishments_for_country_and_section_page(
-            country.to_owned(),
-            section.to_owned(),
-            offset,
-            page_size,
-        )
-        .await?;
-        if establishments_page.is_empty() {
+        if fetched == 0 {
+            break;
+        }
+        if limit.is_some_and(|l| establishments.len() >= l) {
+            establishments.truncate(limit.unwrap());
+            break;
+        }
+        // Trust a known size unless the listing turned out to be longer than it claimed.
+        let full = fetched == page_size as usize;
+        if prefetch_left == 0 && total.is_some_and(|t| !full || establishments.len() <= t) {
             break;
         }
-        establishments.append(&mut establishments_page);
-        offset += page_size;
+
+        let pages = if prefetch_left > 0 {
+            prefetch_left -= 1;
+            prefetched
+                .next()
+                .await
+                .expect("the stream yields every prefetched page")?
+        } else {
+            fetch_establishment_range(&country, &section, offset, page_size).await?
+        };
+        fetched = read_establishment_pages(pages, &section, &mut hasher, &mut establishments);
+        offset += fetched as i32;
+    }
+
+    if let Some(total) = total.filter(|_| limit.is_none()) {
+        if establishments.len() != total {
+            eprintln!(
+                "{country}/{section}: expected {total} establishments but fetched {}",
+                establishments.len()
+            );
+        }
     }
-    return Ok(establishments);
+    Ok((establishments, hasher.finalize().to_vec()))
 }
 
-async fn fetch_establishments_for_country_and_section_page(
-    country: String,
-    section: String,
-    offset: i32,
-    max: i32,
-) -> Result<Vec<Establishment>, Box<dyn std::error::Error>> {
-    let offset_param: String = offset.to_string();
-    let max_param: String = max.to_string();
+/// Moves the establishments of `pages` into `establishments`, hashing the hashes of the raw
+/// pages, and returns how many there were.
+fn read_establishment_pages(
+    pages: Vec<ListingPage<Establishment>>,
+    section: &SectionCode,
+    hasher: &mut Sha256,
+    establishments: &mut Vec<Establishment>,
+) -> usize {
+    let mut read = 0;
+    for mut page in pages {
+        hasher.update(&page.digest);
+        read += page.items.len();
+        for e in &mut page.items {
+            e.section = section.clone();
+        }
+        establishments.append(&mut page.items);
+    }
+    read
+}
 
-    let base_url = format!(
-        "https://webgate.ec.europa.eu/tracesnt/directory/publication/establishment/establishments/{cc}/{section}?sort=operatorName",
-        cc = encode(&country),
-        section = encode(&section));
-    let url =
-        url::Url::parse_with_params(&base_url, &[("max", max_param), ("offset", offset_param)])?;
+/// Smallest page size tried when TRACES fails to serve a page.
+const MIN_PAGE_SIZE: i32 = 25;
 
-    let a_second = time::Duration::from_millis(1000);
-    thread::sleep(a_second);
+/// Fetches the establishments from `offset` to `offset + max` as one page or, while TRACES keeps
+/// failing on a page, as two pages of half its size. Returns the pages in order; the next range
+/// is tried at full size again.
+async fn fetch_establishment_range<T: DeserializeOwned>(
+    country: &CountryCode,
+    section: &SectionCode,
+    offset: i32,
+    max: i32,
+) -> Result<Vec<ListingPage<T>>, Box<dyn std::error::Error>> {
+    let mut pages = vec![];
+    let mut ranges = vec![(offset, max)];
+    while let Some((offset, max)) = ranges.pop() {
+        match fetch_establishments_for_country_and_section_page(country, section, offset, max).await
+        {
+            Ok(page) => pages.push(page),
+            Err(e) if max > MIN_PAGE_SIZE && traces::is_transient(e.as_ref()) => {
+                let half = max / 2;
+                eprintln!(
+                    "{country}/{section}: page of {max} at offset {offset} failed ({e}); \
+                     retrying as pages of {half}"
+                );
+                ranges.push((offset + half, max - half));
+                ranges.push((offset, half));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(pages)
+}
 
-    let resp: Vec<Establishment> = reqwest::get(url).await?.json().await?;
-    return Ok(resp);
+async fn fetch_establishments_for_country_and_section_page<T: DeserializeOwned>(
+    country: &CountryCode,
+    section: &SectionCode,
+    offset: i32,
+    max: i32,
+) -> Result<ListingPage<T>, Box<dyn std::error::Error>> {
+    let url = traces::endpoints()?.establishments(country, section, PageRange { offset, max });
+    let page = traces::fetch_listing_page(url, RequestKind::Listing).await?;
+    Ok(page)
 }
 
-fn write_packager_codes_csv(
-    packager_codes: Vec<PackagerCode>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let data_file = File::create("data.csv").expect("creation failed");
-    let mut wtr = csv::Writer::from_writer(data_file);
-    for c in packager_codes {
-        wtr.serialize(c)?;
-    }
+struct GeocodeJob {
+    country: String,
+    establishment: Establishment,
+}
 
-    wtr.flush()?;
-    return Ok(());
+struct GeocodeJobResult {
+    country: String,
+    establishment: Establishment,
+    /// `None` if the establishment was not worth geocoding.
+    outcome: Option<Result<AddressMatch, String>>,
+    elapsed: Duration,
 }
 
+/// Geocodes all establishments with a pool of `workers` tasks fed through a bounded channel,
+/// while this task collects the results.
+///
+/// Once `shutdown` is cancelled no new establishments are handed out; in-flight ones are drained
+/// before returning an error, so callers can still persist the geocode cache.
+///
+/// `on_country_done` is called once all establishments of a country have been geocoded.
+#[allow(clippy::too_many_arguments)]
 async fn geocode_all_countries(
     establishments_by_country: HashMap<String, Vec<Establishment>>,
-) -> Result<Vec<PackagerCode>, Box<dyn std::error::Error>> {
-    let a_second = time::Duration::from_millis(1000);
-    let mut packager_codes: Vec<PackagerCode> = vec![];
-    for (_, establishments) in &establishments_by_country {
-        for e in establishments {
-            if e.approval_number.is_none()
-                || e.approval_number.to_owned().is_some_and(|f| f.is_empty())
-            {
-                continue;
-            }
+    geocoder: Arc<Geocoder>,
+    workers: usize,
+    geocode_country: CountrySource,
+    annotations: &Annotations<'_>,
+    stats: &mut GeocodeStats,
+    timings: &mut StageTimings,
+    on_country_done: &mut dyn FnMut(&str, &CountryResults),
+    shutdown: &CancellationToken,
+) -> Result<HashMap<String, CountryResults>, Box<dyn std::error::Error>> {
+    let mut pending: HashMap<String, usize> = establishments_by_country
+        .iter()
+        .map(|(country, establishments)| (country.clone(), establishments.len()))
+        .collect();
+    let (job_tx, job_rx) = flume::bounded::<GeocodeJob>(workers * 2);
+    let (result_tx, mut result_rx) = mpsc::channel::<GeocodeJobResult>(workers * 2);
 
-            let mut address_components: Vec<String> = vec![];
-            if !e.address.street.value.is_empty() && !e.address.street.value.eq(".") {
-                address_components.push(e.address.street.value.clone());
-            }
+    let producer_results = result_tx.clone();
+    let shutdown = shutdown.clone();
+    let producer = tokio::spawn(async move {
+        for (country, establishments) in establishments_by_country {
+            for establishment in establishments {
+                if establishment
+                    .approval_number
+                    .as_ref()
+                    .is_none_or(|f| f.is_empty())
+                {
+                    let skipped = GeocodeJobResult {
+                        country: country.clone(),
+                        establishment,
+                        outcome: None,
+                        elapsed: Duration::ZERO,
+                    };
+                    if producer_results.send(skipped).await.is_err() {
+                        return false;
+                    }
+                    continue;
+                }
 
-            if !e.address.city_reference.postal_code.is_none() {
-                let postal_code = e.address.city_reference.postal_code.clone().unwrap();
-                if !postal_code.is_empty() {
-                    address_components.push(postal_code);
+                let job = GeocodeJob {
+                    country: country.clone(),
+                    establishment,
+                };
+                tokio::select! {
+                    _ = shutdown.cancelled() => return false,
+                    sent = job_tx.send_async(job) => if sent.is_err() { return false; }
                 }
             }
+        }
+        true
+    });
 
-            if !e.address.city_reference.country.code.is_empty() {
-                address_components.push(e.address.city_reference.country.code.clone());
+    for _ in 0..workers.max(1) {
+        let job_rx = job_rx.clone();
+        let result_tx = result_tx.clone();
+        let geocoder = geocoder.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok(job) = job_rx.recv_async().await else {
+                    break;
+                };
+                let started = Instant::now();
+                let outcome = geocoder
+                    .geocode_address(
+                        &job.establishment.geocoding_address(geocode_country),
+                        job.establishment.operator_name.as_deref(),
+                    )
+                    .await
+                    .map_err(|e| e.to_string());
+                let result = GeocodeJobResult {
+                    country: job.country,
+                    establishment: job.establishment,
+                    outcome: Some(outcome),
+                    elapsed: started.elapsed(),
+                };
+                if result_tx.send(result).await.is_err() {
+                    break;
+                }
             }
+        });
+    }
+    drop(result_tx);
 
-            let address = address_components.join(", ");
-            let res = task::spawn_blocking(move || {
-                thread::sleep(a_second);
-                let osm = Openstreetmap::new();
-                let r: Vec<Point<f64>> = osm.forward(&address).unwrap_or_default();
-                return r;
-            })
-            .await?;
-            let empty = Point::<f64>::new(0f64, 0f64);
-            let f = res.first().unwrap_or(&empty);
-            if f.x() <= 0f64 || f.y() <= 0f64 {
-                continue;
-            }
+    let mut results_by_country: HashMap<String, CountryResults> = HashMap::new();
+    while let Some(result) = result_rx.recv().await {
+        timings.country(&result.country).geocode += result.elapsed;
+        let results = results_by_country
+            .entry(result.country.clone())
+            .or_default();
+        record_geocode_result(
+            results,
+            result.establishment,
+            result.outcome,
+            annotations,
+            stats,
+        );
+        let left = pending.get_mut(&result.country).unwrap();
+        *left -= 1;
+        if *left == 0 {
+            on_country_done(&result.country, results);
+        }
+    }
 
-            packager_codes.push(PackagerCode {
-                code: format!(
-                    "{} {} EC",
-                    e.address.city_reference.country.code.clone(),
-                    e.approval_number.clone().unwrap()
-                ),
-                name: e.operator_name.clone().unwrap_or_default(),
-                lat: f.x(),
-                lng: f.y(),
-            });
+    if !producer.await? {
+        return Err("geocoding was interrupted".into());
+    }
+    Ok(results_by_country)
+}
+
+/// Lookups adding context to geocoded establishments.
+struct Annotations<'a> {
+    section_names: &'a HashMap<String, String>,
+    nuts_index: Option<&'a NutsIndex>,
+    land_mask: Option<&'a LandMask>,
+    /// Which country prefixes the packager codes.
+    code_country: CountrySource,
+}
+
+fn record_geocode_result(
+    results: &mut CountryResults,
+    e: Establishment,
+    outcome: Option<Result<AddressMatch, String>>,
+    annotations: &Annotations<'_>,
+    stats: &mut GeocodeStats,
+) {
+    let Some(outcome) = outcome else {
+        results
+            .skipped
+            .push(Skipped::new(&e, SkipReason::NoApprovalNumber));
+        return;
+    };
+
+    stats.attempted += 1;
+    let (coordinates, tier, precision, script, geocoded_at) = match outcome {
+        Ok(AddressMatch::Found {
+            coordinates,
+            tier,
+            precision,
+            script,
+            geocoded_at,
+        }) => (coordinates, tier, precision, script, geocoded_at),
+        Ok(failure) => {
+            let reason = match failure {
+                AddressMatch::NoQuery => SkipReason::BadAddress,
+                AddressMatch::OutOfBounds => SkipReason::OutOfBounds,
+                _ => SkipReason::GeocodeFailure,
+            };
+            results.skipped.push(Skipped::new(&e, reason));
+            return;
+        }
+        Err(err) => {
+            results.errors.push(EstablishmentError::new(&e, err));
+            return;
+        }
+    };
+    stats.geocoded += 1;
+    let regions = annotations
+        .nuts_index
+        .map(|n| n.lookup(coordinates.point()))
+        .unwrap_or_default();
+    let in_water = annotations.land_mask.map(|m| m.is_water(coordinates));
+    if in_water == Some(true) {
+        stats.in_water += 1;
+    }
+
+    let address_country = e.foreign_address_country().map(|c| c.to_string());
+    if address_country.is_some() {
+        stats.foreign_address += 1;
+    }
+
+    results.packager_codes.push(PackagerCode {
+        code: e.packager_code(annotations.code_country).unwrap(),
+        name: e.operator_name.clone().unwrap_or_default(),
+        lat: coordinates.lat(),
+        lng: coordinates.lng(),
+        section_name: annotations
+            .section_names
+            .get(e.section.as_str())
+            .cloned()
+            .unwrap_or_default(),
+        section: e.section,
+        geocode_tier: Some(tier),
+        location_precision: Some(precision),
+        nuts2: regions.nuts2,
+        nuts3: regions.nuts3,
+        country_name: None,
+        disambiguator: None,
+        in_water,
+        x_3857: None,
+        y_3857: None,
+        address_country,
+        poi_nearby: None,
+        query_script: Some(script),
+        code_raw: e.approval_number.as_ref().map(|a| a.to_string()),
+        geocoded_at: Some(humantime::format_rfc3339_seconds(geocoded_at).to_string()),
+    });
+}
+
+type EstablishmentsByCountry = HashMap<String, Vec<Establishment>>;
+
+/// Fills in streets and postal codes the list endpoint left out from the per-establishment detail
+/// records. Failures are reported and leave the establishment as it was.
+async fn enrich_incomplete_addresses(establishments_by_country: &mut EstablishmentsByCountry) {
+    for e in establishments_by_country.values_mut().flatten() {
+        if e.operator_id.is_unknown() || !e.address.is_incomplete() {
+            continue;
+        }
+
+        match fetch_establishment_details(e.operator_id).await {
+            Ok(details) => e.address.complete_from(details.address),
+            Err(err) => eprintln!(
+                "Could not fetch details of operator {}: {err}",
+                e.operator_id
+            ),
         }
     }
+}
 
-    return Ok(packager_codes);
+async fn fetch_establishment_details(
+    operator_id: OperatorId,
+) -> Result<Establishment, Box<dyn std::error::Error>> {
+    let url = traces::endpoints()?.details(operator_id);
+    let resp: Establishment =
+        serde_json::from_str(&traces::fetch_json_text(url, RequestKind::Detail).await?)?;
+    Ok(resp)
 }
 
+/// Fetches all establishments, grouped by the country of the category they were listed under,
+/// along with a hash of the raw responses per country.
+///
+/// Up to `concurrency` countries are fetched at once, each of them section by section. Countries
+/// fetched at the same time cannot see each other's counts and may fetch more than the total
+/// limit leaves, so the sections are cut down again as they are collected in category order.
 async fn map_establishments_to_countries(
     countries_categories: Vec<CountryCategory>,
-) -> Result<HashMap<String, Vec<Establishment>>, Box<dyn std::error::Error>> {
-    let mut grouped_map: HashMap<String, Vec<Establishment>> = HashMap::new();
+    limits: Limits,
+    concurrency: usize,
+    timings: &mut StageTimings,
+) -> Result<(EstablishmentsByCountry, HashMap<String, String>), Box<dyn std::error::Error>> {
+    if raw::is_replaying() {
+        return replay_establishments(countries_categories, limits, timings).await;
+    }
+
+    let mut by_country: Vec<Vec<CountryCategory>> = vec![];
     for c in countries_categories {
-        let data = fetch_establishments_for_country_and_section(
-            c.country.code.to_owned(),
-            c.classification_section_id.code.to_owned(),
-        )
-        .await?;
+        match by_country
+            .iter_mut()
+            .find(|categories| categories[0].country.code == c.country.code)
+        {
+            Some(categories) => categories.push(c),
+            None => by_country.push(vec![c]),
+        }
+    }
+
+    let total = AtomicUsize::new(0);
+    let total = &total;
+    let mut fetched = futures::stream::iter(by_country.into_iter().map(|categories| async move {
+        let country = categories[0].country.code.to_string();
+        let started = Instant::now();
+        let mut sections = vec![];
+        let mut in_country = 0;
+        for c in categories {
+            let limit = limits.remaining(total.load(Ordering::Relaxed), in_country);
+            if limit == Some(0) {
+                continue;
+            }
+            let (data, digest) = fetch_establishments_for_country_and_section(
+                c.country.code.clone(),
+                c.classification_section_id.code.clone(),
+                usize::try_from(c.number_of_establishments).ok(),
+                limit,
+            )
+            .await?;
+            in_country += data.len();
+            sections.push((c, data, digest));
+        }
+        Ok::<_, Box<dyn std::error::Error>>((country, sections, started.elapsed()))
+    }))
+    .buffered(concurrency.max(1));
+
+    let mut grouped = GroupedEstablishments::new(limits);
+    while let Some((country, sections, elapsed)) = fetched.try_next().await? {
+        if !sections.is_empty() {
+            timings.country(&country).fetch += elapsed;
+        }
+        for (c, data, digest) in sections {
+            let added = grouped.add(&c, data, &digest);
+            total.fetch_add(added, Ordering::Relaxed);
+        }
+    }
+
+    Ok(grouped.finish())
+}
 
-        let mut stream = futures::stream::iter(data);
-        while let Some(item) = stream.next().await {
-            let key = item.address.city_reference.country.code.clone();
-            grouped_map.entry(key).or_insert_with(Vec::new).push(item);
+/// Reads all categories from a raw snapshot at once. Replays are bound by JSON parsing rather than
+/// the network, so each category is parsed on its own task.
+///
+/// Limits are applied afterwards, as page sizes must match the recorded requests.
+async fn replay_establishments(
+    countries_categories: Vec<CountryCategory>,
+    limits: Limits,
+    timings: &mut StageTimings,
+) -> Result<(EstablishmentsByCountry, HashMap<String, String>), Box<dyn std::error::Error>> {
+    let parallelism = std::thread::available_parallelism().map_or(4, usize::from);
+    let parsed: Vec<_> = futures::stream::iter(countries_categories.into_iter().map(|c| {
+        tokio::spawn(async move {
+            let started = Instant::now();
+            let result = fetch_establishments_for_country_and_section(
+                c.country.code.clone(),
+                c.classification_section_id.code.clone(),
+                usize::try_from(c.number_of_establishments).ok(),
+                None,
+            )
+            .await
+            .map_err(|e| e.to_string());
+            (c, result, started.elapsed())
+        })
+    }))
+    .buffered(parallelism)
+    .try_collect()
+    .await?;
+
+    let mut grouped = GroupedEstablishments::new(limits);
+    for (c, result, elapsed) in parsed {
+        let (data, digest) = result?;
+        grouped.add(&c, data, &digest);
+        timings.country(&c.country.code).fetch += elapsed;
+    }
+    Ok(grouped.finish())
+}
+
+/// Establishments collected per country within the limits, with a hash over the raw pages of
+/// each country's sections in category order.
+struct GroupedEstablishments {
+    establishments: EstablishmentsByCountry,
+    hashers: HashMap<String, Sha256>,
+    limits: Limits,
+    taken: usize,
+}
+
+impl GroupedEstablishments {
+    fn new(limits: Limits) -> Self {
+        GroupedEstablishments {
+            establishments: EstablishmentsByCountry::new(),
+            hashers: HashMap::new(),
+            limits,
+            taken: 0,
         }
     }
 
-    return Ok(grouped_map);
+    /// Adds the establishments of a section, as many as the limits leave, and returns how many.
+    /// Sections must be added in category order for the total limit to cut off the same ones.
+    fn add(&mut self, c: &CountryCategory, mut data: Vec<Establishment>, digest: &[u8]) -> usize {
+        let key = c.country.code.to_string();
+        self.hashers.entry(key.clone()).or_default().update(digest);
+        let establishments = self.establishments.entry(key).or_default();
+        if let Some(limit) = self.limits.remaining(self.taken, establishments.len()) {
+            data.truncate(limit);
+        }
+        self.taken += data.len();
+        let added = data.len();
+        for mut e in data {
+            e.third_country = c.third_country;
+            e.approving_country = Some(c.country.code.clone());
+            establishments.push(e);
+        }
+        added
+    }
+
+    fn finish(self) -> (EstablishmentsByCountry, HashMap<String, String>) {
+        let hashes = self
+            .hashers
+            .into_iter()
+            .map(|(country, hasher)| (country, format!("{:x}", hasher.finalize())))
+            .collect();
+        (self.establishments, hashes)
+    }
+}
+
+/// Caps on the number of establishments processed, for quick smoke-test runs.
+#[derive(Debug, Clone, Copy, Default)]
+struct Limits {
+    total: Option<usize>,
+    per_country: Option<usize>,
+}
+
+impl Limits {
+    fn is_limited(&self) -> bool {
+        self.total.is_some() || self.per_country.is_some()
+    }
+
+    /// How many more establishments may be taken for a country, or `None` if unlimited.
+    fn remaining(&self, taken_total: usize, taken_in_country: usize) -> Option<usize> {
+        let total = self.total.map(|n| n.saturating_sub(taken_total));
+        let in_country = self.per_country.map(|n| n.saturating_sub(taken_in_country));
+        match (total, in_country) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Truncates the establishments to the caps, visiting countries in alphabetical order. The
+    /// fetched establishments are already within them, so this only matters once XLS imports or ESA
+    /// lists were added.
+    fn apply(&self, establishments_by_country: &mut EstablishmentsByCountry) {
+        let mut countries: Vec<&String> = establishments_by_country.keys().collect();
+        countries.sort();
+        let countries: Vec<String> = countries.into_iter().cloned().collect();
+
+        let mut total = 0;
+        for country in countries {
+            let establishments = establishments_by_country.get_mut(&country).unwrap();
+            if let Some(limit) = self.remaining(total, 0) {
+                establishments.truncate(limit);
+            }
+            total += establishments.len();
+        }
+    }
 }
 
+/// Fetches the non-empty categories of countries whose status is one of `statuses`, reporting the
+/// establishments left out because of their country's status.
 async fn fetch_valid_categories_by_countries(
+    include_third_countries: bool,
+    statuses: &[String],
 ) -> Result<Vec<CountryCategory>, Box<dyn std::error::Error>> {
-    let country_categories = fetch_categories_by_countries().await?;
-    let filter = futures::stream::iter(country_categories).filter(|current| {
-        let country_is_valid = current.country.status.id == "V";
-        let section_is_not_empty = current.number_of_establishments > 0;
-        let result = country_is_valid && section_is_not_empty;
-        return std::future::ready(result);
+    let mut country_categories = fetch_categories_by_countries(Listing::Eu).await?;
+    if include_third_countries {
+        let mut third_country_categories =
+            fetch_categories_by_countries(Listing::ThirdCountry).await?;
+        for c in &mut third_country_categories {
+            c.third_country = true;
+        }
+        country_categories.append(&mut third_country_categories);
+    }
+
+    let mut excluded: BTreeMap<(String, String), i32> = BTreeMap::new();
+    let filtered = country_categories
+        .into_iter()
+        .filter(|c| c.number_of_establishments > 0)
+        .filter(|c| {
+            let included = statuses.contains(&c.country.status.id);
+            if !included {
+                *excluded
+                    .entry((c.country.code.to_string(), c.country.status.id.clone()))
+                    .or_default() += c.number_of_establishments;
+            }
+            included
+        })
+        .collect();
+    for ((country, status), count) in excluded {
+        eprintln!("Skipping {count} establishments in {country}: country status is {status:?}");
+    }
+
+    Ok(filtered)
+}
+
+/// Only keeps the categories of the sections and countries asked for with `--sections` and
+/// `--countries`, if any, less those of `--exclude-countries`.
+fn retain_selected(categories: &mut Vec<CountryCategory>, args: &Args) {
+    categories.retain(|c| {
+        (args.sections.is_empty() || args.sections.contains(&c.classification_section_id.code))
+            && (args.countries.is_empty() || args.countries.contains(&c.country.code))
+            && !args.exclude_countries.contains(&c.country.code)
     });
+}
 
-    let filtered = filter.collect::<Vec<_>>().await;
-    return Ok(filtered);
+/// Drops the categories of sections the mapping marks as not food, reporting what was left out,
+/// and returns how many establishments they list. Sections missing from the mapping are kept, so
+/// sections TRACES adds are not lost silently.
+fn retain_food_sections(categories: &mut Vec<CountryCategory>, sections: &SectionMap) -> usize {
+    let mut excluded: BTreeMap<String, i32> = BTreeMap::new();
+    let mut unknown = BTreeSet::new();
+    categories.retain(|c| {
+        let section = &c.classification_section_id.code;
+        match sections.is_food(section) {
+            Some(true) => true,
+            Some(false) => {
+                *excluded.entry(section.to_string()).or_default() += c.number_of_establishments;
+                false
+            }
+            None => {
+                unknown.insert(section.to_string());
+                true
+            }
+        }
+    });
+    let mut total = 0;
+    for (section, count) in excluded {
+        eprintln!("Skipping {count} establishments in section {section}: not food");
+        total += usize::try_from(count).unwrap_or_default();
+    }
+    for section in unknown {
+        eprintln!("Section {section} is not in the section mapping; keeping it");
+    }
+    total
 }
 
-async fn fetch_categories_by_countries() -> Result<Vec<CountryCategory>, Box<dyn std::error::Error>>
-{
-    let mut offset = 0;
+/// Category pages requested at once; the TRACES rate limiter still spaces out the requests.
+const CATEGORY_PAGE_CONCURRENCY: i32 = 4;
+
+/// Fetches all category pages of a listing, in batches of pages requested at once, until an
+/// empty one. Pages of the last batch after the empty one are not read, so their failures, such
+/// as a replayed snapshot not having them, do not matter.
+async fn fetch_categories_by_countries(
+    listing: Listing,
+) -> Result<Vec<CountryCategory>, Box<dyn std::error::Error>> {
     let page_size = 1000;
     let mut country_categories = Vec::<CountryCategory>::default();
 
+    let mut first = 0;
     loop {
-        let mut categories_by_countries =
-            fetch_categories_by_countries_page(offset, page_size).await?;
-        if categories_by_countries.is_empty() {
-            break;
+        let mut pages = std::pin::pin!(futures::stream::iter(
+            (first..first + CATEGORY_PAGE_CONCURRENCY).map(|page| {
+                let page = PageRange {
+                    offset: page * page_size,
+                    max: page_size,
+                };
+                fetch_categories_by_countries_page(listing, page)
+            })
+        )
+        .buffered(CATEGORY_PAGE_CONCURRENCY as usize));
+        while let Some(mut categories_by_countries) = pages.try_next().await? {
+            if categories_by_countries.is_empty() {
+                return Ok(country_categories);
+            }
+            country_categories.append(&mut categories_by_countries);
         }
-        country_categories.append(&mut categories_by_countries);
-        offset += page_size;
+        first += CATEGORY_PAGE_CONCURRENCY;
     }
-
-    return Ok(country_categories);
 }
 
 async fn fetch_categories_by_countries_page(
-    offset: i32,
-    max: i32,
+    listing: Listing,
+    page: PageRange,
 ) -> Result<Vec<CountryCategory>, Box<dyn std::error::Error>> {
-    let offset_param: String = offset.to_string();
-    let max_param: String = max.to_string();
+    let url = traces::endpoints()?.categories(listing, page);
+    let resp: Vec<CountryCategory> =
+        serde_json::from_str(&traces::fetch_json_text(url, RequestKind::Listing).await?)?;
+    Ok(resp)
+}
 
-    let url  = url::Url::parse_with_params("https://webgate.ec.europa.eu/tracesnt/directory/publication/establishment?sort=country.translation",
-        &[("max", max_param), ("offset", offset_param)])?;
-    let resp: Vec<CountryCategory> = reqwest::get(url).await?.json().await?;
-    return Ok(resp);
+/// Fetches the classification section dictionary, mapping section codes to their names in `lang`.
+async fn fetch_section_names(
+    lang: &str,
+) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+    let url = traces::endpoints()?.activities(lang);
+    let resp: Vec<SectionActivity> =
+        serde_json::from_str(&traces::fetch_json_text(url, RequestKind::Listing).await?)?;
+    let names = resp
+        .into_iter()
+        .filter_map(|a| a.translation.map(|name| (a.code, name)))
+        .collect();
+    Ok(names)
 }
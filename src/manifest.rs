@@ -0,0 +1,218 @@
+use crate::coordinates::Coordinates;
+use crate::countries;
+use crate::diff::NameChange;
+use crate::geocode::LocationPrecision;
+use crate::names::NameCleanup;
+use crate::output::AtomicFile;
+use crate::{CountryCategory, EstablishmentError, PackagerCode, Skipped};
+use clap::ValueEnum;
+use serde::*;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Order of the packager codes within a country in the exports; countries always come in order of
+/// their code.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    /// By approval number, then operator name.
+    #[default]
+    Code,
+    /// By section, then approval number.
+    Section,
+    /// By operator name, then approval number.
+    Name,
+    /// As geocoded, which varies from run to run.
+    None,
+}
+
+impl SortOrder {
+    fn compare(&self, a: &PackagerCode, b: &PackagerCode) -> Ordering {
+        let by_code = || a.code.cmp(&b.code).then_with(|| a.name.cmp(&b.name));
+        let first = match self {
+            SortOrder::Code | SortOrder::None => by_code(),
+            SortOrder::Section => a
+                .section
+                .as_str()
+                .cmp(b.section.as_str())
+                .then_with(by_code),
+            SortOrder::Name => a.name.cmp(&b.name).then_with(|| a.code.cmp(&b.code)),
+        };
+        first
+            .then_with(|| a.section.as_str().cmp(b.section.as_str()))
+            .then_with(|| a.coordinates().lat().total_cmp(&b.coordinates().lat()))
+            .then_with(|| a.coordinates().lng().total_cmp(&b.coordinates().lng()))
+    }
+}
+
+/// Version of the manifest layout. Manifests without one stored geocoded latitudes and
+/// longitudes swapped.
+const MANIFEST_FORMAT: u32 = 1;
+
+/// State persisted between runs so unchanged countries can be skipped.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunManifest {
+    #[serde(default)]
+    pub format: u32,
+    pub countries: BTreeMap<String, CountryManifest>,
+    /// Operator renames seen across runs, oldest first.
+    #[serde(default)]
+    pub name_history: Vec<NameChange>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CountryManifest {
+    /// Number of establishments per classification section, as advertised by TRACES.
+    pub categories: BTreeMap<String, i32>,
+    /// SHA-256 over the hashes of the raw establishment pages of each of the country's sections.
+    pub response_hash: String,
+    /// Rows emitted for the country, carried over when the country is skipped.
+    pub packager_codes: Vec<PackagerCode>,
+    /// Establishments excluded from the output, with the reason.
+    #[serde(default)]
+    pub skipped: Vec<Skipped>,
+    /// Establishments whose processing failed.
+    #[serde(default)]
+    pub errors: Vec<EstablishmentError>,
+}
+
+impl Default for RunManifest {
+    fn default() -> RunManifest {
+        RunManifest {
+            format: MANIFEST_FORMAT,
+            countries: BTreeMap::new(),
+            name_history: vec![],
+        }
+    }
+}
+
+impl RunManifest {
+    /// Loads the manifest from `path`, returning `None` if no previous run left one behind.
+    /// Manifests of earlier versions are brought up to date.
+    pub fn load(path: &Path) -> Result<Option<RunManifest>, Box<dyn std::error::Error>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let reader = BufReader::new(File::open(path)?);
+        let mut manifest: RunManifest = serde_json::from_reader(reader)?;
+        for (country, c) in &mut manifest.countries {
+            for p in c.packager_codes.iter_mut() {
+                if manifest.format < 1 {
+                    (p.lat, p.lng) = (p.lng, p.lat);
+                }
+                Coordinates::new(p.lat, p.lng)
+                    .map_err(|e| format!("{} ({country}) in {}: {e}", p.code, path.display()))?;
+            }
+        }
+        manifest.format = MANIFEST_FORMAT;
+        Ok(Some(manifest))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = AtomicFile::create(path)?;
+        serde_json::to_writer_pretty(&mut file, self)?;
+        file.commit()?;
+        Ok(())
+    }
+
+    /// Rounds all output coordinates to `decimals` places.
+    pub fn round_coordinates(&mut self, decimals: u32) {
+        for p in self
+            .countries
+            .values_mut()
+            .flat_map(|c| c.packager_codes.iter_mut())
+        {
+            p.set_coordinates(p.coordinates().round(decimals));
+        }
+    }
+
+    /// Removes rows located less precisely than `min`, returning how many were removed. Rows of
+    /// unknown precision are kept.
+    pub fn retain_min_precision(&mut self, min: LocationPrecision) -> usize {
+        let mut removed = 0;
+        for c in self.countries.values_mut() {
+            let before = c.packager_codes.len();
+            c.packager_codes.retain(|p| {
+                p.location_precision
+                    .is_none_or(|precision| precision >= min)
+            });
+            removed += before - c.packager_codes.len();
+        }
+        removed
+    }
+
+    /// Sorts the rows of each country, so that successive exports can be diffed.
+    pub fn sort_codes(&mut self, order: SortOrder) {
+        if order == SortOrder::None {
+            return;
+        }
+        for c in self.countries.values_mut() {
+            c.packager_codes.sort_by(|a, b| order.compare(a, b));
+        }
+    }
+
+    /// Sets the Web Mercator position of each row from its latitude and longitude.
+    pub fn add_web_mercator(&mut self) {
+        for p in self
+            .countries
+            .values_mut()
+            .flat_map(|c| c.packager_codes.iter_mut())
+        {
+            let (x, y) = p.coordinates().web_mercator();
+            p.x_3857 = Some(x);
+            p.y_3857 = Some(y);
+        }
+    }
+
+    /// Sets the display name of each row's country in `lang`, where the bundled list has one.
+    pub fn add_country_names(&mut self, lang: &str) {
+        for (country, c) in self.countries.iter_mut() {
+            let name = countries::name(country, lang);
+            for p in c.packager_codes.iter_mut() {
+                p.country_name = name.map(str::to_owned);
+            }
+        }
+    }
+
+    /// Cleans operator names, using each country's main language for title casing.
+    pub fn clean_names(&mut self, cleanup: &NameCleanup) {
+        for (country, c) in self.countries.iter_mut() {
+            let languages = countries::languages(country).unwrap_or_default();
+            let lang = languages.split(',').next().unwrap_or("en");
+            for p in c.packager_codes.iter_mut() {
+                p.name = cleanup.clean(&p.name, lang);
+            }
+        }
+    }
+
+    /// Returns the previous entry for `country` if its category counts are identical.
+    pub fn unchanged(
+        &self,
+        country: &str,
+        categories: &BTreeMap<String, i32>,
+    ) -> Option<&CountryManifest> {
+        self.countries
+            .get(country)
+            .filter(|previous| previous.categories == *categories)
+    }
+}
+
+pub fn category_counts<'a>(
+    categories: impl IntoIterator<Item = &'a CountryCategory>,
+) -> BTreeMap<String, BTreeMap<String, i32>> {
+    let mut counts: BTreeMap<String, BTreeMap<String, i32>> = BTreeMap::new();
+    for c in categories {
+        counts
+            .entry(c.country.code.to_string())
+            .or_default()
+            .insert(
+                c.classification_section_id.code.to_string(),
+                c.number_of_establishments,
+            );
+    }
+
+    counts
+}
@@ -0,0 +1,243 @@
+use crate::cli::Args;
+use crate::geocode::Provider;
+use crate::http;
+use crate::output;
+use clap::ValueEnum;
+use serde::*;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Where the data of a run came from, shared by the metadata of every file it exports.
+#[derive(Serialize, Debug)]
+pub struct RunInfo {
+    pub generated_at: String,
+    pub generator: String,
+    pub traces_base_url: String,
+    pub geocoders: Vec<Provider>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dataset_version: Option<String>,
+    pub config: RunConfig,
+}
+
+impl RunInfo {
+    pub fn new(args: &Args, traces_base_url: &str, dataset_version: Option<String>) -> RunInfo {
+        RunInfo {
+            generated_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            generator: concat!("off-imsoc/", env!("CARGO_PKG_VERSION")).to_owned(),
+            traces_base_url: http::redact_str(traces_base_url),
+            geocoders: args.geocoders.clone(),
+            dataset_version,
+            config: RunConfig::new(args),
+        }
+    }
+}
+
+/// The options a run was made with that affect what it exports, so a published dataset can be
+/// reproduced. Output paths and credentials are left out.
+#[derive(Serialize, Debug)]
+pub struct RunConfig {
+    pub config_file: Option<PathBuf>,
+    pub lang: String,
+    pub filters: FilterConfig,
+    pub requests: RequestConfig,
+    pub geocoding: GeocodingConfig,
+    pub export: ExportConfig,
+}
+
+/// Which establishments were crawled.
+#[derive(Serialize, Debug)]
+pub struct FilterConfig {
+    pub since_last_run: bool,
+    pub force: bool,
+    pub limit: Option<usize>,
+    pub limit_per_country: Option<usize>,
+    pub include_third_countries: bool,
+    pub include_statuses: Vec<String>,
+    pub sections: Vec<String>,
+    pub countries: Vec<String>,
+    pub exclude_countries: Vec<String>,
+    pub food_only: bool,
+    pub sections_file: Option<PathBuf>,
+    pub import_xls: Vec<String>,
+    pub force_encoding: Option<String>,
+    pub esa_lists: Vec<String>,
+    pub from_raw: Option<PathBuf>,
+}
+
+/// How TRACES was queried.
+#[derive(Serialize, Debug)]
+pub struct RequestConfig {
+    pub adaptive_rate: bool,
+    pub listing_timeout_secs: u32,
+    pub detail_timeout_secs: u32,
+    pub fetch_details: bool,
+    pub fetch_concurrency: u16,
+}
+
+/// The provider chain and what backs it.
+#[derive(Serialize, Debug)]
+pub struct GeocodingConfig {
+    pub providers: Vec<Provider>,
+    pub name_fallback: bool,
+    pub workers: u16,
+    pub timeout_secs: u32,
+    pub nominatim_url: String,
+    pub photon_url: String,
+    pub offline_index: Option<PathBuf>,
+    pub lau_centroids: Option<PathBuf>,
+    pub country: String,
+    pub cache: Option<PathBuf>,
+    pub cache_days: u32,
+    pub negative_cache_days: u32,
+    pub max_geocode_age: Option<u32>,
+    pub check_water: bool,
+    pub overpass_check: bool,
+    pub overpass_radius: u32,
+}
+
+/// How the results were post-processed before export.
+#[derive(Serialize, Debug)]
+pub struct ExportConfig {
+    pub code_country: String,
+    pub rules_file: Option<PathBuf>,
+    pub overrides: PathBuf,
+    pub missing_names: String,
+    pub missing_name_placeholder: String,
+    pub clean_names: bool,
+    pub strip_legal_forms: bool,
+    pub title_case_names: bool,
+    pub coordinate_precision: Option<u32>,
+    pub min_precision: Option<String>,
+    pub web_mercator: bool,
+    pub country_names: bool,
+    pub duplicate_codes: String,
+    pub sort: String,
+    pub nuts_boundaries: Option<PathBuf>,
+}
+
+/// The command-line spelling of an option value.
+fn value_name(value: &impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_owned())
+        .unwrap_or_default()
+}
+
+impl RunConfig {
+    pub fn new(args: &Args) -> RunConfig {
+        RunConfig {
+            config_file: args.config.clone(),
+            lang: args.lang.clone(),
+            filters: FilterConfig {
+                since_last_run: args.since_last_run,
+                force: args.force,
+                limit: args.limit,
+                limit_per_country: args.limit_per_country,
+                include_third_countries: args.include_third_countries,
+                include_statuses: args.include_statuses.clone(),
+                sections: args.sections.iter().map(|s| s.to_string()).collect(),
+                countries: args.countries.iter().map(|c| c.to_string()).collect(),
+                exclude_countries: args
+                    .exclude_countries
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect(),
+                food_only: args.food_only,
+                sections_file: args.sections_file.clone(),
+                import_xls: args
+                    .import_xls
+                    .iter()
+                    .map(|i| format!("{}:{}={}", i.country, i.section, i.path.display()))
+                    .collect(),
+                force_encoding: args.force_encoding.map(|e| e.name().to_owned()),
+                esa_lists: args
+                    .esa_lists
+                    .iter()
+                    .map(|l| format!("{}:{}={}", l.country, l.section, http::redact(&l.url)))
+                    .collect(),
+                from_raw: args.from_raw.clone(),
+            },
+            requests: RequestConfig {
+                adaptive_rate: args.adaptive_rate,
+                listing_timeout_secs: args.listing_timeout,
+                detail_timeout_secs: args.detail_timeout,
+                fetch_details: args.fetch_details,
+                fetch_concurrency: args.fetch_concurrency,
+            },
+            geocoding: GeocodingConfig {
+                providers: args.geocoders.clone(),
+                name_fallback: args.name_fallback,
+                workers: args.geocode_workers,
+                timeout_secs: args.geocode_timeout,
+                nominatim_url: http::redact_str(&args.nominatim_url),
+                photon_url: http::redact_str(&args.photon_url),
+                offline_index: args.geocoder_index.clone(),
+                lau_centroids: args.lau_centroids.clone(),
+                country: value_name(&args.geocode_country),
+                cache: args.geocode_cache.clone(),
+                cache_days: args.geocode_cache_days,
+                negative_cache_days: args.negative_cache_days,
+                max_geocode_age: args.max_geocode_age,
+                check_water: args.check_water,
+                overpass_check: args.overpass_check,
+                overpass_radius: args.overpass_radius,
+            },
+            export: ExportConfig {
+                code_country: value_name(&args.code_country),
+                rules_file: args.rules_file.clone(),
+                overrides: args.overrides.clone(),
+                missing_names: value_name(&args.missing_names),
+                missing_name_placeholder: args.missing_name_placeholder.clone(),
+                clean_names: args.clean_names,
+                strip_legal_forms: args.strip_legal_forms,
+                title_case_names: args.title_case_names,
+                coordinate_precision: args.coordinate_precision,
+                min_precision: args.min_precision.as_ref().map(value_name),
+                web_mercator: args.web_mercator,
+                country_names: args.country_names,
+                duplicate_codes: value_name(&args.duplicate_codes),
+                sort: value_name(&args.sort),
+                nuts_boundaries: args.nuts_boundaries.clone(),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct FileMetadata<'a> {
+    file: String,
+    records: usize,
+    sha256: String,
+    #[serde(flatten)]
+    run: &'a RunInfo,
+}
+
+/// Writes `<path>.meta.json` next to an exported file, with its record count and the SHA-256 of
+/// its bytes as written (after compression), and returns the sidecar's path.
+pub fn write_sidecar(
+    path: &Path,
+    records: usize,
+    run: &RunInfo,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+
+    let metadata = FileMetadata {
+        file: path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        records,
+        sha256: format!("{:x}", hasher.finalize()),
+        run,
+    };
+
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".meta.json");
+    let sidecar = PathBuf::from(sidecar);
+    output::write_atomically(&sidecar, serde_json::to_string_pretty(&metadata)?)?;
+    Ok(sidecar)
+}
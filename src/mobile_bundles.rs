@@ -0,0 +1,98 @@
+use crate::manifest::RunManifest;
+use crate::output::{self, AtomicFile};
+use flate2::write::GzEncoder;
+use serde::*;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Decimals kept of each coordinate, about a meter, which is plenty for a lookup.
+const COORDINATE_DECIMALS: i32 = 5;
+
+/// Bundle of one country: every packager code as `[code, name, lat, lng]`, sorted by code.
+#[derive(Serialize, Debug)]
+struct Bundle<'a> {
+    country: &'a str,
+    codes: Vec<(&'a str, &'a str, f64, f64)>,
+}
+
+/// Entry of `index.json`, so clients can tell which bundles changed before downloading them.
+#[derive(Serialize, Debug)]
+struct BundleIndexEntry {
+    country: String,
+    file: String,
+    codes: usize,
+    bytes: u64,
+    /// SHA-256 of the compressed file.
+    sha256: String,
+}
+
+#[derive(Serialize, Debug)]
+struct BundleIndex {
+    generated_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dataset_version: Option<String>,
+    bundles: Vec<BundleIndexEntry>,
+}
+
+fn round(value: f64) -> f64 {
+    let factor = 10f64.powi(COORDINATE_DECIMALS);
+    (value * factor).round() / factor
+}
+
+/// Writes a gzip-compressed JSON bundle per country under `dir` for offline packager code lookup
+/// in the mobile apps, plus an `index.json` listing them. Returns the paths of all files
+/// written.
+pub fn write_mobile_bundles(
+    dir: &Path,
+    manifest: &RunManifest,
+    dataset_version: Option<&str>,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(dir)?;
+    let mut index = BundleIndex {
+        generated_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        dataset_version: dataset_version.map(str::to_owned),
+        bundles: vec![],
+    };
+    let mut files = vec![];
+    for (country, c) in &manifest.countries {
+        if c.packager_codes.is_empty() {
+            continue;
+        }
+        let mut codes: Vec<_> = c
+            .packager_codes
+            .iter()
+            .map(|p| {
+                let position = p.coordinates();
+                (
+                    p.code.as_str(),
+                    p.name.as_str(),
+                    round(position.lat()),
+                    round(position.lng()),
+                )
+            })
+            .collect();
+        codes.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+
+        let file = format!("{country}.json.gz");
+        let path = dir.join(&file);
+        let mut encoder = GzEncoder::new(AtomicFile::create(&path)?, flate2::Compression::best());
+        serde_json::to_writer(&mut encoder, &Bundle { country, codes })?;
+        encoder.finish()?.commit()?;
+
+        let bytes = std::fs::read(&path)?;
+        index.bundles.push(BundleIndexEntry {
+            country: country.clone(),
+            file,
+            codes: c.packager_codes.len(),
+            bytes: bytes.len() as u64,
+            sha256: format!("{:x}", Sha256::digest(&bytes)),
+        });
+        files.push(path);
+    }
+
+    let index_path = dir.join("index.json");
+    output::write_atomically(&index_path, serde_json::to_string_pretty(&index)?)?;
+    files.push(index_path);
+    Ok(files)
+}
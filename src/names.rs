@@ -0,0 +1,232 @@
+use clap::ValueEnum;
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// What to do with establishments listed without an operator name.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum MissingNamePolicy {
+    /// Export them with an empty name.
+    #[default]
+    Keep,
+    /// Leave them out, listing them as skipped.
+    Skip,
+    /// Export them under `--missing-name-placeholder`.
+    Placeholder,
+    /// Look the name up on the establishment's detail page, keeping it empty if there is none.
+    Details,
+}
+
+/// Legal forms as usually spelled. Dots are optional when matching, so `SARL` also matches
+/// `S.A.R.L.`.
+const LEGAL_FORMS: &[&str] = &[
+    "GmbH & Co. KG",
+    "Sp. z o.o.",
+    "S.A.S.U.",
+    "SARL",
+    "EURL",
+    "SAS",
+    "SNC",
+    "GAEC",
+    "SCEA",
+    "EARL",
+    "SA",
+    "GmbH",
+    "KG",
+    "AG",
+    "UG",
+    "OHG",
+    "e.K.",
+    "BVBA",
+    "BV",
+    "NV",
+    "SRL",
+    "SpA",
+    "SLU",
+    "SL",
+    "Ltd",
+    "PLC",
+    "LLC",
+    "Lda",
+    "Oy",
+    "AB",
+    "ApS",
+    "A/S",
+    "AS",
+    "s.r.o.",
+    "a.s.",
+    "Kft.",
+    "Zrt.",
+    "d.o.o.",
+    "SE",
+    "Inc.",
+];
+
+/// Words left in lower case inside title-cased names, per language.
+fn minor_words(lang: &str) -> &'static [&'static str] {
+    match lang {
+        "fr" => &[
+            "de", "du", "des", "la", "le", "les", "et", "en", "sur", "aux", "au",
+        ],
+        "de" => &[
+            "von", "vom", "und", "der", "die", "das", "am", "im", "zu", "zum", "zur",
+        ],
+        "nl" => &["van", "de", "der", "den", "het", "en", "op", "aan"],
+        "es" => &["de", "del", "la", "las", "los", "y", "e"],
+        "it" => &["di", "del", "della", "dei", "e", "ed"],
+        "pt" => &["de", "do", "da", "dos", "das", "e"],
+        "en" => &["and", "of", "the"],
+        _ => &[],
+    }
+}
+
+/// Optional clean-ups of operator names, which TRACES passes on as the authorities entered them.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NameCleanup {
+    /// Trim and collapse runs of whitespace.
+    pub whitespace: bool,
+    /// Remove leading and trailing legal forms such as `S.A.` or `GmbH`.
+    pub strip_legal_forms: bool,
+    /// Title-case names written entirely in capitals.
+    pub title_case: bool,
+}
+
+impl NameCleanup {
+    pub fn is_enabled(&self) -> bool {
+        self.whitespace || self.strip_legal_forms || self.title_case
+    }
+
+    /// Cleans `name` of an operator in a country whose main language is `lang`.
+    pub fn clean(&self, name: &str, lang: &str) -> String {
+        let mut name = if self.whitespace {
+            name.split_whitespace().collect::<Vec<_>>().join(" ")
+        } else {
+            name.to_owned()
+        };
+        if self.strip_legal_forms {
+            name = strip_legal_forms(&name);
+        }
+        if self.title_case && is_all_caps(&name) {
+            name = title_case(&name, lang);
+        }
+        name
+    }
+}
+
+/// Pattern matching `form` with optional dots and flexible spacing.
+fn legal_form_pattern(form: &str) -> String {
+    let mut pattern = String::new();
+    for c in form.chars() {
+        match c {
+            '.' => {}
+            ' ' => pattern.push_str(r"\s*"),
+            c => {
+                pattern.push_str(&regex::escape(&c.to_string()));
+                if c.is_alphanumeric() {
+                    pattern.push_str(r"\.?");
+                }
+            }
+        }
+    }
+    pattern
+}
+
+fn legal_form_regexes() -> &'static (Regex, Regex) {
+    static REGEXES: OnceLock<(Regex, Regex)> = OnceLock::new();
+    REGEXES.get_or_init(|| {
+        let forms = LEGAL_FORMS
+            .iter()
+            .map(|f| legal_form_pattern(f))
+            .collect::<Vec<_>>()
+            .join("|");
+        (
+            Regex::new(&format!(r"(?i)^(?:{forms})\s+")).expect("legal forms are valid patterns"),
+            Regex::new(&format!(r"(?i)[\s,]+(?:{forms})\s*$"))
+                .expect("legal forms are valid patterns"),
+        )
+    })
+}
+
+/// Removes legal forms from either end of `name`, unless nothing else would be left.
+pub fn strip_legal_forms(name: &str) -> String {
+    let (leading, trailing) = legal_form_regexes();
+    let mut stripped = leading.replace(name, "").into_owned();
+    loop {
+        let shorter = trailing.replace(&stripped, "").into_owned();
+        if shorter == stripped {
+            break;
+        }
+        stripped = shorter;
+    }
+    let stripped = stripped.trim_end_matches([',', ' ']).trim();
+    if stripped.is_empty() {
+        name.to_owned()
+    } else {
+        stripped.to_owned()
+    }
+}
+
+fn is_all_caps(name: &str) -> bool {
+    name.chars().any(char::is_alphabetic) && !name.chars().any(char::is_lowercase)
+}
+
+/// Spelling of `word` if it is a single-word legal form, which keeps its usual casing.
+fn legal_form_spelling(word: &str) -> Option<&'static str> {
+    let key = |s: &str| s.replace('.', "").to_uppercase();
+    let word = key(word);
+    LEGAL_FORMS
+        .iter()
+        .find(|f| !f.contains(' ') && key(f) == word)
+        .copied()
+}
+
+fn to_lowercase(word: &str, lang: &str) -> String {
+    if lang == "tr" {
+        word.replace('I', "ı").replace('İ', "i").to_lowercase()
+    } else {
+        word.to_lowercase()
+    }
+}
+
+fn to_uppercase(c: char, lang: &str) -> String {
+    if lang == "tr" && c == 'i' {
+        "İ".to_owned()
+    } else {
+        c.to_uppercase().collect()
+    }
+}
+
+/// Title-cases a name, keeping legal forms as usually spelled and the language's short
+/// connecting words in lower case. Letters after hyphens and apostrophes start a new word, as in
+/// `Saint-Jean` or `L'Abattoir`.
+fn title_case(name: &str, lang: &str) -> String {
+    let minor = minor_words(lang);
+    name.split(' ')
+        .enumerate()
+        .map(|(i, word)| {
+            if let Some(form) = legal_form_spelling(word) {
+                return form.to_owned();
+            }
+            let lower = to_lowercase(word, lang);
+            if i > 0 && minor.contains(&lower.as_str()) {
+                return lower;
+            }
+            let mut titled = String::with_capacity(lower.len());
+            let mut start = true;
+            for c in lower.chars() {
+                if start && c.is_alphabetic() {
+                    titled.push_str(&to_uppercase(c, lang));
+                    start = false;
+                } else {
+                    titled.push(c);
+                    if c.is_alphanumeric() {
+                        start = false;
+                    } else if matches!(c, '-' | '\'' | '’' | '(' | '/') {
+                        start = true;
+                    }
+                }
+            }
+            titled
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
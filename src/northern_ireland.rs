@@ -0,0 +1,75 @@
+use crate::code::{register_formatter, strip_affixes};
+
+/// TRACES code of Northern Ireland, whose establishments stay approved under EU food law since
+/// Brexit.
+pub const NORTHERN_IRELAND: &str = "XI";
+
+/// Codes Great Britain is listed under: `GB`, and `UK` as used in identification marks before
+/// Brexit.
+const GREAT_BRITAIN: &[&str] = &["GB", "UK"];
+
+/// Identification mark of Northern Irish establishments, as in `UK(NI) 1234 EC`.
+const MARK: &str = "UK(NI)";
+
+/// Prefixes Northern Irish approval numbers are listed with besides the mark itself.
+const NUMBER_PREFIXES: &[&str] = &[NORTHERN_IRELAND, "NI", "UK", "GB"];
+
+/// Postcode area of Northern Ireland, which covers all of it and nothing else.
+const POSTCODE_AREA: &str = "BT";
+
+/// Renders Northern Irish codes with the `UK(NI)` mark instead of the `XI` TRACES code.
+pub fn register_formatters() {
+    register_formatter(NORTHERN_IRELAND, format_northern_ireland_code);
+}
+
+/// Whether `country` stands for Great Britain, which left the EU listings with Brexit.
+pub fn is_great_britain(country: &str) -> bool {
+    GREAT_BRITAIN
+        .iter()
+        .any(|c| c.eq_ignore_ascii_case(country))
+}
+
+/// Whether `postal_code` is in Northern Ireland.
+pub fn is_northern_irish_postcode(postal_code: &str) -> bool {
+    postal_code
+        .trim()
+        .get(..POSTCODE_AREA.len())
+        .is_some_and(|area| area.eq_ignore_ascii_case(POSTCODE_AREA))
+}
+
+fn format_northern_ireland_code(country: &str, approval_number: &str) -> String {
+    let mut number = strip_affixes(country, approval_number);
+    if let Some(rest) = number
+        .get(..MARK.len())
+        .filter(|m| m.eq_ignore_ascii_case(MARK))
+        .map(|_| &number[MARK.len()..])
+    {
+        number = rest.trim_start();
+    }
+    for prefix in NUMBER_PREFIXES {
+        number = strip_affixes(prefix, number);
+    }
+    format!("{MARK} {number} EC")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_codes_with_the_northern_ireland_mark() {
+        assert_eq!(format_northern_ireland_code("XI", "1234"), "UK(NI) 1234 EC");
+        assert_eq!(
+            format_northern_ireland_code("XI", "UK(NI) 1234 EC"),
+            "UK(NI) 1234 EC"
+        );
+        assert_eq!(
+            format_northern_ireland_code("XI", "XI 1234"),
+            "UK(NI) 1234 EC"
+        );
+        assert_eq!(
+            format_northern_ireland_code("XI", "NI-1234"),
+            "UK(NI) 1234 EC"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use geo::{BoundingRect, Contains, Geometry, MultiPolygon, Point, Rect};
+use std::path::Path;
+
+/// NUTS regions containing a point.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NutsRegions {
+    pub nuts2: Option<String>,
+    pub nuts3: Option<String>,
+}
+
+struct Region {
+    id: String,
+    level: u8,
+    bounds: Rect<f64>,
+    shape: MultiPolygon<f64>,
+}
+
+/// NUTS level 2 and 3 boundaries loaded from a Eurostat GISCO GeoJSON file.
+///
+/// Features need `NUTS_ID` and `LEVL_CODE` properties, as in the GISCO `NUTS_RG_*_4326` exports.
+pub struct NutsIndex {
+    regions: Vec<Region>,
+}
+
+impl NutsIndex {
+    pub fn load(path: &Path) -> Result<NutsIndex, Box<dyn std::error::Error>> {
+        let collection: geojson::FeatureCollection = std::fs::read_to_string(path)?
+            .parse::<geojson::GeoJson>()?
+            .try_into()?;
+
+        let mut regions = vec![];
+        for feature in collection.features {
+            let level = feature
+                .property("LEVL_CODE")
+                .and_then(|v| v.as_u64())
+                .unwrap_or_default();
+            let id = feature.property("NUTS_ID").and_then(|v| v.as_str());
+            let (Some(id), Some(geometry)) = (id, &feature.geometry) else {
+                continue;
+            };
+            if level != 2 && level != 3 {
+                continue;
+            }
+
+            let shape = match Geometry::<f64>::try_from(geometry.clone())? {
+                Geometry::Polygon(p) => MultiPolygon::new(vec![p]),
+                Geometry::MultiPolygon(mp) => mp,
+                _ => continue,
+            };
+            let Some(bounds) = shape.bounding_rect() else {
+                continue;
+            };
+            regions.push(Region {
+                id: id.to_owned(),
+                level: level as u8,
+                bounds,
+                shape,
+            });
+        }
+
+        Ok(NutsIndex { regions })
+    }
+
+    /// Looks up the regions containing `point`, given as longitude/latitude.
+    pub fn lookup(&self, point: Point<f64>) -> NutsRegions {
+        let mut found = NutsRegions::default();
+        for region in &self.regions {
+            if !region.bounds.contains(&point) || !region.shape.contains(&point) {
+                continue;
+            }
+            match region.level {
+                2 => found.nuts2 = Some(region.id.clone()),
+                _ => found.nuts3 = Some(region.id.clone()),
+            }
+        }
+        found
+    }
+}
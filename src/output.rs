@@ -0,0 +1,149 @@
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Compression applied to file outputs.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+}
+
+/// Appends the compression extension, if any, to `path`.
+pub fn output_path(path: &Path, compression: Option<Compression>) -> PathBuf {
+    match compression {
+        Some(c) => {
+            let mut name = path.as_os_str().to_owned();
+            name.push(".");
+            name.push(c.extension());
+            PathBuf::from(name)
+        }
+        None => path.to_path_buf(),
+    }
+}
+
+/// Name a file is written under until it is complete, next to `path`.
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".partial");
+    PathBuf::from(name)
+}
+
+/// A file written under its [partial name](partial_path) and renamed into place by
+/// [`commit`](AtomicFile::commit), so that an interrupted export never leaves a truncated file
+/// where downstream automation picks it up. Dropping it uncommitted removes the partial file.
+#[derive(Debug)]
+pub struct AtomicFile {
+    file: Option<BufWriter<File>>,
+    partial: PathBuf,
+    path: PathBuf,
+}
+
+impl AtomicFile {
+    pub fn create(path: &Path) -> io::Result<AtomicFile> {
+        let partial = partial_path(path);
+        Ok(AtomicFile {
+            file: Some(BufWriter::new(File::create(&partial)?)),
+            partial,
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn commit(mut self) -> io::Result<()> {
+        let file = self.file.take().expect("only committed once");
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(&self.partial, &self.path)
+    }
+
+    fn file(&mut self) -> &mut BufWriter<File> {
+        self.file.as_mut().expect("not committed yet")
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file().flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = std::fs::remove_file(&self.partial);
+        }
+    }
+}
+
+/// Writes `contents` to `path` through an [`AtomicFile`].
+pub fn write_atomically(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut file = AtomicFile::create(path)?;
+    file.write_all(contents.as_ref())?;
+    file.commit()
+}
+
+/// A file output that is optionally compressed on the fly, written atomically.
+///
+/// Compressed streams need an explicit trailer and the file is only moved into place once
+/// complete, so callers must [`finish`](OutputWriter::finish) the writer rather than relying on
+/// drop.
+pub enum OutputWriter {
+    Plain(AtomicFile),
+    Gzip(GzEncoder<AtomicFile>),
+    Zstd(zstd::Encoder<'static, AtomicFile>),
+}
+
+impl OutputWriter {
+    pub fn create(path: &Path, compression: Option<Compression>) -> io::Result<OutputWriter> {
+        let file = AtomicFile::create(path)?;
+        Ok(match compression {
+            None => OutputWriter::Plain(file),
+            Some(Compression::Gzip) => {
+                OutputWriter::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+            }
+            Some(Compression::Zstd) => OutputWriter::Zstd(zstd::Encoder::new(file, 0)?),
+        })
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        let file = match self {
+            OutputWriter::Plain(file) => file,
+            OutputWriter::Gzip(encoder) => encoder.finish()?,
+            OutputWriter::Zstd(encoder) => encoder.finish()?,
+        };
+        file.commit()
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputWriter::Plain(w) => w.write(buf),
+            OutputWriter::Gzip(w) => w.write(buf),
+            OutputWriter::Zstd(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputWriter::Plain(w) => w.flush(),
+            OutputWriter::Gzip(w) => w.flush(),
+            OutputWriter::Zstd(w) => w.flush(),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use crate::clock::TokioClock;
+use crate::coordinates::Coordinates;
+use crate::http;
+use crate::manifest::RunManifest;
+use crate::names::strip_legal_forms;
+use crate::rate::{self, RateLimiter};
+use crate::PackagerCode;
+use serde::*;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+use url::Url;
+
+/// Tags of nearby features compared with the operator name.
+const NAME_TAGS: &[&str] = &["name", "operator", "brand", "official_name"];
+/// Give up on the cross-check after this many failed requests in a row.
+const MAX_CONSECUTIVE_FAILURES: usize = 5;
+
+#[derive(Deserialize, Debug)]
+struct OverpassResponse {
+    elements: Vec<Element>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Element {
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+/// How the cross-check went.
+#[derive(Default, Debug)]
+pub struct CrossCheck {
+    pub checked: usize,
+    pub carried_over: usize,
+    pub no_match: usize,
+    pub failed: usize,
+}
+
+/// Overpass QL for named features within `radius` meters of a point.
+fn query(position: Coordinates, radius: u32) -> String {
+    let (lat, lng) = (position.lat(), position.lng());
+    format!(
+        "[out:json][timeout:25];\
+         (nwr(around:{radius},{lat},{lng})[name];nwr(around:{radius},{lat},{lng})[operator];);\
+         out tags;"
+    )
+}
+
+/// Lower-cased words of a name without legal forms, leaving out those too short to tell
+/// anything.
+fn words(name: &str) -> HashSet<String> {
+    strip_legal_forms(name)
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() >= 3)
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Whether a feature called `candidate` plausibly belongs to `operator`: more than half of the
+/// words of the shorter name appear in the other.
+fn is_plausible(operator: &str, candidate: &str) -> bool {
+    let operator = words(operator);
+    let candidate = words(candidate);
+    let shorter = operator.len().min(candidate.len());
+    shorter > 0 && operator.intersection(&candidate).count() * 2 > shorter
+}
+
+async fn has_matching_feature(
+    url: &Url,
+    limiter: &Mutex<RateLimiter>,
+    operator: &str,
+    position: Coordinates,
+    radius: u32,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    rate::throttle(limiter, &TokioClock).await;
+    let mut url = url.clone();
+    url.query_pairs_mut()
+        .append_pair("data", &query(position, radius));
+    let resp: OverpassResponse = http::get_json(url).await?;
+    Ok(resp.elements.iter().any(|e| {
+        NAME_TAGS
+            .iter()
+            .filter_map(|t| e.tags.get(*t))
+            .any(|name| is_plausible(operator, name))
+    }))
+}
+
+/// What a previous answer is reused for: the same operator at the same point.
+fn key(p: &PackagerCode) -> (&str, &str, u64, u64) {
+    let c = p.coordinates();
+    (&p.code, &p.name, c.lat().to_bits(), c.lng().to_bits())
+}
+
+/// Sets `poi_nearby` on every named code that lacks it, asking Overpass whether OpenStreetMap has
+/// a feature named like the operator within `radius` meters. Codes whose name and point are
+/// unchanged since `previous` keep the earlier answer. Failed lookups leave the field empty.
+pub async fn cross_check(
+    manifest: &mut RunManifest,
+    previous: Option<&RunManifest>,
+    url: &Url,
+    radius: u32,
+) -> CrossCheck {
+    let known: HashMap<(&str, &str, u64, u64), bool> = previous
+        .into_iter()
+        .flat_map(|m| m.countries.values())
+        .flat_map(|c| c.packager_codes.iter())
+        .filter_map(|p| Some((key(p), p.poi_nearby?)))
+        .collect();
+    let limiter = Mutex::new(RateLimiter::fixed(Duration::from_secs(1)));
+    let mut outcome = CrossCheck::default();
+    let mut consecutive_failures = 0;
+    for p in manifest
+        .countries
+        .values_mut()
+        .flat_map(|c| c.packager_codes.iter_mut())
+        .filter(|p| p.poi_nearby.is_none() && !p.name.trim().is_empty())
+    {
+        if let Some(&found) = known.get(&key(p)) {
+            p.poi_nearby = Some(found);
+            outcome.carried_over += 1;
+        } else if consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+            outcome.failed += 1;
+            continue;
+        } else {
+            match has_matching_feature(url, &limiter, &p.name, p.coordinates(), radius).await {
+                Ok(found) => {
+                    p.poi_nearby = Some(found);
+                    outcome.checked += 1;
+                    consecutive_failures = 0;
+                }
+                Err(e) => {
+                    eprintln!("Overpass lookup for {} failed: {e}", p.code);
+                    outcome.failed += 1;
+                    consecutive_failures += 1;
+                    if consecutive_failures == MAX_CONSECUTIVE_FAILURES {
+                        eprintln!("Giving up on the Overpass cross-check after repeated failures");
+                    }
+                    continue;
+                }
+            }
+        }
+        if p.poi_nearby == Some(false) {
+            outcome.no_match += 1;
+        }
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_names_sharing_most_words() {
+        assert!(is_plausible("LACTALIS FROMAGES SAS", "Lactalis"));
+        assert!(is_plausible("Boucherie Martin", "Boucherie Martin & Fils"));
+        assert!(!is_plausible("Boucherie Martin", "Boucherie Dupont"));
+        assert!(!is_plausible("SA", "Mairie"));
+    }
+}
@@ -0,0 +1,71 @@
+use crate::coordinates::Coordinates;
+use crate::geocode::QueryTier;
+use crate::manifest::RunManifest;
+use crate::nuts::NutsIndex;
+use serde::*;
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Deserialize, Debug)]
+struct OverrideRow {
+    code: String,
+    lat: f64,
+    lng: f64,
+}
+
+/// Community-corrected coordinates, keyed by packager code.
+#[derive(Debug, Default)]
+pub struct Overrides {
+    points: HashMap<String, Coordinates>,
+}
+
+impl Overrides {
+    /// Loads `code,lat,lng` rows from `path`, or nothing if the file does not exist.
+    pub fn load(path: &Path) -> Result<Overrides, Box<dyn std::error::Error>> {
+        if !path.exists() {
+            return Ok(Overrides::default());
+        }
+
+        let mut points = HashMap::new();
+        for row in csv::Reader::from_path(path)?.deserialize() {
+            let row: OverrideRow = row?;
+            let coordinates = Coordinates::new(row.lat, row.lng)
+                .map_err(|e| format!("override for {}: {e}", row.code.trim()))?;
+            points.insert(row.code.trim().to_owned(), coordinates);
+        }
+        Ok(Overrides { points })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Replaces the coordinates of overridden codes, marking them as manual and refreshing their
+    /// NUTS regions. Returns the number of rows changed.
+    pub fn apply(&self, manifest: &mut RunManifest, nuts_index: Option<&NutsIndex>) -> usize {
+        let mut applied = 0;
+        for p in manifest
+            .countries
+            .values_mut()
+            .flat_map(|c| c.packager_codes.iter_mut())
+        {
+            let Some(&coordinates) = self.points.get(&p.code) else {
+                continue;
+            };
+            p.set_coordinates(coordinates);
+            p.geocode_tier = Some(QueryTier::Manual);
+            p.location_precision = Some(QueryTier::Manual.precision());
+            p.in_water = None;
+            p.poi_nearby = None;
+            p.query_script = None;
+            p.geocoded_at = None;
+            if let Some(index) = nuts_index {
+                let regions = index.lookup(coordinates.point());
+                p.nuts2 = regions.nuts2;
+                p.nuts3 = regions.nuts3;
+            }
+            applied += 1;
+        }
+        applied
+    }
+}
@@ -0,0 +1,100 @@
+use crate::manifest::RunManifest;
+use tokio_postgres::NoTls;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS packager_codes (
+    country TEXT NOT NULL,
+    code TEXT NOT NULL,
+    name TEXT NOT NULL,
+    lat DOUBLE PRECISION NOT NULL,
+    lng DOUBLE PRECISION NOT NULL,
+    wkt TEXT NOT NULL,
+    section TEXT NOT NULL,
+    section_name TEXT NOT NULL,
+    geocode_tier TEXT,
+    nuts2 TEXT,
+    nuts3 TEXT,
+    country_name TEXT,
+    disambiguator BIGINT,
+    in_water BOOLEAN
+);
+CREATE INDEX IF NOT EXISTS packager_codes_code ON packager_codes (code);
+";
+
+/// Only run where PostGIS is installed, so the table stays usable on plain Postgres.
+const POSTGIS_SCHEMA: &str = "
+ALTER TABLE packager_codes ADD COLUMN IF NOT EXISTS geom geometry(Point, 4326);
+CREATE INDEX IF NOT EXISTS packager_codes_geom ON packager_codes USING GIST (geom);
+";
+
+/// Replaces the contents of the `packager_codes` table with the run's packager codes, creating
+/// the table if needed. Points are stored as WKT, and also as a PostGIS geometry if the
+/// extension is installed.
+pub async fn write_postgres(
+    dsn: &str,
+    manifest: &RunManifest,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut client, connection) = tokio_postgres::connect(dsn, NoTls).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("Postgres connection error: {e}");
+        }
+    });
+
+    let postgis: bool = client
+        .query_one(
+            "SELECT EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'postgis')",
+            &[],
+        )
+        .await?
+        .get(0);
+
+    let tx = client.transaction().await?;
+    tx.batch_execute(SCHEMA).await?;
+    if postgis {
+        tx.batch_execute(POSTGIS_SCHEMA).await?;
+    }
+    tx.execute("DELETE FROM packager_codes", &[]).await?;
+
+    let geom = if postgis {
+        ", geom) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, \
+         ST_GeomFromText($6, 4326))"
+    } else {
+        ") VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)"
+    };
+    let insert_code = tx
+        .prepare(&format!(
+            "INSERT INTO packager_codes
+                (country, code, name, lat, lng, wkt, section, section_name, geocode_tier, nuts2,
+                 nuts3, country_name, disambiguator, in_water{geom}"
+        ))
+        .await?;
+
+    for (country, c) in &manifest.countries {
+        for p in &c.packager_codes {
+            let coordinates = p.coordinates();
+            tx.execute(
+                &insert_code,
+                &[
+                    country,
+                    &p.code,
+                    &p.name,
+                    &coordinates.lat(),
+                    &coordinates.lng(),
+                    &coordinates.wkt(),
+                    &p.section.as_str(),
+                    &p.section_name,
+                    &p.geocode_tier.map(|t| t.as_str()),
+                    &p.nuts2,
+                    &p.nuts3,
+                    &p.country_name,
+                    &p.disambiguator.map(i64::from),
+                    &p.in_water,
+                ],
+            )
+            .await?;
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
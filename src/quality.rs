@@ -0,0 +1,51 @@
+use crate::cli::Args;
+use crate::geocode::GeocodeStats;
+use std::fmt;
+
+/// Reasons a run is considered too degraded to publish.
+#[derive(Debug)]
+pub enum QualityFailure {
+    Empty,
+    LowSuccessRate { rate: f64, minimum: f64 },
+}
+
+impl QualityFailure {
+    /// Process exit code, distinct per failure so pipelines can tell them apart.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            QualityFailure::Empty => 3,
+            QualityFailure::LowSuccessRate { .. } => 4,
+        }
+    }
+}
+
+impl fmt::Display for QualityFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QualityFailure::Empty => write!(f, "no packager codes were produced"),
+            QualityFailure::LowSuccessRate { rate, minimum } => write!(
+                f,
+                "geocoding success rate {:.1}% is below the required {:.1}%",
+                rate * 100.0,
+                minimum * 100.0
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QualityFailure {}
+
+/// Checks the run against the thresholds requested on the command line.
+pub fn check(args: &Args, records: usize, stats: &GeocodeStats) -> Result<(), QualityFailure> {
+    if args.fail_on_empty && records == 0 {
+        return Err(QualityFailure::Empty);
+    }
+
+    if let (Some(minimum), Some(rate)) = (args.min_success_rate, stats.success_rate()) {
+        if rate < minimum {
+            return Err(QualityFailure::LowSuccessRate { rate, minimum });
+        }
+    }
+
+    Ok(())
+}
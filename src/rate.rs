@@ -0,0 +1,77 @@
+use crate::clock::Clock;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Interval the adaptive limiter starts out with.
+pub const ADAPTIVE_START: Duration = Duration::from_secs(2);
+const ADAPTIVE_MIN: Duration = Duration::from_millis(100);
+const ADAPTIVE_MAX: Duration = Duration::from_secs(60);
+/// Responses faster than this let the adaptive limiter speed up.
+const FAST_RESPONSE: Duration = Duration::from_secs(2);
+/// Responses slower than this make the adaptive limiter slow down.
+const SLOW_RESPONSE: Duration = Duration::from_secs(5);
+
+/// How a request went, as far as pacing is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feedback {
+    Success(Duration),
+    /// Rate limited, server error, or the upstream answered with a maintenance page.
+    Throttled,
+}
+
+/// Spaces out requests, either at a fixed interval or adapting the interval to how the server
+/// responds.
+///
+/// The adaptive mode starts conservatively, speeds up while responses stay fast and successful,
+/// and backs off on throttling or slow responses.
+#[derive(Debug)]
+pub struct RateLimiter {
+    delay: Duration,
+    adaptive: bool,
+    next_slot: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn fixed(delay: Duration) -> RateLimiter {
+        RateLimiter {
+            delay,
+            adaptive: false,
+            next_slot: None,
+        }
+    }
+
+    pub fn adaptive() -> RateLimiter {
+        RateLimiter {
+            delay: ADAPTIVE_START,
+            adaptive: true,
+            next_slot: None,
+        }
+    }
+
+    /// Reserves the next request slot and returns how long to wait for it.
+    pub fn reserve(&mut self, now: Instant) -> Duration {
+        let slot = self.next_slot.map_or(now, |next| next.max(now));
+        self.next_slot = Some(slot + self.delay);
+        slot - now
+    }
+
+    pub fn record(&mut self, feedback: Feedback) {
+        if !self.adaptive {
+            return;
+        }
+
+        self.delay = match feedback {
+            Feedback::Success(elapsed) if elapsed < FAST_RESPONSE => self.delay.mul_f64(0.9),
+            Feedback::Success(elapsed) if elapsed > SLOW_RESPONSE => self.delay.mul_f64(1.5),
+            Feedback::Success(_) => self.delay,
+            Feedback::Throttled => self.delay * 2,
+        }
+        .clamp(ADAPTIVE_MIN, ADAPTIVE_MAX);
+    }
+}
+
+/// Waits for the next request slot of a limiter shared between tasks.
+pub async fn throttle(limiter: &Mutex<RateLimiter>, clock: &impl Clock) {
+    let wait = limiter.lock().unwrap().reserve(clock.now());
+    clock.sleep(wait).await;
+}
@@ -0,0 +1,179 @@
+use crate::output::{self, AtomicFile};
+use crate::traces;
+use sha2::{Digest, Sha256};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use url::Url;
+
+/// Whether TRACES responses are recorded to or replayed from a snapshot directory.
+#[derive(Debug)]
+enum Mode {
+    /// Records responses; with `resume`, complete recordings already in the directory are reused
+    /// instead of being fetched again.
+    Save {
+        dir: PathBuf,
+        resume: bool,
+    },
+    Replay(PathBuf),
+}
+
+static MODE: OnceLock<Mode> = OnceLock::new();
+
+/// Records every TRACES response under `save`, or answers every TRACES request from `replay`
+/// instead of the network. With `resume`, a save picks up where an interrupted one stopped.
+///
+/// Has no effect once the first request was made.
+pub fn configure(save: Option<PathBuf>, replay: Option<PathBuf>, resume: bool) {
+    let mode = match (save, replay) {
+        (_, Some(dir)) => Mode::Replay(dir),
+        (Some(dir), None) => Mode::Save { dir, resume },
+        (None, None) => return,
+    };
+    let _ = MODE.set(mode);
+}
+
+pub fn is_replaying() -> bool {
+    matches!(MODE.get(), Some(Mode::Replay(_)))
+}
+
+/// File a response is stored in: the URL's path below the TRACES base URL, with the query string
+/// as the file name, e.g. `establishments/FR/I/sort=operatorName&max=1000&offset=0.json`.
+fn snapshot_path(dir: &Path, url: &Url) -> PathBuf {
+    let base = Url::parse(traces::base_url()).ok();
+    let base_path = base.as_ref().map_or("", |b| b.path().trim_end_matches('/'));
+    let relative = url.path().strip_prefix(base_path).unwrap_or(url.path());
+    let mut path = dir.to_path_buf();
+    for segment in relative.split('/').filter(|s| !s.is_empty() && *s != "..") {
+        path.push(segment);
+    }
+    path.push(format!("{}.json", url.query().unwrap_or("index")));
+    path
+}
+
+/// File holding the SHA-256 of the response stored at `path`, written once the response is
+/// complete.
+fn checksum_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".sha256");
+    PathBuf::from(name)
+}
+
+fn checksum(body: &str) -> String {
+    format!("{:x}", Sha256::digest(body.as_bytes()))
+}
+
+/// A response as found in the snapshot directory.
+enum Recorded {
+    /// The response matches its checksum.
+    Complete(String),
+    /// Recorded before checksums were written, so it cannot be verified.
+    Unverified(String),
+    /// The response does not match its checksum, e.g. after a crash while writing it.
+    Partial,
+}
+
+async fn read_recorded(path: &Path) -> io::Result<Recorded> {
+    let body = tokio::fs::read_to_string(path).await?;
+    let expected = match tokio::fs::read_to_string(checksum_path(path)).await {
+        Ok(expected) => expected,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Recorded::Unverified(body)),
+        Err(e) => return Err(e),
+    };
+    Ok(if expected.trim() == checksum(&body) {
+        Recorded::Complete(body)
+    } else {
+        Recorded::Partial
+    })
+}
+
+/// Writes `contents` to a temporary file next to `path` and renames it into place, so that a
+/// crash never leaves a truncated file at `path`.
+async fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let temporary = output::partial_path(path);
+    tokio::fs::write(&temporary, contents).await?;
+    tokio::fs::rename(&temporary, path).await
+}
+
+/// Reads the recorded response for `url`, or `None` if not replaying. Responses not matching
+/// their checksum are an error rather than being replayed truncated.
+pub async fn replay(url: &Url) -> Option<io::Result<String>> {
+    let Some(Mode::Replay(dir)) = MODE.get() else {
+        return None;
+    };
+    let path = snapshot_path(dir, url);
+    Some(match read_recorded(&path).await {
+        Ok(Recorded::Complete(body) | Recorded::Unverified(body)) => Ok(body),
+        Ok(Recorded::Partial) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} is incomplete; record it again with --save-raw --resume-raw",
+                path.display()
+            ),
+        )),
+        Err(e) => Err(e),
+    })
+}
+
+/// Reads the recorded response for `url` when resuming a save and the recording is complete.
+/// Missing, partial and unverifiable recordings are fetched again.
+pub async fn resume(url: &Url) -> Option<String> {
+    let Some(Mode::Save { dir, resume: true }) = MODE.get() else {
+        return None;
+    };
+    let path = snapshot_path(dir, url);
+    match read_recorded(&path).await {
+        Ok(Recorded::Complete(body)) => Some(body),
+        Ok(Recorded::Partial | Recorded::Unverified(_)) => {
+            eprintln!("Fetching {} again: incomplete", path.display());
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+/// Records the response for `url`, if saving, followed by its checksum.
+pub async fn save(url: &Url, body: &str) -> io::Result<()> {
+    let Some(Mode::Save { dir, .. }) = MODE.get() else {
+        return Ok(());
+    };
+    let path = snapshot_path(dir, url);
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    write_atomically(&path, body).await?;
+    write_atomically(&checksum_path(&path), &checksum(body)).await
+}
+
+/// A response recorded as it arrives, if saving. Dropping it unfinished, as when the request
+/// fails halfway, leaves no recording behind.
+pub struct Recording {
+    path: PathBuf,
+    file: AtomicFile,
+}
+
+impl Recording {
+    /// Starts recording the response for `url`, or returns `None` if not saving.
+    pub fn start(url: &Url) -> io::Result<Option<Recording>> {
+        let Some(Mode::Save { dir, .. }) = MODE.get() else {
+            return Ok(None);
+        };
+        let path = snapshot_path(dir, url);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = AtomicFile::create(&path)?;
+        Ok(Some(Recording { path, file }))
+    }
+
+    pub fn write(&mut self, chunk: &[u8]) -> io::Result<()> {
+        self.file.write_all(chunk)
+    }
+
+    /// Puts the complete response in place, followed by its SHA-256 `digest` as checksum.
+    pub fn finish(self, digest: &[u8]) -> io::Result<()> {
+        self.file.commit()?;
+        let checksum: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+        output::write_atomically(&checksum_path(&self.path), checksum)
+    }
+}
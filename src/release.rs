@@ -0,0 +1,105 @@
+use crate::output;
+use serde::*;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// `--dataset-version` value asking for a version derived from the current date.
+pub const DATE_VERSION: &str = "date";
+
+/// Name part replacing the version in the `latest` links.
+const LATEST: &str = "latest";
+
+/// Turns the `--dataset-version` argument into a version, deriving `YYYY.MM.DD` (UTC) from the
+/// current date if asked to.
+pub fn resolve_version(version: &str) -> String {
+    if version == DATE_VERSION {
+        let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+        now[..10].replace('-', ".")
+    } else {
+        version.to_owned()
+    }
+}
+
+/// Inserts `-{version}` before the extensions of the file name, e.g. `data.csv.gz` becomes
+/// `data-2024.05.01.csv.gz`.
+pub fn versioned_path(path: &Path, version: &str) -> PathBuf {
+    let Some(name) = path.file_name().map(|n| n.to_string_lossy().into_owned()) else {
+        return path.to_path_buf();
+    };
+    let name = match name.split_once('.') {
+        Some((stem, extensions)) => format!("{stem}-{version}.{extensions}"),
+        None => format!("{name}-{version}"),
+    };
+    path.with_file_name(name)
+}
+
+/// Renames the exported files to their versioned names.
+pub fn stamp(written: &mut [(PathBuf, usize)], version: &str) -> std::io::Result<()> {
+    for (path, _) in written.iter_mut() {
+        let versioned = versioned_path(path, version);
+        std::fs::rename(&*path, &versioned)?;
+        *path = versioned;
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Debug)]
+struct LatestFile {
+    /// Stable name of the file, as a `latest` link points to it.
+    name: String,
+    /// The versioned file.
+    file: String,
+    records: usize,
+}
+
+#[derive(Serialize, Debug)]
+struct Latest {
+    dataset_version: String,
+    files: Vec<LatestFile>,
+}
+
+/// Points the `latest` names of the exported files at this release: writes `manifest`, listing
+/// the version and its files, and on Unix also replaces a `-latest` symlink next to each file.
+pub fn update_latest(
+    manifest: &Path,
+    written: &[(PathBuf, usize)],
+    version: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file_name = |p: &Path| {
+        p.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+
+    let mut files = vec![];
+    for (path, records) in written {
+        let name = file_name(path).replacen(&format!("-{version}"), "", 1);
+        let link = versioned_path(&path.with_file_name(&name), LATEST);
+        link_latest(&link, Path::new(&file_name(path)))?;
+        files.push(LatestFile {
+            name,
+            file: file_name(path),
+            records: *records,
+        });
+    }
+
+    let latest = Latest {
+        dataset_version: version.to_owned(),
+        files,
+    };
+    output::write_atomically(manifest, serde_json::to_string_pretty(&latest)?)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn link_latest(link: &Path, target: &Path) -> std::io::Result<()> {
+    if link.symlink_metadata().is_ok() {
+        std::fs::remove_file(link)?;
+    }
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn link_latest(_link: &Path, _target: &Path) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,204 @@
+use crate::coverage;
+use crate::diff::{Change, ChangeKind};
+use crate::manifest::RunManifest;
+use crate::output;
+use crate::stats::StageCounts;
+use serde::*;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Leaflet release loaded by the map preview.
+const LEAFLET_URL: &str = "https://unpkg.com/leaflet@1.9.4/dist";
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:2em}\
+th,td{border:1px solid #ccc;padding:.3em .6em}td.n{text-align:right}\
+#map{height:480px;margin-bottom:2em}";
+
+/// A new or changed point on the map preview.
+#[derive(Serialize, Debug)]
+struct MapPoint<'a> {
+    code: &'a str,
+    name: &'a str,
+    change: ChangeKind,
+    lat: f64,
+    lng: f64,
+}
+
+/// Escapes text for HTML element content and attribute values.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes a standalone HTML page summarizing the run for review before publishing: stage counts,
+/// per-country coverage, a map of added and renamed codes, and the establishments that failed.
+pub fn write_report(
+    path: &Path,
+    manifest: &RunManifest,
+    changes: &[Change],
+    stage_counts: &StageCounts,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let generated = humantime::format_rfc3339_seconds(SystemTime::now());
+    let mut html = String::new();
+    writeln!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{name} run report</title>\n\
+         <link rel=\"stylesheet\" href=\"{LEAFLET_URL}/leaflet.css\">\n\
+         <style>{STYLE}</style>\n</head>\n<body>\n\
+         <h1>{name} run report</h1>\n<p>Generated {generated}.</p>",
+        name = env!("CARGO_PKG_NAME"),
+    )?;
+
+    html.push_str("<h2>Summary</h2>\n<table>\n");
+    let stages = [
+        ("non-food", stage_counts.non_food),
+        ("carried over", stage_counts.carried_over),
+        ("fetched", stage_counts.fetched),
+        ("blocklisted", stage_counts.blocklisted),
+        ("Great Britain (pre-Brexit)", stage_counts.great_britain),
+        ("missing name", stage_counts.missing_name),
+        ("unknown country", stage_counts.unknown_country),
+        ("geocoded", stage_counts.geocoded),
+        ("skipped", stage_counts.skipped),
+        ("errors", stage_counts.errors),
+        ("deduplicated", stage_counts.deduplicated),
+        ("exported", stage_counts.exported),
+    ];
+    for (stage, count) in stages {
+        writeln!(
+            html,
+            "<tr><th>{stage}</th><td class=\"n\">{count}</td></tr>"
+        )?;
+    }
+    let count = |kind: ChangeKind| changes.iter().filter(|c| c.change == kind).count();
+    writeln!(
+        html,
+        "<tr><th>added</th><td class=\"n\">{}</td></tr>\n\
+         <tr><th>removed</th><td class=\"n\">{}</td></tr>\n\
+         <tr><th>renamed</th><td class=\"n\">{}</td></tr>\n</table>",
+        count(ChangeKind::Added),
+        count(ChangeKind::Removed),
+        count(ChangeKind::Updated)
+    )?;
+
+    html.push_str(
+        "<h2>Countries</h2>\n<table>\n<tr><th>Country</th><th>Establishments</th>\
+         <th>Geocoded</th><th>Coverage</th><th>Skipped</th><th>Errors</th></tr>\n",
+    );
+    for r in coverage::coverage(manifest)
+        .iter()
+        .filter(|r| r.section == "total")
+    {
+        writeln!(
+            html,
+            "<tr><td>{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td>\
+             <td class=\"n\">{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td></tr>",
+            escape(&r.country),
+            r.establishments,
+            r.geocoded,
+            r.share(),
+            r.skipped,
+            r.errors
+        )?;
+    }
+    html.push_str("</table>\n");
+
+    let codes: HashMap<&str, _> = manifest
+        .countries
+        .values()
+        .flat_map(|c| c.packager_codes.iter())
+        .map(|p| (p.code.as_str(), p))
+        .collect();
+    let points: Vec<MapPoint> = changes
+        .iter()
+        .filter(|c| c.change != ChangeKind::Removed)
+        .filter_map(|c| {
+            let p = codes.get(c.code.as_str())?;
+            Some(MapPoint {
+                code: &p.code,
+                name: &p.name,
+                change: c.change,
+                lat: p.coordinates().lat(),
+                lng: p.coordinates().lng(),
+            })
+        })
+        .collect();
+    writeln!(
+        html,
+        "<h2>New and renamed codes</h2>\n<p>{} points.</p>\n<div id=\"map\"></div>",
+        points.len()
+    )?;
+
+    html.push_str("<h2>Failures</h2>\n");
+    let failures: Vec<_> = manifest
+        .countries
+        .iter()
+        .flat_map(|(country, c)| c.errors.iter().map(move |e| (country, e)))
+        .collect();
+    if failures.is_empty() {
+        html.push_str("<p>None.</p>\n");
+    } else {
+        html.push_str(
+            "<table>\n<tr><th>Country</th><th>Section</th><th>Operator</th>\
+             <th>Approval number</th><th>Error</th></tr>\n",
+        );
+        for (country, e) in failures {
+            writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(country),
+                escape(&e.section),
+                escape(e.operator_name.as_deref().unwrap_or_default()),
+                escape(e.approval_number.as_deref().unwrap_or_default()),
+                escape(&e.message)
+            )?;
+        }
+        html.push_str("</table>\n");
+    }
+
+    // `</` must not appear inside the script element.
+    let points = serde_json::to_string(&points)?.replace("</", "<\\/");
+    writeln!(
+        html,
+        "<script src=\"{LEAFLET_URL}/leaflet.js\"></script>\n<script>\n\
+         const points = {points};\n\
+         const map = L.map('map');\n\
+         L.tileLayer('https://tile.openstreetmap.org/{{z}}/{{x}}/{{y}}.png', {{\n\
+         \x20 maxZoom: 19,\n\
+         \x20 attribution: '&copy; OpenStreetMap contributors'\n\
+         }}).addTo(map);\n\
+         const label = p => {{\n\
+         \x20 const span = document.createElement('span');\n\
+         \x20 span.textContent = `${{p.code}}: ${{p.name}}`;\n\
+         \x20 return span;\n\
+         }};\n\
+         const markers = points.map(p => L.circleMarker([p.lat, p.lng], {{\n\
+         \x20 radius: 5,\n\
+         \x20 color: p.change === 'added' ? '#2a7' : '#e80'\n\
+         }}).bindTooltip(label(p)));\n\
+         if (markers.length) {{\n\
+         \x20 const group = L.featureGroup(markers).addTo(map);\n\
+         \x20 map.fitBounds(group.getBounds(), {{ maxZoom: 12 }});\n\
+         }} else {{\n\
+         \x20 map.setView([50, 10], 4);\n\
+         }}\n\
+         </script>\n</body>\n</html>"
+    )?;
+
+    output::write_atomically(path, html)?;
+    Ok(())
+}
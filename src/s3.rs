@@ -0,0 +1,59 @@
+use object_store::aws::AmazonS3Builder;
+use object_store::{ObjectStore, PutPayload};
+use std::path::{Component, Path, PathBuf};
+use url::Url;
+
+/// Where `path` is stored below `prefix`: relative paths keep their directories, so GeoJSON
+/// chunks stay next to their index, while absolute paths are reduced to the file name.
+fn object_key(prefix: &str, path: &Path) -> String {
+    let relative: Vec<_> = if path.is_relative() {
+        path.components()
+            .filter_map(|c| match c {
+                Component::Normal(s) => Some(s.to_string_lossy()),
+                _ => None,
+            })
+            .collect()
+    } else {
+        path.file_name()
+            .map(|n| n.to_string_lossy())
+            .into_iter()
+            .collect()
+    };
+    let prefix = prefix.trim_matches('/');
+    if prefix.is_empty() {
+        relative.join("/")
+    } else {
+        format!("{prefix}/{}", relative.join("/"))
+    }
+}
+
+/// Uploads `files` to the bucket and key prefix of an `s3://bucket/prefix` URL. Credentials,
+/// region and endpoint come from the usual `AWS_*` environment variables.
+pub async fn upload(
+    destination: &Url,
+    files: &[PathBuf],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if destination.scheme() != "s3" {
+        return Err(format!("{destination} is not an s3:// URL").into());
+    }
+    let store = AmazonS3Builder::from_env()
+        .with_url(destination.as_str())
+        .build()?;
+
+    for file in files {
+        let key = object_key(destination.path(), file);
+        let body = tokio::fs::read(file).await?;
+        store
+            .put(
+                &object_store::path::Path::from(key.as_str()),
+                PutPayload::from(body),
+            )
+            .await?;
+        eprintln!(
+            "Uploaded {} to s3://{}/{key}",
+            file.display(),
+            destination.host_str().unwrap_or_default()
+        );
+    }
+    Ok(())
+}
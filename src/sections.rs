@@ -0,0 +1,169 @@
+use crate::cli::Args;
+use crate::ids::CountryCode;
+use crate::output::AtomicFile;
+use crate::{fetch_section_names, fetch_valid_categories_by_countries};
+use serde::*;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Which TRACES classification sections list food for human consumption, maintained by hand from
+/// Annex III of Regulation (EC) No 853/2004 and the animal by-product plant types.
+const SECTIONS_CSV: &str = include_str!("../data/sections.csv");
+
+#[derive(Deserialize, Debug)]
+struct SectionRow {
+    code: String,
+    food: YesNo,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum YesNo {
+    Yes,
+    No,
+}
+
+/// Section codes mapped to whether they are relevant to Open Food Facts.
+#[derive(Debug)]
+pub struct SectionMap {
+    food: HashMap<String, bool>,
+}
+
+impl SectionMap {
+    /// Reads a mapping with `code,food` columns (`yes` or `no`), or the bundled one if `path` is
+    /// `None`.
+    pub fn load(path: Option<&Path>) -> Result<SectionMap, Box<dyn std::error::Error>> {
+        let text = match path {
+            Some(path) => std::fs::read_to_string(path)?,
+            None => SECTIONS_CSV.to_owned(),
+        };
+        let mut rdr = csv::Reader::from_reader(text.as_bytes());
+        let mut food = HashMap::new();
+        for row in rdr.deserialize() {
+            let row: SectionRow = row?;
+            food.insert(row.code.trim().to_uppercase(), row.food == YesNo::Yes);
+        }
+        Ok(SectionMap { food })
+    }
+
+    /// Whether establishments of `section` list food, or `None` if the mapping does not know the
+    /// section.
+    pub fn is_food(&self, section: &str) -> Option<bool> {
+        self.food.get(&section.trim().to_uppercase()).copied()
+    }
+}
+
+/// Where `sections` keeps the last listing it fetched.
+const CACHE_FILE: &str = "sections-cache.json";
+/// How long a cached listing is used before fetching it again.
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SectionCount {
+    code: String,
+    name: Option<String>,
+    establishments: i32,
+}
+
+/// Sections and establishment counts of every country, as listed when fetched.
+#[derive(Serialize, Deserialize, Debug)]
+struct SectionsCache {
+    fetched_at: String,
+    lang: String,
+    countries: BTreeMap<String, Vec<SectionCount>>,
+}
+
+impl SectionsCache {
+    /// The cached listing, if there is one in `lang` younger than the TTL.
+    fn load(lang: &str) -> Option<SectionsCache> {
+        let file = File::open(CACHE_FILE).ok()?;
+        let cache: SectionsCache = serde_json::from_reader(BufReader::new(file)).ok()?;
+        let fetched_at = humantime::parse_rfc3339(&cache.fetched_at).ok()?;
+        let age = SystemTime::now().duration_since(fetched_at).ok()?;
+        (cache.lang == lang && age < CACHE_TTL).then_some(cache)
+    }
+
+    async fn fetch(args: &Args) -> Result<SectionsCache, Box<dyn std::error::Error>> {
+        let categories = fetch_valid_categories_by_countries(
+            args.include_third_countries,
+            &args.include_statuses,
+        )
+        .await?;
+        let names = fetch_section_names(&args.lang).await?;
+        let mut countries: BTreeMap<String, Vec<SectionCount>> = BTreeMap::new();
+        for c in categories {
+            let code = c.classification_section_id.code.to_string();
+            countries
+                .entry(c.country.code.to_string())
+                .or_default()
+                .push(SectionCount {
+                    name: names.get(&code).cloned(),
+                    code,
+                    establishments: c.number_of_establishments,
+                });
+        }
+        for sections in countries.values_mut() {
+            sections.sort_by(|a, b| a.code.cmp(&b.code));
+        }
+        Ok(SectionsCache {
+            fetched_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            lang: args.lang.clone(),
+            countries,
+        })
+    }
+
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = AtomicFile::create(Path::new(CACHE_FILE))?;
+        serde_json::to_writer_pretty(&mut file, self)?;
+        file.commit()?;
+        Ok(())
+    }
+}
+
+/// Prints the classification sections TRACES lists for `country` with their establishment
+/// counts and whether the bundled mapping counts them as food. All countries' listings are
+/// fetched at once and cached, so looking up further countries is instant.
+pub async fn print_sections(
+    args: &Args,
+    country: &CountryCode,
+    refresh: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let cache = match SectionsCache::load(&args.lang).filter(|_| !refresh) {
+        Some(cache) => cache,
+        None => {
+            let cache = SectionsCache::fetch(args).await?;
+            cache.save()?;
+            cache
+        }
+    };
+    let Some(sections) = cache.countries.get(country.as_str()) else {
+        return Err(format!("TRACES lists no establishments for {country}").into());
+    };
+    let food = SectionMap::load(None)?;
+
+    println!("Sections of {country} as of {}:", cache.fetched_at);
+    println!(
+        "{:<8} {:>14} {:<5} name",
+        "section", "establishments", "food"
+    );
+    for s in sections {
+        let is_food = match food.is_food(&s.code) {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "?",
+        };
+        println!(
+            "{:<8} {:>14} {:<5} {}",
+            s.code,
+            s.establishments,
+            is_food,
+            s.name.as_deref().unwrap_or_default()
+        );
+    }
+    let total: i32 = sections.iter().map(|s| s.establishments).sum();
+    println!("{:<8} {:>14}", "total", total);
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use crate::cli::Args;
+use crate::geojson_chunks;
+use crate::manifest::RunManifest;
+use crate::nuts::NutsIndex;
+use crate::overrides::Overrides;
+use crate::{prepare_export, PackagerCode};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use geojson::FeatureCollection;
+use serde::*;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// The packager codes of the last run as exported, by country.
+struct Dataset {
+    countries: BTreeMap<String, Vec<PackagerCode>>,
+}
+
+impl Dataset {
+    fn codes(&self) -> impl Iterator<Item = (&String, &PackagerCode)> {
+        self.countries
+            .iter()
+            .flat_map(|(country, codes)| codes.iter().map(move |p| (country, p)))
+    }
+}
+
+#[derive(Serialize)]
+struct CodeRecord<'a> {
+    country: &'a str,
+    #[serde(flatten)]
+    code: &'a PackagerCode,
+}
+
+#[derive(Deserialize)]
+struct CodesQuery {
+    country: Option<String>,
+}
+
+async fn packager_codes(
+    State(dataset): State<Arc<Dataset>>,
+    Query(query): Query<CodesQuery>,
+) -> Response {
+    let country = query.country.map(|c| c.to_uppercase());
+    let records: Vec<CodeRecord> = dataset
+        .codes()
+        .filter(|(c, _)| country.as_ref().is_none_or(|country| *c == country))
+        .map(|(country, code)| CodeRecord { country, code })
+        .collect();
+    Json(records).into_response()
+}
+
+/// Rows of a single code; several if operators share it.
+async fn packager_code(State(dataset): State<Arc<Dataset>>, Path(code): Path<String>) -> Response {
+    let records: Vec<CodeRecord> = dataset
+        .codes()
+        .filter(|(_, p)| p.code.eq_ignore_ascii_case(&code))
+        .map(|(country, code)| CodeRecord { country, code })
+        .collect();
+    if records.is_empty() {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("unknown packager code {code:?}"),
+        )
+            .into_response();
+    }
+    Json(records).into_response()
+}
+
+async fn geojson(State(dataset): State<Arc<Dataset>>) -> Response {
+    let collection = FeatureCollection {
+        bbox: None,
+        features: dataset
+            .codes()
+            .map(|(_, p)| geojson_chunks::feature(p))
+            .collect(),
+        foreign_members: None,
+    };
+    (
+        [("content-type", "application/geo+json")],
+        collection.to_string(),
+    )
+        .into_response()
+}
+
+/// Serves the packager codes of the last run, with the same export options as a crawl, until the
+/// process is stopped.
+pub async fn serve(args: &Args, listen: SocketAddr) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(run_manifest) = RunManifest::load(&args.manifest)? else {
+        return Err(format!("no run manifest at {}", args.manifest.display()).into());
+    };
+    let nuts_index = match &args.nuts_boundaries {
+        Some(path) => Some(NutsIndex::load(path)?),
+        None => None,
+    };
+    let overrides = Overrides::load(&args.overrides)?;
+    let (exported, _) = prepare_export(args, &overrides, &run_manifest, nuts_index.as_ref());
+    let dataset = Dataset {
+        countries: exported
+            .countries
+            .iter()
+            .map(|(country, c)| (country.clone(), c.packager_codes.clone()))
+            .collect(),
+    };
+
+    let app = Router::new()
+        .route("/packager-codes", get(packager_codes))
+        .route("/packager-codes/*code", get(packager_code))
+        .route("/geojson", get(geojson))
+        .with_state(Arc::new(dataset));
+    let listener = tokio::net::TcpListener::bind(listen).await?;
+    eprintln!("Serving {} on http://{listen}", args.manifest.display());
+    axum::serve(listener, app).await?;
+    Ok(())
+}
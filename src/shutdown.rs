@@ -0,0 +1,23 @@
+use tokio_util::sync::CancellationToken;
+
+/// Exit status of a process ended by SIGINT.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Handles Ctrl-C for the rest of the process: the first one cancels the returned token, so
+/// stages stop taking on new work and finish what is in flight; a second one exits at once.
+pub fn install() -> CancellationToken {
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        eprintln!("Interrupted; finishing in-flight work, press Ctrl-C again to exit at once");
+        cancel.cancel();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("Interrupted again; exiting");
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+    });
+    token
+}
@@ -0,0 +1,179 @@
+use crate::ids::{ApprovalNumber, OperatorId, SectionCode};
+use crate::manifest::RunManifest;
+use crate::metadata::RunInfo;
+use crate::output;
+use rusqlite::types::ToSqlOutput;
+use rusqlite::{params, Connection, ToSql};
+use std::path::Path;
+
+const SCHEMA: &str = "
+CREATE TABLE packager_codes (
+    country TEXT NOT NULL,
+    code TEXT NOT NULL,
+    code_raw TEXT,
+    name TEXT NOT NULL,
+    lat REAL NOT NULL,
+    lng REAL NOT NULL,
+    section TEXT NOT NULL,
+    section_name TEXT NOT NULL,
+    geocode_tier TEXT,
+    nuts2 TEXT,
+    nuts3 TEXT,
+    country_name TEXT,
+    disambiguator INTEGER,
+    in_water INTEGER,
+    query_script TEXT,
+    geocoded_at TEXT
+);
+CREATE TABLE skipped (
+    country TEXT NOT NULL,
+    operator_id INTEGER NOT NULL,
+    operator_name TEXT,
+    approval_number TEXT,
+    section TEXT NOT NULL,
+    reason TEXT NOT NULL
+);
+CREATE TABLE errors (
+    country TEXT NOT NULL,
+    operator_id INTEGER NOT NULL,
+    operator_name TEXT,
+    approval_number TEXT,
+    section TEXT NOT NULL,
+    message TEXT NOT NULL
+);
+CREATE TABLE name_history (
+    code TEXT NOT NULL,
+    previous_name TEXT NOT NULL,
+    name TEXT NOT NULL,
+    changed_at INTEGER NOT NULL
+);
+CREATE TABLE run (
+    generated_at TEXT NOT NULL,
+    generator TEXT NOT NULL,
+    traces_base_url TEXT NOT NULL,
+    dataset_version TEXT,
+    config TEXT NOT NULL
+);
+";
+
+/// Writes the run's packager codes, skipped establishments, errors and operator name history to a
+/// fresh database, along with the configuration it was made with as JSON.
+pub fn write_sqlite(
+    path: &Path,
+    manifest: &RunManifest,
+    run: &RunInfo,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Built under the partial name and moved into place once complete, like the other exports.
+    let partial = output::partial_path(path);
+    if partial.exists() {
+        std::fs::remove_file(&partial)?;
+    }
+
+    let mut conn = Connection::open(&partial)?;
+    let tx = conn.transaction()?;
+    tx.execute_batch(SCHEMA)?;
+    {
+        let mut insert_code = tx.prepare(
+            "INSERT INTO packager_codes
+                (country, code, code_raw, name, lat, lng, section, section_name, geocode_tier,
+                 nuts2, nuts3, country_name, disambiguator, in_water, query_script, geocoded_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+        )?;
+        let mut insert_skipped = tx.prepare(
+            "INSERT INTO skipped
+                (country, operator_id, operator_name, approval_number, section, reason)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        let mut insert_error = tx.prepare(
+            "INSERT INTO errors
+                (country, operator_id, operator_name, approval_number, section, message)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+
+        for (country, c) in &manifest.countries {
+            for p in &c.packager_codes {
+                let coordinates = p.coordinates();
+                insert_code.execute(params![
+                    country,
+                    p.code,
+                    p.code_raw,
+                    p.name,
+                    coordinates.lat(),
+                    coordinates.lng(),
+                    p.section,
+                    p.section_name,
+                    p.geocode_tier.map(|t| t.as_str()),
+                    p.nuts2,
+                    p.nuts3,
+                    p.country_name,
+                    p.disambiguator,
+                    p.in_water,
+                    p.query_script.map(|s| s.as_str()),
+                    p.geocoded_at,
+                ])?;
+            }
+            for s in &c.skipped {
+                insert_skipped.execute(params![
+                    country,
+                    s.operator_id,
+                    s.operator_name,
+                    s.approval_number,
+                    s.section,
+                    s.reason.as_str(),
+                ])?;
+            }
+            for e in &c.errors {
+                insert_error.execute(params![
+                    country,
+                    e.operator_id,
+                    e.operator_name,
+                    e.approval_number,
+                    e.section,
+                    e.message,
+                ])?;
+            }
+        }
+
+        let mut insert_name_change = tx.prepare(
+            "INSERT INTO name_history (code, previous_name, name, changed_at)
+             VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for n in &manifest.name_history {
+            insert_name_change.execute(params![n.code, n.previous_name, n.name, n.changed_at])?;
+        }
+
+        tx.execute(
+            "INSERT INTO run (generated_at, generator, traces_base_url, dataset_version, config)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                run.generated_at,
+                run.generator,
+                run.traces_base_url,
+                run.dataset_version,
+                serde_json::to_string(&run.config)?,
+            ],
+        )?;
+    }
+    tx.commit()?;
+    conn.close().map_err(|(_, e)| e)?;
+    std::fs::rename(&partial, path)?;
+    Ok(())
+}
+
+impl ToSql for OperatorId {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.get()))
+    }
+}
+
+impl ToSql for SectionCode {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl ToSql for ApprovalNumber {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
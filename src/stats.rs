@@ -0,0 +1,38 @@
+use crate::output;
+use serde::*;
+use std::path::Path;
+
+/// Record counts after each stage of a crawl, for sanity checks in downstream CI.
+#[derive(Serialize, Debug, Default)]
+pub struct StageCounts {
+    /// Establishments TRACES lists in sections left out by `--food-only`.
+    pub non_food: usize,
+    /// Packager codes of countries carried over unchanged from the previous run.
+    pub carried_over: usize,
+    /// Establishments fetched from TRACES or imported, after `--limit`.
+    pub fetched: usize,
+    /// Establishments removed by the blocklist.
+    pub blocklisted: usize,
+    /// EU-approved establishments in Great Britain listed from before Brexit.
+    pub great_britain: usize,
+    /// Establishments listed without an operator name, however `--missing-names` handled them.
+    pub missing_name: usize,
+    /// Establishments set aside because their code would start with an unknown country code.
+    pub unknown_country: usize,
+    /// Establishments geocoded into packager codes.
+    pub geocoded: usize,
+    /// Establishments skipped for a missing approval number or an unusable address.
+    pub skipped: usize,
+    pub errors: usize,
+    /// Rows removed or withheld by `--duplicate-codes`.
+    pub deduplicated: usize,
+    /// Packager codes in the exported files, including carried over ones.
+    pub exported: usize,
+}
+
+impl StageCounts {
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        output::write_atomically(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
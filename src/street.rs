@@ -0,0 +1,220 @@
+/// A street value with any trailing locality split off.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SplitStreet<'a> {
+    pub street: &'a str,
+    pub postal_code: Option<String>,
+    pub city: Option<String>,
+}
+
+/// Splits street values that carry the whole address, such as `Rue X 12, 75001 Paris`, into the
+/// street and the postal code and city found after it.
+///
+/// The last comma-separated segment after the first that starts with the address's known postal
+/// code, equals its known city, or starts with something shaped like a postal code is taken as the
+/// locality. Everything from that segment on, including a trailing country name, is removed
+/// from the street.
+pub fn split_street<'a>(
+    value: &'a str,
+    postal_code: Option<&str>,
+    city: Option<&str>,
+) -> SplitStreet<'a> {
+    let unchanged = SplitStreet {
+        street: value.trim(),
+        ..Default::default()
+    };
+
+    let mut offset = 0;
+    let mut segments = vec![];
+    for segment in value.split(',') {
+        segments.push((offset, segment.trim()));
+        offset += segment.len() + 1;
+    }
+    let Some(&(start, locality)) = segments
+        .iter()
+        .skip(1)
+        .rfind(|(_, s)| is_locality(s, postal_code, city))
+    else {
+        return unchanged;
+    };
+
+    let street = value[..start - 1].trim();
+    if street.is_empty() {
+        return unchanged;
+    }
+    let (found_postal_code, found_city) = parse_locality(locality, postal_code);
+    SplitStreet {
+        street,
+        postal_code: found_postal_code,
+        city: found_city,
+    }
+}
+
+fn compact(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn is_locality(segment: &str, postal_code: Option<&str>, city: Option<&str>) -> bool {
+    let known_postal_code = postal_code
+        .map(compact)
+        .filter(|p| !p.is_empty())
+        .is_some_and(|p| compact(segment).starts_with(&p));
+    let known_city =
+        city.is_some_and(|c| !c.trim().is_empty() && segment.eq_ignore_ascii_case(c.trim()));
+    known_postal_code || known_city || parse_locality(segment, None).0.is_some()
+}
+
+/// Reads `POSTCODE CITY` from a segment. Postcodes are a leading token with at least three
+/// digits (`75001`, `00-950`, `LV-1010`), optionally followed by a short suffix (`123 45`,
+/// `1000 AB`, `SW1A 1AA`).
+fn parse_locality(
+    segment: &str,
+    known_postal_code: Option<&str>,
+) -> (Option<String>, Option<String>) {
+    let tokens: Vec<&str> = segment.split_whitespace().collect();
+    let looks_like_code = |t: &str| {
+        t.chars().filter(|c| c.is_ascii_digit()).count() >= 3
+            && t.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+
+    let mut taken = 0;
+    if let Some(known) = known_postal_code.map(compact).filter(|p| !p.is_empty()) {
+        let mut prefix = String::new();
+        for t in &tokens {
+            prefix.push_str(&t.to_lowercase());
+            taken += 1;
+            if prefix.len() >= known.len() {
+                break;
+            }
+        }
+        if prefix != known {
+            taken = 0;
+        }
+    }
+    if taken == 0 && tokens.first().is_some_and(|t| looks_like_code(t)) {
+        taken = 1;
+        if let Some(next) = tokens.get(1) {
+            let suffix = next.len() <= 3
+                && (next.chars().all(|c| c.is_ascii_digit())
+                    || next.chars().all(|c| c.is_ascii_uppercase())
+                    || next.chars().any(|c| c.is_ascii_digit())
+                        && next.chars().all(|c| c.is_ascii_alphanumeric()));
+            if suffix && tokens.len() > 2 {
+                taken = 2;
+            }
+        }
+    }
+
+    let postal_code = Some(tokens[..taken].join(" ")).filter(|p| !p.is_empty());
+    let city = Some(tokens[taken..].join(" ")).filter(|c| !c.is_empty());
+    if postal_code.is_some() || known_postal_code.is_some() {
+        (postal_code, city)
+    } else {
+        (None, None)
+    }
+}
+
+/// Common street type abbreviations and their spelled-out form, per language. Matching ignores
+/// case and the trailing dot.
+fn abbreviations(lang: &str) -> &'static [(&'static str, &'static str)] {
+    match lang {
+        "fr" => &[
+            ("av", "avenue"),
+            ("bd", "boulevard"),
+            ("bld", "boulevard"),
+            ("ch", "chemin"),
+            ("imp", "impasse"),
+            ("pl", "place"),
+            ("r", "rue"),
+            ("rte", "route"),
+            ("za", "zone artisanale"),
+            ("zi", "zone industrielle"),
+        ],
+        "de" => &[("str", "straße"), ("pl", "platz")],
+        "nl" => &[("str", "straat"), ("ln", "laan")],
+        "es" => &[
+            ("av", "avenida"),
+            ("avda", "avenida"),
+            ("c", "calle"),
+            ("c/", "calle"),
+            ("ctra", "carretera"),
+            ("pol", "polígono"),
+            ("pza", "plaza"),
+        ],
+        "it" => &[
+            ("v", "via"),
+            ("p.za", "piazza"),
+            ("pza", "piazza"),
+            ("loc", "località"),
+        ],
+        "pt" => &[("av", "avenida"), ("r", "rua"), ("estr", "estrada")],
+        "en" => &[
+            ("rd", "road"),
+            ("st", "street"),
+            ("ave", "avenue"),
+            ("ln", "lane"),
+        ],
+        _ => &[],
+    }
+}
+
+/// Spells out street type abbreviations such as `av.` or `Hauptstr.` in a street written in
+/// `lang`.
+pub fn expand_abbreviations(street: &str, lang: &str) -> String {
+    let table = abbreviations(lang);
+    street
+        .split(' ')
+        .map(|word| {
+            let bare = word.trim_end_matches(['.', ',']).to_lowercase();
+            let trailing = &word[word.trim_end_matches(',').len()..];
+            if let Some((_, long)) = table.iter().find(|(short, _)| *short == bare) {
+                return format!("{long}{trailing}");
+            }
+            // German and Dutch write the street type into the name, as in `Hauptstr.`.
+            if matches!(lang, "de" | "nl") && bare.len() > 3 && word.contains("str.") {
+                if let Some((_, long)) = table.iter().find(|(short, _)| *short == "str") {
+                    return word.replacen("str.", long, 1);
+                }
+            }
+            word.to_owned()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The street without its house number, e.g. `Rue de la Paix` for `12 bis, Rue de la Paix`, or
+/// `None` if there is no number to drop. Only numbers leading or ending the street are taken as
+/// house numbers, so `Straße des 17. Juni` keeps its date.
+pub fn without_house_number(street: &str) -> Option<String> {
+    let words: Vec<&str> = street.split([' ', ',']).filter(|w| !w.is_empty()).collect();
+    let is_number = |w: &str| w.chars().next().is_some_and(|c| c.is_ascii_digit());
+    let is_marker = |w: &str| {
+        matches!(
+            w.to_lowercase().as_str(),
+            "n°" | "nº" | "n." | "nr." | "no."
+        )
+    };
+    let is_suffix = |w: &str| matches!(w.to_lowercase().as_str(), "bis" | "ter");
+
+    let mut start = 0;
+    while start < words.len() && (is_number(words[start]) || is_marker(words[start])) {
+        start += 1;
+        while start < words.len() && is_suffix(words[start]) {
+            start += 1;
+        }
+    }
+    let mut end = words.len();
+    while end > start && (is_number(words[end - 1]) || is_suffix(words[end - 1])) {
+        end -= 1;
+        while end > start && is_marker(words[end - 1]) {
+            end -= 1;
+        }
+    }
+    if (start == 0 && end == words.len()) || start == end {
+        return None;
+    }
+    Some(words[start..end].join(" "))
+}
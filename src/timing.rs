@@ -0,0 +1,71 @@
+use serde::*;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Wall time spent in each pipeline stage, and the time spent on each country.
+#[derive(Serialize, Debug, Default)]
+pub struct StageTimings {
+    #[serde(serialize_with = "as_secs")]
+    pub categories: Duration,
+    #[serde(serialize_with = "as_secs")]
+    pub fetch: Duration,
+    #[serde(serialize_with = "as_secs")]
+    pub geocode: Duration,
+    #[serde(serialize_with = "as_secs")]
+    pub export: Duration,
+    pub countries: BTreeMap<String, CountryTimings>,
+}
+
+/// Time spent on a country's requests and geocoding jobs, added up. Countries are fetched and
+/// geocoded concurrently, so these overlap and add up to more than the wall time of the stages.
+#[derive(Serialize, Debug, Default, Clone, Copy)]
+pub struct CountryTimings {
+    #[serde(rename = "fetch_cumulative", serialize_with = "as_secs")]
+    pub fetch: Duration,
+    #[serde(rename = "geocode_cumulative", serialize_with = "as_secs")]
+    pub geocode: Duration,
+}
+
+impl StageTimings {
+    pub fn country(&mut self, country: &str) -> &mut CountryTimings {
+        self.countries.entry(country.to_owned()).or_default()
+    }
+
+    /// Prints the cumulative per-country times, slowest countries first, followed by the wall time
+    /// of each stage.
+    pub fn print_report(&self) {
+        let mut countries: Vec<_> = self.countries.iter().collect();
+        countries.sort_by_key(|(_, t)| std::cmp::Reverse(t.fetch + t.geocode));
+
+        eprintln!("Cumulative time per country, overlapping across concurrent work:");
+        eprintln!("{:<8} {:>10} {:>10}", "country", "fetch s", "geocode s");
+        for (country, t) in countries {
+            eprintln!(
+                "{:<8} {:>10.1} {:>10.1}",
+                country,
+                t.fetch.as_secs_f64(),
+                t.geocode.as_secs_f64()
+            );
+        }
+
+        eprintln!("Wall time per stage:");
+        let total = self.categories + self.fetch + self.geocode + self.export;
+        for (stage, time) in [
+            ("categories", self.categories),
+            ("fetch", self.fetch),
+            ("geocode", self.geocode),
+            ("export", self.export),
+        ] {
+            eprintln!(
+                "{:<10} {:>10.1}s {:>5.1}%",
+                stage,
+                time.as_secs_f64(),
+                100.0 * time.as_secs_f64() / total.as_secs_f64().max(f64::EPSILON)
+            );
+        }
+    }
+}
+
+fn as_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
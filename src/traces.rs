@@ -0,0 +1,536 @@
+pub mod client;
+mod json_array;
+pub mod urls;
+
+use crate::clock::{Clock, TokioClock};
+use crate::http;
+use crate::rate::{self, Feedback, RateLimiter};
+use crate::raw;
+use json_array::{ArrayElements, ArrayError};
+use reqwest::header::CONTENT_TYPE;
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use url::Url;
+use urls::Endpoints;
+
+/// Response header some deployments use to report the size of a paged listing.
+const TOTAL_COUNT_HEADER: &str = "X-Total-Count";
+
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Publication API all TRACES endpoints are built from.
+pub const DEFAULT_BASE_URL: &str =
+    "https://webgate.ec.europa.eu/tracesnt/directory/publication/establishment";
+
+static BASE_URL: OnceLock<String> = OnceLock::new();
+
+/// Points all endpoints at another deployment, e.g. a staging instance or a local mock server.
+///
+/// Has no effect once the first URL was built.
+pub fn configure_base_url(base_url: Option<String>) {
+    let base_url = base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_owned());
+    let _ = BASE_URL.set(base_url.trim_end_matches('/').to_owned());
+}
+
+pub fn base_url() -> &'static str {
+    BASE_URL.get_or_init(|| DEFAULT_BASE_URL.to_owned())
+}
+
+/// URL builder for the configured deployment.
+pub fn endpoints() -> Result<Endpoints, url::ParseError> {
+    Endpoints::new(base_url())
+}
+
+/// Kinds of TRACES requests, which take very different times to answer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestKind {
+    /// Paged listings and dictionaries, which can be several megabytes.
+    Listing,
+    /// The record of a single establishment.
+    Detail,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Timeouts {
+    listing: Duration,
+    detail: Duration,
+}
+
+static TIMEOUTS: OnceLock<Timeouts> = OnceLock::new();
+
+/// Sets how long each kind of request may take before it is abandoned and retried; defaults to
+/// 120 seconds for listings and 30 for details.
+///
+/// Has no effect once the first request was made.
+pub fn configure_timeouts(listing: Duration, detail: Duration) {
+    let _ = TIMEOUTS.set(Timeouts { listing, detail });
+}
+
+impl RequestKind {
+    fn timeout(self) -> Duration {
+        let timeouts = TIMEOUTS.get_or_init(|| Timeouts {
+            listing: Duration::from_secs(120),
+            detail: Duration::from_secs(30),
+        });
+        match self {
+            RequestKind::Listing => timeouts.listing,
+            RequestKind::Detail => timeouts.detail,
+        }
+    }
+}
+
+/// Interval between TRACES requests unless `--adaptive-rate` is given.
+pub const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+static RATE_LIMITER: OnceLock<Mutex<RateLimiter>> = OnceLock::new();
+
+/// Selects how TRACES requests are paced; defaults to one request per second.
+///
+/// Has no effect once the first request was made.
+pub fn configure_rate_limit(adaptive: bool) {
+    let limiter = if adaptive {
+        RateLimiter::adaptive()
+    } else {
+        RateLimiter::fixed(REQUEST_INTERVAL)
+    };
+    let _ = RATE_LIMITER.set(Mutex::new(limiter));
+}
+
+fn rate_limiter() -> &'static Mutex<RateLimiter> {
+    RATE_LIMITER.get_or_init(|| Mutex::new(RateLimiter::fixed(REQUEST_INTERVAL)))
+}
+
+/// Errors talking to TRACES.
+#[derive(Debug)]
+pub enum TracesError {
+    /// TRACES answered, but not with JSON (typically an HTML maintenance page served with 200).
+    UpstreamUnavailable {
+        url: Url,
+        reason: String,
+    },
+    Http(reqwest::Error),
+    /// The response could not be read from or written to the raw snapshot.
+    Snapshot {
+        url: Url,
+        source: std::io::Error,
+    },
+    /// The listing is not a JSON array of what was expected.
+    Parse {
+        url: Url,
+        reason: String,
+    },
+}
+
+impl TracesError {
+    /// Whether retrying the same request later may succeed.
+    fn is_transient(&self) -> bool {
+        match self {
+            TracesError::UpstreamUnavailable { .. } => true,
+            TracesError::Snapshot { .. } | TracesError::Parse { .. } => false,
+            TracesError::Http(e) => {
+                e.is_timeout()
+                    || e.is_connect()
+                    || e.status().is_some_and(|s| {
+                        s.is_server_error() || s == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    })
+            }
+        }
+    }
+}
+
+/// Whether `e` is a TRACES failure that persisted through all retries but may go away with a
+/// smaller request, such as a timeout or a server error.
+pub fn is_transient(e: &(dyn std::error::Error + 'static)) -> bool {
+    e.downcast_ref::<TracesError>()
+        .is_some_and(TracesError::is_transient)
+}
+
+impl fmt::Display for TracesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TracesError::UpstreamUnavailable { url, reason } => {
+                let url = http::redact(url);
+                write!(f, "TRACES upstream unavailable for {url}: {reason}")
+            }
+            TracesError::Http(e) => write!(f, "TRACES request failed: {e}"),
+            TracesError::Snapshot { url, source } => {
+                let url = http::redact(url);
+                write!(f, "raw snapshot of {url} unusable: {source}")
+            }
+            TracesError::Parse { url, reason } => {
+                let url = http::redact(url);
+                write!(f, "TRACES listing at {url} unreadable: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TracesError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TracesError::UpstreamUnavailable { .. } | TracesError::Parse { .. } => None,
+            TracesError::Http(e) => Some(e),
+            TracesError::Snapshot { source, .. } => Some(source),
+        }
+    }
+}
+
+impl From<reqwest::Error> for TracesError {
+    fn from(e: reqwest::Error) -> Self {
+        TracesError::Http(http::redact_error(e))
+    }
+}
+
+/// The page size to keep paging by after asking for `requested` records and being `served`
+/// fewer, while `remaining` from that offset on says the listing goes on: some endpoints cap
+/// `max` below what was asked without saying so. Otherwise `requested` is kept, and a short page
+/// is taken as the end of the listing.
+pub fn effective_page_size(requested: i32, served: usize, remaining: Option<usize>) -> i32 {
+    if served > 0 && served < requested as usize && remaining.is_some_and(|r| served < r) {
+        served as i32
+    } else {
+        requested
+    }
+}
+
+/// Fetches a TRACES JSON document as text, retrying transient failures with exponential backoff.
+///
+/// When replaying a raw snapshot the document is read from disk instead; when saving one, each
+/// successful response is recorded.
+pub async fn fetch_json_text(url: Url, kind: RequestKind) -> Result<String, TracesError> {
+    if let Some(replayed) = raw::replay(&url).await {
+        return replayed.map_err(|source| TracesError::Snapshot { url, source });
+    }
+    if let Some(body) = raw::resume(&url).await {
+        return Ok(body);
+    }
+
+    let body = with_retries(&TokioClock, rate_limiter(), || {
+        fetch_json_text_once(&url, kind.timeout())
+    })
+    .await?;
+    raw::save(&url, &body)
+        .await
+        .map_err(|source| TracesError::Snapshot {
+            url: url.clone(),
+            source,
+        })?;
+    Ok(body)
+}
+
+/// A page of a paged listing, deserialized element by element as the response arrives so that
+/// the page is never held as text, with the SHA-256 of the raw response.
+pub struct ListingPage<T> {
+    pub items: Vec<T>,
+    pub total: Option<usize>,
+    pub digest: Vec<u8>,
+}
+
+impl<T: DeserializeOwned> ListingPage<T> {
+    /// Reads a page recorded in a raw snapshot, which has no total.
+    fn from_recorded(url: &Url, body: &str) -> Result<ListingPage<T>, TracesError> {
+        let items = serde_json::from_str(body).map_err(|e| TracesError::Parse {
+            url: url.clone(),
+            reason: e.to_string(),
+        })?;
+        Ok(ListingPage {
+            items,
+            total: None,
+            digest: Sha256::digest(body.as_bytes()).to_vec(),
+        })
+    }
+}
+
+/// Like [`fetch_json_text`] for a paged listing, deserializing the records as they arrive, see
+/// [`ListingPage`], and reading the total count header. Raw snapshots do not record headers, so
+/// replayed pages never have a total. A response that fails halfway is retried like a failed
+/// request.
+pub async fn fetch_listing_page<T: DeserializeOwned>(
+    url: Url,
+    kind: RequestKind,
+) -> Result<ListingPage<T>, TracesError> {
+    if let Some(replayed) = raw::replay(&url).await {
+        let body = replayed.map_err(|source| TracesError::Snapshot {
+            url: url.clone(),
+            source,
+        })?;
+        return ListingPage::from_recorded(&url, &body);
+    }
+    if let Some(body) = raw::resume(&url).await {
+        return ListingPage::from_recorded(&url, &body);
+    }
+
+    with_retries(&TokioClock, rate_limiter(), || {
+        fetch_listing_page_once(&url, kind.timeout())
+    })
+    .await
+}
+
+/// Runs `request` at the pace of `limiter` until it succeeds, fails permanently, or ran out of
+/// attempts, doubling the delay between attempts each time.
+async fn with_retries<T, F, Fut>(
+    clock: &impl Clock,
+    limiter: &Mutex<RateLimiter>,
+    mut request: F,
+) -> Result<T, TracesError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, TracesError>>,
+{
+    let mut delay = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        rate::throttle(limiter, clock).await;
+
+        let started = clock.now();
+        let result = request().await;
+        let feedback = match &result {
+            Err(e) if e.is_transient() => Feedback::Throttled,
+            _ => Feedback::Success(clock.now() - started),
+        };
+        limiter.lock().unwrap().record(feedback);
+
+        match result {
+            Err(e) if attempt < MAX_ATTEMPTS && e.is_transient() => {
+                eprintln!("{e}; retrying in {}s", delay.as_secs());
+                clock.sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn fetch_json_text_once(url: &Url, timeout: Duration) -> Result<String, TracesError> {
+    let resp = http::get_with_timeout(url.clone(), timeout)
+        .await?
+        .error_for_status()?;
+    let content_type = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    let body = resp.text().await?;
+
+    let looks_like_html = body.trim_start().starts_with('<');
+    if looks_like_html || !(content_type.is_empty() || content_type.contains("json")) {
+        return Err(TracesError::UpstreamUnavailable {
+            url: url.clone(),
+            reason: format!("unexpected content type {content_type:?}"),
+        });
+    }
+
+    Ok(body)
+}
+
+/// Why a listing response could not be read.
+enum ListingError {
+    Array(ArrayError),
+    Element(serde_json::Error),
+}
+
+impl From<ArrayError> for ListingError {
+    fn from(e: ArrayError) -> Self {
+        ListingError::Array(e)
+    }
+}
+
+impl ListingError {
+    fn into_traces_error(self, url: &Url) -> TracesError {
+        let url = url.clone();
+        match self {
+            // Typically an HTML maintenance page served with 200.
+            ListingError::Array(ArrayError::NotAnArray { found: b'<' }) => {
+                TracesError::UpstreamUnavailable {
+                    url,
+                    reason: "HTML instead of JSON".to_owned(),
+                }
+            }
+            ListingError::Array(e) => TracesError::Parse {
+                url,
+                reason: e.to_string(),
+            },
+            ListingError::Element(e) => TracesError::Parse {
+                url,
+                reason: e.to_string(),
+            },
+        }
+    }
+}
+
+async fn fetch_listing_page_once<T: DeserializeOwned>(
+    url: &Url,
+    timeout: Duration,
+) -> Result<ListingPage<T>, TracesError> {
+    let mut resp = http::get_with_timeout(url.clone(), timeout)
+        .await?
+        .error_for_status()?;
+    let total = resp
+        .headers()
+        .get(TOTAL_COUNT_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok());
+    let content_type = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    if !(content_type.is_empty() || content_type.contains("json")) {
+        return Err(TracesError::UpstreamUnavailable {
+            url: url.clone(),
+            reason: format!("unexpected content type {content_type:?}"),
+        });
+    }
+
+    let snapshot_error = |source| TracesError::Snapshot {
+        url: url.clone(),
+        source,
+    };
+    let mut recording = raw::Recording::start(url).map_err(snapshot_error)?;
+    let mut hasher = Sha256::new();
+    let mut elements = ArrayElements::default();
+    let mut items = vec![];
+    while let Some(chunk) = resp.chunk().await? {
+        hasher.update(&chunk);
+        if let Some(recording) = &mut recording {
+            recording.write(&chunk).map_err(snapshot_error)?;
+        }
+        elements
+            .push(&chunk, |element| {
+                items.push(serde_json::from_slice(element).map_err(ListingError::Element)?);
+                Ok::<_, ListingError>(())
+            })
+            .map_err(|e| e.into_traces_error(url))?;
+    }
+    elements
+        .finish()
+        .map_err(|e| ListingError::from(e).into_traces_error(url))?;
+
+    let digest = hasher.finalize().to_vec();
+    if let Some(recording) = recording {
+        recording.finish(&digest).map_err(snapshot_error)?;
+    }
+    Ok(ListingPage {
+        items,
+        total,
+        digest,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn unavailable() -> TracesError {
+        TracesError::UpstreamUnavailable {
+            url: Url::parse("https://traces.example/").unwrap(),
+            reason: "maintenance".to_owned(),
+        }
+    }
+
+    fn limiter() -> Mutex<RateLimiter> {
+        Mutex::new(RateLimiter::fixed(Duration::from_secs(1)))
+    }
+
+    #[test]
+    fn adapts_to_silently_capped_pages() {
+        assert_eq!(effective_page_size(500, 100, Some(1234)), 100);
+        assert_eq!(effective_page_size(500, 500, Some(1234)), 500);
+        // The last page of a listing, or one of unknown length, is just short.
+        assert_eq!(effective_page_size(500, 100, Some(100)), 500);
+        assert_eq!(effective_page_size(500, 100, None), 500);
+        assert_eq!(effective_page_size(500, 0, Some(1234)), 500);
+    }
+
+    #[test]
+    fn adapts_to_capped_pages_of_advertised_listings() {
+        // Replayed pages carry no total count header, so the size advertised by the category
+        // listing is all there is to tell a capped page from the last one.
+        let advertised = 2500;
+        let page_size = effective_page_size(1000, 100, Some(advertised));
+        assert_eq!(page_size, 100);
+        let offsets: Vec<i32> = (1..advertised.div_ceil(page_size as usize))
+            .map(|page| page as i32 * page_size)
+            .collect();
+        assert_eq!(offsets.first(), Some(&100));
+        assert_eq!(offsets.last(), Some(&2400));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backs_off_exponentially_until_success() {
+        let attempts = Cell::new(0);
+        let started = tokio::time::Instant::now();
+        let result = with_retries(&TokioClock, &limiter(), || {
+            attempts.set(attempts.get() + 1);
+            let result = if attempts.get() < 3 {
+                Err(unavailable())
+            } else {
+                Ok(attempts.get())
+            };
+            async { result }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 3);
+        // 5s after the first failure, 10s after the second.
+        assert_eq!(started.elapsed(), Duration::from_secs(15));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_after_max_attempts() {
+        let attempts = Cell::new(0);
+        let started = tokio::time::Instant::now();
+        let result: Result<(), _> = with_retries(&TokioClock, &limiter(), || {
+            attempts.set(attempts.get() + 1);
+            async { Err(unavailable()) }
+        })
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(TracesError::UpstreamUnavailable { .. })
+        ));
+        assert_eq!(attempts.get(), MAX_ATTEMPTS);
+        assert_eq!(started.elapsed(), Duration::from_secs(5 + 10 + 20 + 40));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn does_not_retry_permanent_errors() {
+        let attempts = Cell::new(0);
+        let result: Result<(), _> = with_retries(&TokioClock, &limiter(), || {
+            attempts.set(attempts.get() + 1);
+            async {
+                Err(TracesError::Snapshot {
+                    url: Url::parse("https://traces.example/").unwrap(),
+                    source: std::io::ErrorKind::NotFound.into(),
+                })
+            }
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn paces_consecutive_requests() {
+        let limiter = limiter();
+        let started = tokio::time::Instant::now();
+        for _ in 0..3 {
+            with_retries(&TokioClock, &limiter, || async { Ok(()) })
+                .await
+                .unwrap();
+        }
+        // The first request goes out immediately, the others one second apart.
+        assert_eq!(started.elapsed(), Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,72 @@
+use crate::fetch_establishment_range;
+use crate::ids::{CountryCode, SectionCode};
+use crate::traces::effective_page_size;
+use futures::{stream, Stream, TryStreamExt};
+use serde::de::DeserializeOwned;
+
+/// Establishments requested per page.
+const PAGE_SIZE: i32 = 1000;
+
+/// Lazily paginated access to the TRACES establishment listings, for code that processes
+/// establishments as they arrive rather than holding a whole country in memory.
+///
+/// Requests go through the same base URL, rate limit, retries and raw snapshots as a crawl.
+#[derive(Debug, Clone)]
+pub struct TracesClient {
+    page_size: i32,
+}
+
+impl Default for TracesClient {
+    fn default() -> TracesClient {
+        TracesClient {
+            page_size: PAGE_SIZE,
+        }
+    }
+}
+
+impl TracesClient {
+    /// The establishments of `section` in `country`, as [`crate::Establishment`]s or as raw
+    /// `serde_json::Value`s. The next page is only requested once the establishments of the
+    /// previous one have been consumed. The stream ends once the total count TRACES sent is
+    /// reached or, without one, at the first empty page, so a silently capped page size does not
+    /// cut the listing short.
+    pub fn establishments<T: DeserializeOwned>(
+        &self,
+        country: CountryCode,
+        section: SectionCode,
+    ) -> impl Stream<Item = Result<T, Box<dyn std::error::Error>>> {
+        stream::try_unfold(Some((0, self.page_size)), move |next| {
+            next_page(country.clone(), section.clone(), next)
+        })
+        .map_ok(|page| stream::iter(page.into_iter().map(Ok)))
+        .try_flatten()
+    }
+}
+
+/// The establishments of the page at `next`, an offset and page size, and the page after them,
+/// or `None` once the listing has ended.
+async fn next_page<T: DeserializeOwned>(
+    country: CountryCode,
+    section: SectionCode,
+    next: Option<(i32, i32)>,
+) -> Result<Option<(Vec<T>, Option<(i32, i32)>)>, Box<dyn std::error::Error>> {
+    let Some((offset, page_size)) = next else {
+        return Ok(None);
+    };
+    let mut establishments = vec![];
+    let mut total = None;
+    for mut page in fetch_establishment_range(&country, &section, offset, page_size).await? {
+        total = total.or(page.total);
+        establishments.append(&mut page.items);
+    }
+    let served = establishments.len();
+    if served == 0 {
+        return Ok(None);
+    }
+    let remaining = total.map(|t: usize| t.saturating_sub(offset as usize));
+    let page_size = effective_page_size(page_size, served, remaining);
+    let next = remaining
+        .is_none_or(|r| served < r)
+        .then_some((offset + served as i32, page_size));
+    Ok(Some((establishments, next)))
+}
@@ -0,0 +1,174 @@
+use std::fmt;
+
+/// Splits a JSON array arriving in chunks into the raw text of its elements, so each can be
+/// deserialized as soon as it is complete and only the element being received is held.
+#[derive(Debug, Default)]
+pub struct ArrayElements {
+    /// Bytes received but not yet handed out, from the start of the current element if any.
+    pending: Vec<u8>,
+    /// Offset in `pending` up to which the bytes were scanned.
+    scanned: usize,
+    /// Offset in `pending` where the current element starts.
+    element_start: Option<usize>,
+    depth: u32,
+    in_string: bool,
+    escaped: bool,
+    started: bool,
+    ended: bool,
+}
+
+/// Why the text is not a JSON array.
+#[derive(Debug)]
+pub enum ArrayError {
+    /// The text starts with `found` instead of `[`.
+    NotAnArray { found: u8 },
+    /// Text follows the end of the array.
+    TrailingText,
+    /// The text ended before the array did.
+    Truncated,
+}
+
+impl fmt::Display for ArrayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArrayError::NotAnArray { found } => {
+                write!(f, "expected a JSON array, found {:?}", char::from(*found))
+            }
+            ArrayError::TrailingText => write!(f, "text after the end of the JSON array"),
+            ArrayError::Truncated => write!(f, "the JSON array is incomplete"),
+        }
+    }
+}
+
+impl ArrayElements {
+    /// Scans `chunk`, calling `element` with the text of every element it completes. Errors of
+    /// `element` stop the scan and are returned as they are.
+    pub fn push<E: From<ArrayError>>(
+        &mut self,
+        chunk: &[u8],
+        mut element: impl FnMut(&[u8]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        self.pending.extend_from_slice(chunk);
+        while self.scanned < self.pending.len() {
+            let i = self.scanned;
+            let byte = self.pending[i];
+            self.scanned += 1;
+
+            if self.in_string {
+                match byte {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            if byte.is_ascii_whitespace() {
+                continue;
+            }
+            if self.ended {
+                return Err(ArrayError::TrailingText.into());
+            }
+            if !self.started {
+                if byte != b'[' {
+                    return Err(ArrayError::NotAnArray { found: byte }.into());
+                }
+                self.started = true;
+                self.depth = 1;
+                continue;
+            }
+
+            if self.depth == 1 && self.element_start.is_none() && !matches!(byte, b',' | b']') {
+                self.element_start = Some(i);
+            }
+            match byte {
+                b'"' => self.in_string = true,
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' => {
+                    self.depth -= 1;
+                    if self.depth == 0 {
+                        self.ended = true;
+                        // A scalar last element ends with the array.
+                        if let Some(start) = self.element_start.take() {
+                            element(&self.pending[start..i])?;
+                        }
+                    } else if self.depth == 1 {
+                        if let Some(start) = self.element_start.take() {
+                            element(&self.pending[start..=i])?;
+                        }
+                    }
+                }
+                b',' if self.depth == 1 => {
+                    if let Some(start) = self.element_start.take() {
+                        element(&self.pending[start..i])?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Keeps only the element still being received.
+        let keep_from = self.element_start.unwrap_or(self.scanned);
+        self.pending.drain(..keep_from);
+        self.scanned -= keep_from;
+        self.element_start = self.element_start.map(|_| 0);
+        Ok(())
+    }
+
+    /// Checks that the whole array was received.
+    pub fn finish(&self) -> Result<(), ArrayError> {
+        if self.ended {
+            Ok(())
+        } else {
+            Err(ArrayError::Truncated)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(chunks: &[&str]) -> Result<Vec<String>, ArrayError> {
+        let mut elements = ArrayElements::default();
+        let mut found = vec![];
+        for chunk in chunks {
+            elements.push(chunk.as_bytes(), |e| {
+                found.push(String::from_utf8(e.to_vec()).unwrap());
+                Ok::<_, ArrayError>(())
+            })?;
+        }
+        elements.finish()?;
+        Ok(found)
+    }
+
+    #[test]
+    fn splits_elements_across_chunks() {
+        let elements = split(&[
+            " [{\"a\": \"x}\\\"\", \"b\": [1, {}]},",
+            " {\"a\"",
+            ": 2}, 3, \"s,]\"]\n",
+        ])
+        .unwrap();
+        assert_eq!(
+            elements,
+            [
+                "{\"a\": \"x}\\\"\", \"b\": [1, {}]}",
+                "{\"a\": 2}",
+                "3",
+                "\"s,]\""
+            ]
+        );
+        assert!(split(&["[]"]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_whole_array() {
+        assert!(matches!(
+            split(&["<html>"]),
+            Err(ArrayError::NotAnArray { found: b'<' })
+        ));
+        assert!(matches!(split(&["[{}, {"]), Err(ArrayError::Truncated)));
+        assert!(matches!(split(&["[] x"]), Err(ArrayError::TrailingText)));
+    }
+}
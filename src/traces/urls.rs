@@ -0,0 +1,226 @@
+use crate::ids::{CountryCode, OperatorId, SectionCode};
+use std::str::FromStr;
+use url::{ParseError, Url};
+
+/// Category listings TRACES publishes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Listing {
+    /// Establishments approved in the EU and EFTA.
+    Eu,
+    /// Approved third-country exporters.
+    ThirdCountry,
+}
+
+impl Listing {
+    fn segments(self) -> &'static [&'static str] {
+        match self {
+            Listing::Eu => &[],
+            Listing::ThirdCountry => &["third-country"],
+        }
+    }
+}
+
+/// Window of a paged listing, as the `offset` and `max` query parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PageRange {
+    pub offset: i32,
+    pub max: i32,
+}
+
+/// Records `START..END` of a listing, as given to `--offset-range`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OffsetRange {
+    pub start: i32,
+    pub end: i32,
+}
+
+impl OffsetRange {
+    /// The pages of `page_size` records, as a crawl requests them, that hold part of the range.
+    pub fn pages(self, page_size: i32) -> impl Iterator<Item = PageRange> {
+        let first = self.start / page_size;
+        let last = (self.end + page_size - 1) / page_size;
+        (first..last).map(move |page| PageRange {
+            offset: page * page_size,
+            max: page_size,
+        })
+    }
+}
+
+impl FromStr for OffsetRange {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected START..END with START < END, got {value:?}");
+        let (start, end) = value.trim().split_once("..").ok_or_else(invalid)?;
+        let start: i32 = start.parse().map_err(|_| invalid())?;
+        let end: i32 = end.parse().map_err(|_| invalid())?;
+        if start < 0 || start >= end {
+            return Err(invalid());
+        }
+        Ok(OffsetRange { start, end })
+    }
+}
+
+/// Builds the URLs of the TRACES publication API below a base URL.
+///
+/// Path parameters are passed unencoded and escaped exactly once, so section codes containing
+/// slashes or spaces stay a single path segment.
+#[derive(Clone, Debug)]
+pub struct Endpoints {
+    base: Url,
+}
+
+impl Endpoints {
+    pub fn new(base: &str) -> Result<Endpoints, ParseError> {
+        let base = Url::parse(base.trim_end_matches('/'))?;
+        if base.cannot_be_a_base() {
+            return Err(ParseError::RelativeUrlWithCannotBeABaseBase);
+        }
+        Ok(Endpoints { base })
+    }
+
+    fn endpoint(&self, segments: &[&str]) -> Url {
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .expect("checked in Endpoints::new")
+            .pop_if_empty()
+            .extend(segments);
+        url
+    }
+
+    fn paged(mut url: Url, sort: &str, page: PageRange) -> Url {
+        url.query_pairs_mut()
+            .append_pair("sort", sort)
+            .append_pair("max", &page.max.to_string())
+            .append_pair("offset", &page.offset.to_string());
+        url
+    }
+
+    /// One page of the categories (country × classification section) of a listing.
+    pub fn categories(&self, listing: Listing, page: PageRange) -> Url {
+        Self::paged(
+            self.endpoint(listing.segments()),
+            "country.translation",
+            page,
+        )
+    }
+
+    /// One page of the establishments listed for a country and section, sorted by operator name.
+    pub fn establishments(
+        &self,
+        country: &CountryCode,
+        section: &SectionCode,
+        page: PageRange,
+    ) -> Url {
+        Self::paged(
+            self.endpoint(&["establishments", country.as_str(), section.as_str()]),
+            "operatorName",
+            page,
+        )
+    }
+
+    /// Detail record of a single establishment.
+    pub fn details(&self, operator_id: OperatorId) -> Url {
+        self.endpoint(&["details", &operator_id.to_string()])
+    }
+
+    /// Classification section dictionary, with names in `lang`.
+    pub fn activities(&self, lang: &str) -> Url {
+        let mut url = self.endpoint(&["activities"]);
+        url.query_pairs_mut().append_pair("lang", lang);
+        url
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "https://traces.example/publication/establishment";
+
+    fn endpoints() -> Endpoints {
+        Endpoints::new(BASE).unwrap()
+    }
+
+    fn page(offset: i32) -> PageRange {
+        PageRange { offset, max: 1000 }
+    }
+
+    #[test]
+    fn builds_category_listings() {
+        assert_eq!(
+            endpoints().categories(Listing::Eu, page(0)).as_str(),
+            "https://traces.example/publication/establishment\
+             ?sort=country.translation&max=1000&offset=0"
+        );
+        assert_eq!(
+            endpoints()
+                .categories(Listing::ThirdCountry, page(2000))
+                .as_str(),
+            "https://traces.example/publication/establishment/third-country\
+             ?sort=country.translation&max=1000&offset=2000"
+        );
+    }
+
+    #[test]
+    fn builds_establishment_pages() {
+        let url =
+            endpoints().establishments(&"FR".parse().unwrap(), &"I".parse().unwrap(), page(0));
+        assert_eq!(
+            url.as_str(),
+            "https://traces.example/publication/establishment/establishments/FR/I\
+             ?sort=operatorName&max=1000&offset=0"
+        );
+    }
+
+    #[test]
+    fn escapes_path_parameters_once() {
+        // Codes read from TRACES are not validated, so they may contain anything.
+        let section: SectionCode = serde_json::from_str(r#""IX/b 2%""#).unwrap();
+        let url = endpoints().establishments(&"DE".parse().unwrap(), &section, page(0));
+        assert_eq!(
+            url.path(),
+            "/publication/establishment/establishments/DE/IX%2Fb%202%25"
+        );
+        assert_eq!(url.path_segments().unwrap().count(), 5);
+    }
+
+    #[test]
+    fn builds_details_and_activities() {
+        assert_eq!(
+            endpoints().details("1234".parse().unwrap()).as_str(),
+            "https://traces.example/publication/establishment/details/1234"
+        );
+        assert_eq!(
+            endpoints().activities("pt-BR").as_str(),
+            "https://traces.example/publication/establishment/activities?lang=pt-BR"
+        );
+    }
+
+    #[test]
+    fn ignores_trailing_slash_in_base() {
+        let endpoints = Endpoints::new(&format!("{BASE}/")).unwrap();
+        assert_eq!(
+            endpoints.details("1".parse().unwrap()).as_str(),
+            "https://traces.example/publication/establishment/details/1"
+        );
+    }
+
+    #[test]
+    fn rejects_bases_without_path() {
+        assert!(Endpoints::new("mailto:traces@example.org").is_err());
+        assert!(Endpoints::new("not a url").is_err());
+    }
+
+    #[test]
+    fn covers_offset_ranges_with_whole_pages() {
+        let range: OffsetRange = "1500..3000".parse().unwrap();
+        assert_eq!(
+            range.pages(1000).collect::<Vec<_>>(),
+            [page(1000), page(2000)]
+        );
+        let range: OffsetRange = "1000..2000".parse().unwrap();
+        assert_eq!(range.pages(1000).collect::<Vec<_>>(), [page(1000)]);
+        assert!("2000..1000".parse::<OffsetRange>().is_err());
+    }
+}
@@ -0,0 +1,405 @@
+use serde::*;
+
+/// Which writing of an address a geocoding query used.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryScript {
+    /// As listed in TRACES.
+    Original,
+    /// Transliterated from Greek or Cyrillic into Latin letters.
+    Latin,
+    /// Transliterated from Latin letters into the country's own script.
+    Native,
+}
+
+impl QueryScript {
+    #[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueryScript::Original => "original",
+            QueryScript::Latin => "latin",
+            QueryScript::Native => "native",
+        }
+    }
+}
+
+/// Non-Latin alphabets addresses are written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Script {
+    Greek,
+    Cyrillic,
+}
+
+impl Script {
+    /// The alphabet of a language, given as the first entry of the country table's language list.
+    fn of_language(lang: &str) -> Option<Script> {
+        match lang {
+            "el" => Some(Script::Greek),
+            "bg" | "sr" | "mk" | "uk" | "ru" | "be" => Some(Script::Cyrillic),
+            _ => None,
+        }
+    }
+
+    fn contains(self, c: char) -> bool {
+        match self {
+            Script::Greek => matches!(c, '\u{0370}'..='\u{03FF}' | '\u{1F00}'..='\u{1FFF}'),
+            Script::Cyrillic => matches!(c, '\u{0400}'..='\u{04FF}'),
+        }
+    }
+}
+
+/// Greek letter pairs with their own romanization (ELOT 743), checked before single letters.
+const GREEK_PAIRS: &[(&str, &str)] = &[
+    ("ου", "ou"),
+    ("ού", "ou"),
+    ("αυ", "av"),
+    ("αύ", "av"),
+    ("ευ", "ev"),
+    ("εύ", "ev"),
+    ("γγ", "ng"),
+    ("γκ", "gk"),
+    ("μπ", "mp"),
+    ("ντ", "nt"),
+];
+
+const GREEK_LETTERS: &[(char, &str)] = &[
+    ('α', "a"),
+    ('ά', "a"),
+    ('β', "v"),
+    ('γ', "g"),
+    ('δ', "d"),
+    ('ε', "e"),
+    ('έ', "e"),
+    ('ζ', "z"),
+    ('η', "i"),
+    ('ή', "i"),
+    ('θ', "th"),
+    ('ι', "i"),
+    ('ί', "i"),
+    ('ϊ', "i"),
+    ('ΐ', "i"),
+    ('κ', "k"),
+    ('λ', "l"),
+    ('μ', "m"),
+    ('ν', "n"),
+    ('ξ', "x"),
+    ('ο', "o"),
+    ('ό', "o"),
+    ('π', "p"),
+    ('ρ', "r"),
+    ('σ', "s"),
+    ('ς', "s"),
+    ('τ', "t"),
+    ('υ', "y"),
+    ('ύ', "y"),
+    ('ϋ', "y"),
+    ('ΰ', "y"),
+    ('φ', "f"),
+    ('χ', "ch"),
+    ('ψ', "ps"),
+    ('ω', "o"),
+    ('ώ', "o"),
+];
+
+/// Cyrillic letters in the romanization common to the Bulgarian, Serbian, Macedonian, Ukrainian
+/// and Russian national systems; [`cyrillic_overrides`] adjusts it per language.
+const CYRILLIC_LETTERS: &[(char, &str)] = &[
+    ('а', "a"),
+    ('б', "b"),
+    ('в', "v"),
+    ('г', "g"),
+    ('ґ', "g"),
+    ('д', "d"),
+    ('ђ', "dj"),
+    ('ѓ', "gj"),
+    ('е', "e"),
+    ('є', "ye"),
+    ('ё', "yo"),
+    ('ж', "zh"),
+    ('з', "z"),
+    ('ѕ', "dz"),
+    ('и', "i"),
+    ('і', "i"),
+    ('ї', "yi"),
+    ('й', "y"),
+    ('ј', "j"),
+    ('к', "k"),
+    ('л', "l"),
+    ('љ', "lj"),
+    ('м', "m"),
+    ('н', "n"),
+    ('њ', "nj"),
+    ('о', "o"),
+    ('п', "p"),
+    ('р', "r"),
+    ('с', "s"),
+    ('т', "t"),
+    ('ћ', "c"),
+    ('ќ', "kj"),
+    ('у', "u"),
+    ('ў', "u"),
+    ('ф', "f"),
+    ('х', "h"),
+    ('ц', "ts"),
+    ('ч', "ch"),
+    ('џ', "dzh"),
+    ('ш', "sh"),
+    ('щ', "shch"),
+    ('ъ', ""),
+    ('ы', "y"),
+    ('ь', ""),
+    ('э', "e"),
+    ('ю', "yu"),
+    ('я', "ya"),
+];
+
+/// Letters a language romanizes differently from [`CYRILLIC_LETTERS`].
+fn cyrillic_overrides(lang: &str) -> &'static [(char, &'static str)] {
+    match lang {
+        "bg" => &[('щ', "sht"), ('ъ', "a"), ('ь', "y")],
+        "sr" | "mk" => &[('ц', "c"), ('ч', "č"), ('ш', "š"), ('ж', "ž"), ('џ', "dž")],
+        "uk" => &[('г', "h"), ('и', "y"), ('х', "kh")],
+        "ru" | "be" => &[('х', "kh")],
+        _ => &[],
+    }
+}
+
+/// Romanizes the Greek or Cyrillic letters of `text` by the conventions of `lang`, keeping
+/// everything else. Returns `None` if `text` has no letters of the language's script.
+fn to_latin(text: &str, lang: &str) -> Option<String> {
+    let script = Script::of_language(lang)?;
+    if !text.chars().any(|c| script.contains(c)) {
+        return None;
+    }
+
+    let chars: Vec<char> = text.chars().collect();
+    let mut latin = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let upper = c.is_uppercase();
+        let lower = c.to_lowercase().next().unwrap_or(c);
+        if script == Script::Greek {
+            if let Some(next) = chars.get(i + 1) {
+                let pair: String = [lower, next.to_lowercase().next().unwrap_or(*next)]
+                    .iter()
+                    .collect();
+                if let Some((_, roman)) = GREEK_PAIRS.iter().find(|(p, _)| *p == pair) {
+                    push_cased(&mut latin, roman, upper);
+                    i += 2;
+                    continue;
+                }
+            }
+        }
+        let roman = match script {
+            Script::Greek => lookup(GREEK_LETTERS, lower),
+            Script::Cyrillic => {
+                lookup(cyrillic_overrides(lang), lower).or(lookup(CYRILLIC_LETTERS, lower))
+            }
+        };
+        match roman {
+            Some(roman) => push_cased(&mut latin, roman, upper),
+            None => latin.push(c),
+        }
+        i += 1;
+    }
+    Some(latin)
+}
+
+/// Latin spellings read back into Greek, longest first.
+const LATIN_TO_GREEK: &[(&str, &str)] = &[
+    ("th", "θ"),
+    ("ch", "χ"),
+    ("ps", "ψ"),
+    ("ou", "ου"),
+    ("mp", "μπ"),
+    ("nt", "ντ"),
+    ("a", "α"),
+    ("b", "μπ"),
+    ("c", "κ"),
+    ("d", "δ"),
+    ("e", "ε"),
+    ("f", "φ"),
+    ("g", "γ"),
+    ("h", "χ"),
+    ("i", "ι"),
+    ("j", "τζ"),
+    ("k", "κ"),
+    ("l", "λ"),
+    ("m", "μ"),
+    ("n", "ν"),
+    ("o", "ο"),
+    ("p", "π"),
+    ("q", "κ"),
+    ("r", "ρ"),
+    ("s", "σ"),
+    ("t", "τ"),
+    ("u", "ου"),
+    ("v", "β"),
+    ("w", "β"),
+    ("x", "ξ"),
+    ("y", "υ"),
+    ("z", "ζ"),
+];
+
+/// Latin spellings read back into Cyrillic, longest first.
+const LATIN_TO_CYRILLIC: &[(&str, &str)] = &[
+    ("shch", "щ"),
+    ("sht", "щ"),
+    ("zh", "ж"),
+    ("ts", "ц"),
+    ("ch", "ч"),
+    ("sh", "ш"),
+    ("yu", "ю"),
+    ("ya", "я"),
+    ("lj", "љ"),
+    ("nj", "њ"),
+    ("dž", "џ"),
+    ("č", "ч"),
+    ("ć", "ћ"),
+    ("đ", "ђ"),
+    ("š", "ш"),
+    ("ž", "ж"),
+    ("a", "а"),
+    ("b", "б"),
+    ("c", "ц"),
+    ("d", "д"),
+    ("e", "е"),
+    ("f", "ф"),
+    ("g", "г"),
+    ("h", "х"),
+    ("i", "и"),
+    ("j", "й"),
+    ("k", "к"),
+    ("l", "л"),
+    ("m", "м"),
+    ("n", "н"),
+    ("o", "о"),
+    ("p", "п"),
+    ("q", "к"),
+    ("r", "р"),
+    ("s", "с"),
+    ("t", "т"),
+    ("u", "у"),
+    ("v", "в"),
+    ("w", "в"),
+    ("x", "кс"),
+    ("y", "й"),
+    ("z", "з"),
+];
+
+/// Reads the Latin letters of `text` back into the script of `lang`, as a best guess at how the
+/// address is written locally. Returns `None` if the language is written in Latin letters or
+/// `text` has none.
+fn to_native(text: &str, lang: &str) -> Option<String> {
+    let script = Script::of_language(lang)?;
+    let table = match script {
+        Script::Greek => LATIN_TO_GREEK,
+        Script::Cyrillic => LATIN_TO_CYRILLIC,
+    };
+    if !text.chars().any(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let lower = text.to_lowercase();
+    let original: Vec<char> = text.chars().collect();
+    let mut native = String::with_capacity(text.len() * 2);
+    let mut rest = lower.as_str();
+    let mut position = 0;
+    while !rest.is_empty() {
+        let upper = original.get(position).is_some_and(|c| c.is_uppercase());
+        match table.iter().find(|(latin, _)| rest.starts_with(latin)) {
+            Some((latin, letters)) => {
+                push_cased(&mut native, letters, upper);
+                rest = &rest[latin.len()..];
+                position += latin.chars().count();
+            }
+            None => {
+                let c = rest.chars().next().expect("rest is not empty");
+                native.push(original.get(position).copied().unwrap_or(c));
+                rest = &rest[c.len_utf8()..];
+                position += 1;
+            }
+        }
+    }
+    if script == Script::Greek {
+        native = final_sigma(&native);
+    }
+    Some(native)
+}
+
+/// Replaces `σ` at the end of a word with `ς`.
+fn final_sigma(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let at_end = chars.get(i + 1).is_none_or(|n| !n.is_alphabetic());
+            if *c == 'σ' && at_end {
+                'ς'
+            } else {
+                *c
+            }
+        })
+        .collect()
+}
+
+fn lookup(table: &[(char, &'static str)], c: char) -> Option<&'static str> {
+    table.iter().find(|(l, _)| *l == c).map(|(_, roman)| *roman)
+}
+
+/// Appends `letters`, capitalizing the first if the letter they stand for was upper case.
+fn push_cased(out: &mut String, letters: &str, upper: bool) {
+    let mut chars = letters.chars();
+    match chars.next() {
+        Some(first) if upper => {
+            out.extend(first.to_uppercase());
+            out.push_str(chars.as_str());
+        }
+        _ => out.push_str(letters),
+    }
+}
+
+/// The other writing of `text` worth trying for an address in `lang`: Latin letters for Greek or
+/// Cyrillic text, and the reverse for Latin text, since geocoder coverage differs by script.
+pub fn variant(text: &str, lang: &str) -> Option<(QueryScript, String)> {
+    if let Some(latin) = to_latin(text, lang) {
+        return Some((QueryScript::Latin, latin));
+    }
+    to_native(text, lang).map(|native| (QueryScript::Native, native))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn romanizes_greek_and_cyrillic() {
+        assert_eq!(
+            variant("Οδός Αθηνάς 12", "el"),
+            Some((QueryScript::Latin, "Odos Athinas 12".to_owned()))
+        );
+        assert_eq!(
+            variant("ул. Шипка 5, Пловдив", "bg"),
+            Some((QueryScript::Latin, "ul. Shipka 5, Plovdiv".to_owned()))
+        );
+        assert_eq!(
+            variant("Щастлива", "bg"),
+            Some((QueryScript::Latin, "Shtastliva".to_owned()))
+        );
+    }
+
+    #[test]
+    fn reads_latin_back_into_the_native_script() {
+        assert_eq!(
+            variant("Plovdiv", "bg"),
+            Some((QueryScript::Native, "Пловдив".to_owned()))
+        );
+        assert_eq!(
+            variant("Athinas", "el"),
+            Some((QueryScript::Native, "Αθινας".to_owned()))
+        );
+        assert_eq!(variant("Rue de la Paix", "fr"), None);
+    }
+}
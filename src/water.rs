@@ -0,0 +1,26 @@
+use crate::coordinates::Coordinates;
+use country_boundaries::{CountryBoundaries, LatLon, BOUNDARIES_ODBL_360X180};
+
+/// Coarse land mask from the country boundaries bundled with the `country-boundaries` crate
+/// (OpenStreetMap, ODbL).
+///
+/// Points outside every country are at sea, which usually means a geocoder fell back to a country
+/// or postcode centroid for a coastal address.
+pub struct LandMask {
+    boundaries: CountryBoundaries,
+}
+
+impl LandMask {
+    pub fn load() -> Result<LandMask, Box<dyn std::error::Error>> {
+        Ok(LandMask {
+            boundaries: CountryBoundaries::from_reader(BOUNDARIES_ODBL_360X180)?,
+        })
+    }
+
+    pub fn is_water(&self, position: Coordinates) -> bool {
+        match LatLon::new(position.lat(), position.lng()) {
+            Ok(position) => self.boundaries.ids(position).is_empty(),
+            Err(_) => false,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::cli::Args;
+use crate::history::RunCounts;
+use crate::http;
+use serde::*;
+use std::time::{Duration, SystemTime};
+
+/// How long a webhook may take to answer, so a hung endpoint cannot hold up the end of the run.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Debug)]
+struct RunSummary<'a> {
+    /// One-line description, shown by Slack and similar chat webhooks.
+    text: String,
+    outcome: &'static str,
+    started_at: String,
+    duration_secs: f64,
+    failure: Option<String>,
+    counts: Option<&'a RunCounts>,
+}
+
+/// Posts a summary of the crawl started at `started` to `--on-success-webhook` or
+/// `--on-failure-webhook`. Delivery failures are reported but do not fail the run.
+pub async fn notify(
+    args: &Args,
+    started: SystemTime,
+    result: &Result<RunCounts, Box<dyn std::error::Error>>,
+) {
+    let name = env!("CARGO_PKG_NAME");
+    let (url, outcome, text, failure, counts) = match result {
+        Ok(counts) => (
+            &args.on_success_webhook,
+            "success",
+            format!(
+                "{name} crawl succeeded: {} packager codes in {} countries, {} skipped, {} errors",
+                counts.packager_codes, counts.countries, counts.skipped, counts.errors
+            ),
+            None,
+            Some(counts),
+        ),
+        Err(e) => (
+            &args.on_failure_webhook,
+            "failure",
+            format!("{name} crawl failed: {e}"),
+            Some(e.to_string()),
+            None,
+        ),
+    };
+    let Some(url) = url else {
+        return;
+    };
+
+    let summary = RunSummary {
+        text,
+        outcome,
+        started_at: humantime::format_rfc3339_seconds(started).to_string(),
+        duration_secs: started.elapsed().unwrap_or_default().as_secs_f64(),
+        failure,
+        counts,
+    };
+    let sent = http::post_json(url, &summary, WEBHOOK_TIMEOUT)
+        .await
+        .and_then(|resp| resp.error_for_status());
+    if let Err(e) = sent {
+        // Chat webhooks carry their secret in the path, so only the origin is shown.
+        eprintln!(
+            "Could not notify {}: {}",
+            url.origin().ascii_serialization(),
+            e.without_url()
+        );
+    }
+}